@@ -0,0 +1,188 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use pengwin::annealing;
+use pengwin::grid::Grid;
+use pengwin::point::{Metric, Point};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+const SIZES: [&str; 3] = ["small", "medium", "large"];
+
+/// Returns the fixture grid for the given size with no towers placed, along
+/// with the towers of its fixture solution in sorted order. The fixtures are
+/// copies of input and output 001, so the benches don't change as the outputs
+/// improve.
+fn fixture(size: &str) -> (Grid, Vec<Point>) {
+	let grid = Grid::from_file(&format!("./benches/fixtures/{}.in", size)).unwrap();
+	let mut towers: Vec<Point> = Grid::towers_from_file(&format!("./benches/fixtures/{}.out", size))
+		.unwrap()
+		.into_iter()
+		.collect();
+	towers.sort();
+	(grid, towers)
+}
+
+/// Returns the fixture grid for the given size with its solution placed.
+fn solved_fixture(size: &str) -> Grid {
+	let (mut grid, towers) = fixture(size);
+	for t in towers.iter() {
+		grid.add_tower(t.x, t.y);
+	}
+	grid
+}
+
+/// Adds and then removes all the towers of the fixture solution for each size,
+/// which exercises the tower and city coverage maps.
+fn add_tower(c: &mut Criterion) {
+	for size in SIZES {
+		let (grid, towers) = fixture(size);
+
+		c.bench_function(&format!("add_tower/{}", size), |b| {
+			let mut grid = grid.clone();
+			b.iter(|| {
+				for t in towers.iter() {
+					grid.add_tower(t.x, t.y);
+				}
+				for t in towers.iter() {
+					grid.remove_tower(t.x, t.y);
+				}
+				black_box(&grid);
+			})
+		});
+	}
+}
+
+/// Removes each tower of the solved fixture and puts it back, i.e. the move a
+/// local search makes most often.
+fn remove_tower(c: &mut Criterion) {
+	for size in SIZES {
+		let grid = solved_fixture(size);
+		let mut towers: Vec<Point> = grid.towers().collect();
+		towers.sort();
+
+		c.bench_function(&format!("remove_tower/{}", size), |b| {
+			let mut grid = grid.clone();
+			b.iter(|| {
+				for t in towers.iter() {
+					grid.remove_tower(t.x, t.y);
+					grid.add_tower(t.x, t.y);
+				}
+				black_box(&grid);
+			})
+		});
+	}
+}
+
+/// Moves each tower of the solved fixture one cell over (where that cell is
+/// free) and back, against working out the penalty of the same moves with
+/// penalty_if_moved, which is how annealing evaluates its moves.
+fn move_tower(c: &mut Criterion) {
+	for size in SIZES {
+		let grid = solved_fixture(size);
+		let mut moves: Vec<(Point, Point)> = grid
+			.towers()
+			.map(|t| (t, Point::new((t.x + 1) % grid.dimension() as i32, t.y)))
+			.filter(|(_, q)| !grid.is_tower_present(*q))
+			.collect();
+		moves.sort();
+
+		c.bench_function(&format!("move_tower/{}", size), |b| {
+			let mut grid = grid.clone();
+			b.iter(|| {
+				for &(p, q) in moves.iter() {
+					grid.move_tower(p, q);
+					black_box(grid.penalty());
+					grid.move_tower(q, p);
+				}
+			})
+		});
+		c.bench_function(&format!("penalty_if_moved/{}", size), |b| {
+			b.iter(|| {
+				for &(p, q) in moves.iter() {
+					black_box(grid.penalty_if_moved(p, q));
+				}
+			})
+		});
+	}
+}
+
+/// Takes annealing steps from the solved fixture, each moving a random tower
+/// and pruning the towers that became redundant. This is what an annealing
+/// iteration costs besides the acceptance test.
+fn anneal_step(c: &mut Criterion) {
+	for size in SIZES {
+		let grid = solved_fixture(size);
+		c.bench_function(&format!("anneal_step/{}", size), |b| {
+			let mut rng = StdRng::seed_from_u64(1312);
+			b.iter(|| black_box(annealing::neighbor_remove_towers(&grid, &mut rng)))
+		});
+	}
+}
+
+fn penalty(c: &mut Criterion) {
+	for size in SIZES {
+		let grid = solved_fixture(size);
+		c.bench_function(&format!("penalty/{}", size), |b| b.iter(|| black_box(&grid).penalty()));
+	}
+}
+
+fn is_valid(c: &mut Criterion) {
+	for size in SIZES {
+		let grid = solved_fixture(size);
+		c.bench_function(&format!("is_valid/{}", size), |b| {
+			b.iter(|| black_box(&grid).is_valid())
+		});
+	}
+}
+
+fn clone(c: &mut Criterion) {
+	for size in SIZES {
+		let grid = solved_fixture(size);
+		c.bench_function(&format!("clone/{}", size), |b| b.iter(|| black_box(&grid).clone()));
+	}
+}
+
+/// Looks up the points within the penalty radius of every cell of a large
+/// grid. Warm lookups hit the preprocessed data (or the computed cache for
+/// radii that aren't preprocessed), while cold ones compute every set from
+/// scratch like the first lookup of an uncached radius does.
+fn points_within_radius(c: &mut Criterion) {
+	let (dim, r) = (100, 14);
+	let points: Vec<Point> = (0..dim as i32)
+		.flat_map(|x| (0..dim as i32).map(move |y| Point::new(x, y)))
+		.collect();
+
+	c.bench_function("points_within_radius/cold", |b| {
+		b.iter(|| {
+			for p in points.iter() {
+				black_box(Point::points_within_naive(*p, r, dim, Metric::Euclidean, false));
+			}
+		})
+	});
+
+	for (name, metric) in [("preprocessed", Metric::Euclidean), ("computed", Metric::Chebyshev)] {
+		// Fill the cache before measuring
+		for p in points.iter() {
+			Point::points_within_radius(*p, r, dim, metric, false).unwrap();
+		}
+		c.bench_function(&format!("points_within_radius/warm_{}", name), |b| {
+			b.iter(|| {
+				for p in points.iter() {
+					black_box(Point::points_within_radius(*p, r, dim, metric, false).unwrap());
+				}
+			})
+		});
+	}
+}
+
+criterion_group!(
+	benches,
+	add_tower,
+	remove_tower,
+	move_tower,
+	anneal_step,
+	penalty,
+	is_valid,
+	clone,
+	points_within_radius
+);
+criterion_main!(benches);
@@ -4,16 +4,17 @@ use std::sync::{Arc, Mutex};
 
 use argmin::prelude::*;
 use argmin::solver::simulatedannealing::{SATempFunc, SimulatedAnnealing};
+use log::{debug, info, trace};
 use rand::prelude::*;
 use rand_xoshiro::Xoshiro256PlusPlus;
 
 use crate::grid::Grid;
-use crate::point::Point;
+use crate::point::{Metric, Point};
 use crate::{api, solvers};
 
 const INIT_TEMP: f64 = 150.0;
 const INIT_CULLING: f64 = 0.1;
-const MAX_ITERS: u64 = 10000;
+pub const MAX_ITERS: u64 = 10000;
 
 struct Penalty {
 	p:   f64,
@@ -21,10 +22,10 @@ struct Penalty {
 }
 
 impl Penalty {
-	pub fn new(p: f64) -> Self {
+	pub fn new(p: f64, rng: Xoshiro256PlusPlus) -> Self {
 		Penalty {
 			p,
-			rng: Arc::new(Mutex::new(Xoshiro256PlusPlus::from_entropy())),
+			rng: Arc::new(Mutex::new(rng)),
 		}
 	}
 }
@@ -36,60 +37,73 @@ impl ArgminOp for Penalty {
 	type Output = f64;
 	type Param = Grid;
 
+	// The penalty is kept up to date as towers are moved, so this doesn't
+	// recompute it from scratch
 	fn apply(&self, param: &Grid) -> Result<f64, Error> {
 		Ok(param.penalty())
 	}
 
-	// Return a valid neighbor of the current state
+	// Return a valid neighbor of the current state. argmin hands over the
+	// current state by reference and keeps it, e.g. as the best so far, while
+	// taking the neighbor by value, so every step has to build a new Grid. The
+	// snapshot/restore API would need to edit the current state in place, so
+	// the neighbor functions clone param once and only for the move they make.
 	fn modify(&self, param: &Grid, temp: f64) -> Result<Grid, Error> {
-		// Ok(neighbor_one_tower(param))
-		// Ok(neighbor_temp_towers(param, temp))
-		Ok(neighbor_remove_towers(param))
+		// argmin can't be stopped partway, so once asked to stop the remaining
+		// iterations stay put, which is cheap, and the best so far is kept
+		if solvers::stop_requested() {
+			return Ok(param.clone());
+		}
+		let mut rng = self.rng.lock().unwrap();
+		// Ok(neighbor_one_tower(param, &mut *rng))
+		// Ok(neighbor_temp_towers(param, temp, &mut *rng))
+		Ok(neighbor_remove_towers(param, &mut *rng))
 	}
 }
 
 // Neigboring Functions
 
 /// Returns a neighbor of the given grid by moving one random tower
-/// to a random valid location
-fn neighbor_one_tower(param: &Grid) -> Grid {
-	let mut rng = Xoshiro256PlusPlus::from_entropy();
-
-	let mut grid = param.clone();
-
-	// Returns random value from a hashmap
-	let towers_hashmap = grid.get_towers_ref();
-	let mut towers: Vec<Point> = towers_hashmap.keys().map(|p| *p).collect();
-	towers.shuffle(&mut rng);
-
-	let towers_to_move = 1;
-	let mut valid = false;
+/// to a random valid location. Candidate moves are checked against param
+/// without modifying it, so only the move that is made touches a copy.
+fn neighbor_one_tower(param: &Grid, rng: &mut impl Rng) -> Grid {
+	let mut towers: Vec<Point> = param.towers().collect();
+	towers.sort(); // so the result only depends on the rng
+	towers.shuffle(rng);
+
+	// Get valid points to move the tower
+	let tower = towers[0];
+	let candidate_points = Point::points_within_naive(tower, 5, param.dimension(), Metric::Euclidean, param.wrap());
+	let mut points: Vec<Point> = candidate_points.into_iter().collect();
+	points.sort();
 
 	let mut counter = 0;
-	while !valid {
-		grid = param.clone();
+	loop {
 		counter += 1;
-		println!("Iteration {}", counter);
-		for i in 0..towers_to_move {
-			// Get valid points to move the tower
-			let tower = towers[i];
-			let candidate_points = Point::points_within_naive(tower, 5, grid.dimension());
-			let points: Vec<Point> = candidate_points.iter().map(|p| *p).collect();
-			let point_to_move_to = points.choose(&mut rng).unwrap();
-			if !grid.is_tower_present(*point_to_move_to) && grid.is_on_grid(point_to_move_to.x, point_to_move_to.y) {
-				grid.move_tower(tower, *point_to_move_to);
-			}
+		trace!("Iteration {}", counter);
+		let point_to_move_to = *points.choose(rng).unwrap();
+		// Staying put is always valid
+		if param.is_tower_present(point_to_move_to) || !param.is_on_grid(point_to_move_to.x, point_to_move_to.y) {
+			return param.clone();
+		}
+		if param.is_move_valid(tower, point_to_move_to) {
+			trace!(
+				"Moving {} to {}: {} -> {}",
+				tower,
+				point_to_move_to,
+				param.penalty(),
+				param.penalty_if_moved(tower, point_to_move_to)
+			);
+			let mut grid = param.clone();
+			grid.move_tower(tower, point_to_move_to);
+			return grid;
 		}
-		valid = grid.is_valid();
 	}
-	grid
 }
 
 /// Returns a neighbor of the given grid by moving a random number of
 /// random towers to a random valid location (functions of temp)
-fn neighbor_temp_towers(param: &Grid, temp: f64) -> Grid {
-	let mut rng = Xoshiro256PlusPlus::from_entropy();
-
+fn neighbor_temp_towers(param: &Grid, temp: f64, rng: &mut impl Rng) -> Grid {
 	// Percent of towers to remove as a func of temperature
 	let percent = (temp / INIT_TEMP) * INIT_CULLING;
 
@@ -98,7 +112,8 @@ fn neighbor_temp_towers(param: &Grid, temp: f64) -> Grid {
 	// Create a random vector of towers
 	let towers_hashmap = grid.get_towers_ref();
 	let mut towers: Vec<Point> = towers_hashmap.keys().map(|p| *p).collect();
-	towers.shuffle(&mut rng);
+	towers.sort();
+	towers.shuffle(rng);
 
 	let towers_to_move = max((percent * (towers.len() as f64)) as usize, 2);
 
@@ -109,58 +124,83 @@ fn neighbor_temp_towers(param: &Grid, temp: f64) -> Grid {
 	}
 
 	// Move towers to a random locations such that they cover uncovered cities
-	let mut uncovered_cities: Vec<Point> = grid.get_uncovered_cities().iter().map(|p| *p).collect();
-
-	while !grid.is_valid() {
-		let city_to_cover = uncovered_cities.pop().unwrap();
-
-		// If city_to_cover is not covered
-		if grid.is_city_uncovered(city_to_cover) {
-			// Add a tower in a random location that covers city_to_cover
-			let candidate_points: Vec<Point> =
-				Point::points_within_naive(city_to_cover, grid.service_radius(), grid.dimension())
-					.iter()
-					.map(|p| *p)
-					.collect();
-			let point_to_move_to = candidate_points.choose(&mut rng).unwrap();
-
-			grid.add_tower(point_to_move_to.x, point_to_move_to.y);
-		}
-	}
+	grid.cover_remaining_greedily(rng);
 
 	grid
 }
 
 // Return a valid neighbor of the current state with the redundant towers
-// removed
-fn neighbor_remove_towers(param: &Grid) -> Grid {
-	let grid = neighbor_one_tower(param);
-	let clone_towers = grid.get_towers_ref();
-	let mut ret_grid = grid.clone();
-	for (t, _) in clone_towers {
-		ret_grid.remove_tower(t.x, t.y);
-		if !ret_grid.is_valid() {
-			ret_grid.add_tower(t.x, t.y);
+// removed. The neighbor is pruned in place, checking each tower with
+// is_removable rather than removing it and adding it back, so each step costs
+// a single clone of the grid.
+pub fn neighbor_remove_towers(param: &Grid, rng: &mut impl Rng) -> Grid {
+	let mut grid = neighbor_one_tower(param, rng);
+	let mut towers: Vec<Point> = grid.towers().collect();
+	towers.sort();
+	for t in towers {
+		if grid.is_removable(t) {
+			grid.remove_tower(t.x, t.y);
 		}
 	}
-	ret_grid
+	grid
 }
 
-/// Run the simulated annealing algorithm
-pub fn run(grid: &mut Grid, output_path: &str) -> Result<(), Error> {
-	let rng = Xoshiro256PlusPlus::from_entropy();
-
+/// Run the simulated annealing algorithm restarts times (at least once) from
+/// the current solution, writing out the best result across all runs. Each run
+/// seeds its random number generators from rng.
+pub fn run(
+	grid: &mut Grid,
+	output_path: &str,
+	restarts: usize,
+	max_iters: u64,
+	rng: &mut impl Rng,
+) -> Result<(), Error> {
 	// Initial grid
 	let mut init_grid = grid.clone();
-	let sol_towers = Grid::towers_from_file(output_path);
-	for point in sol_towers.iter() {
-		init_grid.add_tower(point.x, point.y);
+	init_grid.apply_solution(output_path)?;
+	let best = anneal_restarts(&init_grid, restarts, max_iters, rng)?;
+
+	let old_penalty = api::get_penalty_from_file(output_path)?;
+	info!("{} -> {}", old_penalty, best.penalty());
+	write_log(output_path, old_penalty, best.penalty());
+	best.write_solution(output_path, false)?;
+
+	Ok(())
+}
+
+/// Runs simulated annealing restarts times (at least once) from init_grid and
+/// returns the best grid found across all runs.
+fn anneal_restarts(init_grid: &Grid, restarts: usize, max_iters: u64, rng: &mut impl Rng) -> Result<Grid, Error> {
+	let mut best = anneal(init_grid.clone(), max_iters, rng)?;
+	for i in 1..max(restarts, 1) {
+		if solvers::stop_requested() {
+			break;
+		}
+		let candidate = anneal(init_grid.clone(), max_iters, rng)?;
+		debug!(
+			"Restart {}: {} (best so far {})",
+			i,
+			candidate.penalty(),
+			best.penalty()
+		);
+		if candidate.penalty() < best.penalty() {
+			best = candidate;
+		}
 	}
+	Ok(best)
+}
+
+/// Runs a single simulated annealing pass starting from the given grid and
+/// returns the best grid found
+fn anneal(init_grid: Grid, max_iters: u64, rng: &mut impl Rng) -> Result<Grid, Error> {
+	// Separate generators for accepting moves and for picking neighbors
+	let rng_accept = Xoshiro256PlusPlus::seed_from_u64(rng.gen());
+	let rng_neighbor = Xoshiro256PlusPlus::seed_from_u64(rng.gen());
 
 	// Cost function
-	let operator = Penalty::new(init_grid.penalty());
+	let operator = Penalty::new(init_grid.penalty(), rng_neighbor);
 
-	let solver = SimulatedAnnealing::new(INIT_TEMP, rng)?
+	let solver = SimulatedAnnealing::new(INIT_TEMP, rng_accept)?
 		.temp_func(SATempFunc::TemperatureFast)
 		// Optional: Reanneal after n iterations (resets temperature to initial temperature)
 		.reannealing_fixed(1000)
@@ -171,7 +211,7 @@ pub fn run(grid: &mut Grid, output_path: &str) -> Result<(), Error> {
 
 	let res = Executor::new(operator, solver, init_grid)
 		.add_observer(ArgminSlogLogger::term(), ObserverMode::Always)
-		.max_iters(MAX_ITERS)
+		.max_iters(max_iters)
 		.target_cost(0.0)
 		.run()?;
 
@@ -179,22 +219,8 @@ pub fn run(grid: &mut Grid, output_path: &str) -> Result<(), Error> {
 	std::thread::sleep(std::time::Duration::from_secs(1));
 
 	// Print result
-	println!("{}", res);
-	println!("---------------------------------------");
-	println!(
-		"{} -> {}",
-		api::get_penalty_from_file(output_path).unwrap(),
-		res.state.best_param.penalty()
-	);
-	println!("---------------------------------------");
-	write_log(
-		output_path,
-		api::get_penalty_from_file(output_path).unwrap(),
-		res.state.best_param.penalty(),
-	);
-	res.state.best_param.write_solution(output_path);
-
-	Ok(())
+	debug!("{}", res);
+	Ok(res.state.best_param)
 }
 
 /// Write the log to a file
@@ -213,3 +239,50 @@ fn write_log(id: &str, old_pen: f64, new_pen: f64) {
 	log_string.push_str("\n");
 	file.write_all(log_string.as_bytes()).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+	use rand::rngs::StdRng;
+
+	use super::*;
+	use crate::grid::tests::{random_grid, temp_path};
+
+	#[test]
+	fn restarts_keep_the_best_valid_grid() {
+		let mut rng = StdRng::seed_from_u64(1189);
+		let mut init_grid = random_grid(&mut rng, 10, 2, 3, 12);
+		init_grid.random_valid_solution(&mut rng);
+
+		for restarts in [0, 2] {
+			let best = anneal_restarts(&init_grid, restarts, 50, &mut rng).unwrap();
+			assert!(best.is_valid());
+			assert!(best.penalty() <= init_grid.penalty());
+		}
+	}
+
+	#[test]
+	fn restarts_write_the_better_of_two_runs() {
+		let mut rng = StdRng::seed_from_u64(1189);
+		let mut init_grid = random_grid(&mut rng, 10, 2, 3, 12);
+		init_grid.random_valid_solution(&mut rng);
+
+		// Each run draws its seeds from the rng in turn, so the same seed gives
+		// the same two runs
+		let mut runs_rng = StdRng::seed_from_u64(11890);
+		let first = anneal(init_grid.clone(), 50, &mut runs_rng).unwrap();
+		let second = anneal(init_grid.clone(), 50, &mut runs_rng).unwrap();
+		let best_penalty = first.penalty().min(second.penalty());
+		let best = anneal_restarts(&init_grid, 2, 50, &mut StdRng::seed_from_u64(11890)).unwrap();
+		assert_eq!(best.penalty(), best_penalty);
+
+		let path = temp_path("anneal-restarts.out");
+		init_grid.write_solution(&path, true).unwrap();
+		let mut grid = init_grid.clone();
+		grid.remove_all_towers();
+		run(&mut grid, &path, 2, 50, &mut StdRng::seed_from_u64(11890)).unwrap();
+		grid.apply_solution(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert!(grid.is_valid());
+		assert_eq!(grid.penalty(), best_penalty);
+	}
+}
@@ -1,12 +1,17 @@
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::prelude::*;
-use std::io::BufReader;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fmt, fs, io};
 
+use futures::stream::{self, StreamExt};
+use log::warn;
 use reqwest;
 use serde::{Deserialize, Serialize};
 
+use crate::grid::{input_stem, open_file, Grid};
+
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Debug)]
 struct APIResponse {
@@ -20,64 +25,263 @@ struct Scores {
 	TeamScore: f64,
 }
 
+// Server hosting the scoreboard, unless overridden by --server or
+// PENGUIN_SERVER
+pub const DEFAULT_SERVER: &str = "https://project.cs170.dev";
+
+// Where leaderboard scores are cached between runs
+const LEADERBOARD_CACHE_PATH: &str = "./.leaderboard_cache.json";
+// How long a cached leaderboard score is used before fetching it again
+pub const LEADERBOARD_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
 pub enum InputType {
 	Small,
 	Medium,
 	Large,
 }
 
-/// Prints out the inputs we have better/worse scores than
-pub async fn get_api_result(size: &InputType) {
-	let input_type: &str;
+impl InputType {
+	/// Returns the directory name for this input size
+	pub fn dir_name(&self) -> &'static str {
+		match self {
+			InputType::Small => "small",
+			InputType::Medium => "medium",
+			InputType::Large => "large",
+		}
+	}
+}
+
+/// How our output for a test compares to the leaderboard, for the api
+/// command's report.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Comparison {
+	pub test:             u8,
+	// Our penalty, if we have an output with a readable penalty
+	pub ours:             Option<f64>,
+	pub leaderboard:      f64,
+	// ours - leaderboard, so negative means we are better
+	pub diff:             Option<f64>,
+	pub has_local_output: bool,
+}
+
+/// Leaderboard scores saved to disk between runs, since they change slowly.
+pub struct LeaderboardCache {
+	// Mapping from <leaderboard url of a test, score and when it was fetched>,
+	// so scores from different servers are kept apart
+	scores:  Mutex<HashMap<String, CachedScore>>,
+	// How long a cached score is used for
+	ttl:     Duration,
+	// Whether to ignore the cached scores (they are still updated)
+	refresh: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct CachedScore {
+	score:      f64,
+	// Seconds since the unix epoch
+	fetched_at: u64,
+}
+
+impl LeaderboardCache {
+	/// Loads the cache from disk, starting empty if there is none yet or it is
+	/// corrupt. If refresh is set, every score is fetched again.
+	pub fn load(ttl: Duration, refresh: bool) -> Self {
+		let scores = match fs::read_to_string(LEADERBOARD_CACHE_PATH) {
+			Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+				warn!("Ignoring corrupt leaderboard cache {}: {}", LEADERBOARD_CACHE_PATH, e);
+				HashMap::new()
+			}),
+			Err(_) => HashMap::new(),
+		};
+		LeaderboardCache {
+			scores: Mutex::new(scores),
+			ttl,
+			refresh,
+		}
+	}
+
+	/// Returns the cached score at the given leaderboard url, unless it is
+	/// missing, older than the ttl, or the cache is being refreshed.
+	fn get(&self, url: &str) -> Option<f64> {
+		if self.refresh {
+			return None;
+		}
+		let cached = *self.scores.lock().unwrap().get(url)?;
+		(now_secs().saturating_sub(cached.fetched_at) < self.ttl.as_secs()).then_some(cached.score)
+	}
+
+	fn insert(&self, url: &str, score: f64) {
+		let cached = CachedScore {
+			score,
+			fetched_at: now_secs(),
+		};
+		self.scores.lock().unwrap().insert(url.to_string(), cached);
+	}
+
+	/// Writes the cache to disk, printing a warning if that fails since the
+	/// scores can always be fetched again.
+	pub fn save(&self) {
+		let json = serde_json::to_string(&*self.scores.lock().unwrap()).expect("Failed to serialize leaderboard cache");
+		if let Err(e) = fs::write(LEADERBOARD_CACHE_PATH, json) {
+			warn!("Could not save leaderboard cache {}: {}", LEADERBOARD_CACHE_PATH, e);
+		}
+	}
+}
+
+fn now_secs() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Prints out the inputs we have better/worse scores than (reading our outputs
+/// from output_dir, laid out as <size>/<id>.out), fetching up to concurrency
+/// leaderboard scores at once from server (unless they are cached).
+/// Tests whose score couldn't be fetched are listed at the end rather than
+/// aborting the run. If report is given, also writes the comparison of every
+/// fetched test to it, see write_comparison_report.
+pub async fn get_api_result(
+	server: &str,
+	size: &InputType,
+	output_dir: &Path,
+	concurrency: usize,
+	cache: &LeaderboardCache,
+	report: Option<&Path>,
+) -> io::Result<()> {
+	let input_type = size.dir_name();
+	let output_dir = output_dir.join(input_type);
 	// { test_number: (our_score, leaderboard_score), ... }
 	let mut worse_scores: HashMap<u8, (f64, f64)> = HashMap::new();
 	let mut better_scores: HashMap<u8, (f64, f64)> = HashMap::new();
 
-	// Maps to directory name
-	match size {
-		InputType::Small => input_type = "small",
-		InputType::Medium => input_type = "medium",
-		InputType::Large => input_type = "large",
-	}
-
 	// Number of tests in each size
 	let input_count: HashMap<&str, u8> = HashMap::from([("small", 241), ("medium", 239), ("large", 239)]);
 
 	let count = *input_count.get(input_type).unwrap();
-	for i in 1..=count {
-		if i == 240 && input_type == "small" {
-			// small/240 is invalid
-			continue;
-		}
+	// small/240 is invalid
+	let tests = (1..=count).filter(|&i| !(i == 240 && input_type == "small"));
+	let mut highest_scores = stream::iter(tests)
+		.map(|i| async move { (i, get_best_leaderboard_score(server, i, input_type, cache).await) })
+		.buffer_unordered(concurrency.max(1));
+	let mut failures: Vec<(u8, String)> = Vec::new();
+	let mut comparisons: Vec<Comparison> = Vec::new();
 
-		let highest_score = get_best_leaderboard_score(i, &input_type).await;
+	while let Some((i, highest_score)) = highest_scores.next().await {
 		match highest_score {
-			Err(e) => panic!("{}", e),
+			Err(e) => {
+				println!("{:0>3}: Could not fetch leaderboard: {}", i, e);
+				failures.push((i, e));
+			}
 			Ok(leaderboard_penalty) => {
-				// Found highest leaderboard score
-				println!("{}: {:?}", format!("{:0>3}", i), round(leaderboard_penalty));
-				let our_path = "./outputs/".to_string() + &input_type.to_string() + "/" + &format!("{:0>3}", i) + ".out";
-				// We don't have an output file
-				if !Path::new(&our_path).is_file() {
-					println!("Local test {} not found", i.to_string());
-					continue;
-				}
+				comparisons.push(record_comparison(
+					i,
+					&output_dir,
+					leaderboard_penalty,
+					&mut better_scores,
+					&mut worse_scores,
+				));
+			}
+		}
+	}
 
-				let our_penalty = round(get_penalty_from_file(our_path.as_str()).unwrap());
-				let rounded_leaderboard = round(leaderboard_penalty);
+	cache.save();
+	print_comparison(better_scores, worse_scores);
 
-				if our_penalty > rounded_leaderboard {
-					worse_scores.insert(i, (our_penalty, rounded_leaderboard));
-				} else if our_penalty < rounded_leaderboard {
-					better_scores.insert(i, (our_penalty, rounded_leaderboard));
-				}
-			}
+	if !failures.is_empty() {
+		failures.sort();
+		println!("\n{} Failed:", failures.len());
+		for (i, e) in failures {
+			println!("Test {:0>3}. {}", i, e);
 		}
 	}
 
+	if let Some(report) = report {
+		comparisons.sort_by_key(|c| c.test);
+		write_comparison_report(&comparisons, report)?;
+		println!("\nWrote report to {}", report.display());
+	}
+	Ok(())
+}
+
+/// Writes the comparisons to path as JSON if its extension is json, and as CSV
+/// (with a header row, and empty fields for missing values) otherwise.
+fn write_comparison_report(comparisons: &[Comparison], path: &Path) -> io::Result<()> {
+	let contents = if path.extension().is_some_and(|ext| ext == "json") {
+		serde_json::to_string_pretty(comparisons)?
+	} else {
+		let optional = |value: Option<f64>| value.map_or(String::new(), |v| v.to_string());
+		let mut csv = String::from("test,ours,leaderboard,diff,has_local_output\n");
+		for c in comparisons {
+			csv += &format!(
+				"{},{},{},{},{}\n",
+				c.test,
+				optional(c.ours),
+				c.leaderboard,
+				optional(c.diff),
+				c.has_local_output
+			);
+		}
+		csv
+	};
+	fs::write(path, contents)
+}
+
+/// Compares our output for the given test in output_dir against the
+/// leaderboard penalty, recording it as better or worse, and prints a running
+/// summary line.
+fn record_comparison(
+	i: u8,
+	output_dir: &Path,
+	leaderboard_penalty: f64,
+	better_scores: &mut HashMap<u8, (f64, f64)>,
+	worse_scores: &mut HashMap<u8, (f64, f64)>,
+) -> Comparison {
+	let rounded_leaderboard = round(leaderboard_penalty);
+	let mut comparison = Comparison {
+		test:             i,
+		ours:             None,
+		leaderboard:      rounded_leaderboard,
+		diff:             None,
+		has_local_output: false,
+	};
+	let our_path = output_dir.join(format!("{:0>3}.out", i));
+	// We don't have an output file
+	if !our_path.is_file() {
+		println!("{:0>3}: {:?}. Local test not found", i, rounded_leaderboard);
+		return comparison;
+	}
+	comparison.has_local_output = true;
+
+	let our_penalty = match get_penalty_from_file(our_path.to_str().unwrap()) {
+		Ok(penalty) => round(penalty),
+		Err(e) => {
+			println!("{:0>3}: Could not read local test: {}", i, e);
+			return comparison;
+		}
+	};
+	comparison.ours = Some(our_penalty);
+	comparison.diff = Some(round(our_penalty - rounded_leaderboard));
+
+	if our_penalty > rounded_leaderboard {
+		worse_scores.insert(i, (our_penalty, rounded_leaderboard));
+	} else if our_penalty < rounded_leaderboard {
+		better_scores.insert(i, (our_penalty, rounded_leaderboard));
+	}
+	println!(
+		"{:0>3}: {:?}. Ours: {}. ({} better, {} worse so far)",
+		i,
+		rounded_leaderboard,
+		our_penalty,
+		better_scores.len(),
+		worse_scores.len()
+	);
+	comparison
+}
+
+/// Prints the final better/worse comparison, each sorted by diff. Results may
+/// be recorded in any order, so everything is sorted here.
+fn print_comparison(better_scores: HashMap<u8, (f64, f64)>, worse_scores: HashMap<u8, (f64, f64)>) {
 	println!("\n\n\n\n");
 	println!("{} Better:", better_scores.len());
-	for (key, (ours, leaderboard)) in better_scores {
+	for (key, (ours, leaderboard)) in sort_by_diff(better_scores).into_iter().rev() {
 		println!(
 			"Test {}. Ours: {}. Best: {}. Diff: {}",
 			format!("{:0>3}", key),
@@ -99,9 +303,336 @@ pub async fn get_api_result(size: &InputType) {
 	}
 }
 
-fn sort_by_diff(scores: HashMap<u8, (f64, f64)>) -> Vec<(u8, (f64, f64))> {
-	let mut vec = scores.into_iter().collect::<Vec<(u8, (f64, f64))>>();
-	vec.sort_by(|a, b| (a.1 .1 - a.1 .0).partial_cmp(&(b.1 .1 - b.1 .0)).unwrap());
+/// Compares our current outputs of the given size in output_dir against a saved
+/// baseline directory (both laid out as <dir>/<size>/<id>.out).
+/// Prints the inputs that regressed and improved, and returns whether any
+/// regressed. A baseline input with no current output counts as a regression.
+pub fn regress(baseline_dir: &Path, output_dir: &Path, size: &InputType) -> bool {
+	let input_type = size.dir_name();
+	// { test_number: (baseline_score, our_score), ... }
+	let mut regressed: HashMap<u8, (f64, f64)> = HashMap::new();
+	let mut improved: HashMap<u8, (f64, f64)> = HashMap::new();
+	let mut missing: Vec<u8> = Vec::new();
+	let (mut baseline_total, mut our_total) = (0., 0.);
+
+	let baseline_path = baseline_dir.join(input_type);
+	let dir = fs::read_dir(&baseline_path).expect("Error reading baseline directory");
+	for path in dir {
+		let path = path.expect("Error reading baseline directory").path();
+		let test_num = match path
+			.file_stem()
+			.and_then(|s| s.to_str())
+			.and_then(|s| s.parse::<u8>().ok())
+		{
+			Some(i) => i,
+			None => continue,
+		};
+		let our_path = output_dir.join(input_type).join(path.file_name().unwrap());
+		let baseline_penalty = match get_penalty_from_file(path.to_str().unwrap()) {
+			Ok(penalty) => round(penalty),
+			Err(e) => {
+				println!("Skipping baseline {}: {}", path.display(), e);
+				continue;
+			}
+		};
+		baseline_total += baseline_penalty;
+
+		// A missing or unreadable output counts as a regression
+		let our_penalty = match get_penalty_from_file(our_path.to_str().unwrap()) {
+			Ok(penalty) => round(penalty),
+			_ => {
+				missing.push(test_num);
+				continue;
+			}
+		};
+		our_total += our_penalty;
+
+		if our_penalty > baseline_penalty {
+			regressed.insert(test_num, (baseline_penalty, our_penalty));
+		} else if our_penalty < baseline_penalty {
+			improved.insert(test_num, (baseline_penalty, our_penalty));
+		}
+	}
+
+	let any_regressed = !regressed.is_empty() || !missing.is_empty();
+	println!("{} Improved:", improved.len());
+	for (key, (baseline, ours)) in sort_by_diff(improved) {
+		println!(
+			"Test {:0>3}. Baseline: {}. Ours: {}. Diff: {}",
+			key,
+			baseline,
+			ours,
+			round(baseline - ours)
+		);
+	}
+
+	println!("\n{} Regressed:", regressed.len() + missing.len());
+	for (key, (baseline, ours)) in sort_by_diff(regressed).into_iter().rev() {
+		println!(
+			"Test {:0>3}. Baseline: {}. Ours: {}. Diff: {}",
+			key,
+			baseline,
+			ours,
+			round(ours - baseline)
+		);
+	}
+	missing.sort();
+	for key in missing.iter() {
+		println!("Test {:0>3}. Missing local output", key);
+	}
+
+	println!(
+		"\nTotal. Baseline: {}. Ours: {}.",
+		round(baseline_total),
+		round(our_total)
+	);
+	any_regressed
+}
+
+/// Summarizes our outputs of the given size in output_dir, for the inputs in
+/// input_dir (both laid out as <dir>/<size>/<id>): the total, mean, median, min
+/// and max penalty over the outputs with a readable penalty header, the inputs
+/// with no output or an unreadable one, and the num_worst highest penalties.
+pub fn stats(input_dir: &Path, output_dir: &Path, size: &InputType, num_worst: usize, bound: bool) -> io::Result<()> {
+	let input_type = size.dir_name();
+	let output_dir = output_dir.join(input_type);
+	// [ (test_number, our_score), ... ]
+	let mut penalties: Vec<(u8, f64)> = Vec::new();
+	let mut missing: Vec<u8> = Vec::new();
+	let mut unreadable: Vec<(u8, ParseError)> = Vec::new();
+	// Lower bound on the penalty of each read output's input, if asked for
+	let mut bounds: HashMap<u8, f64> = HashMap::new();
+	// Tests already read
+	let mut seen: HashSet<u8> = HashSet::new();
+
+	for path in fs::read_dir(input_dir.join(input_type))? {
+		let path = path?.path();
+		// Inputs are named <id>.in, or <id>.in.gz if compressed, and an input
+		// stored both ways is only counted once
+		let test_num = match input_stem(&path).and_then(|s| s.parse::<u8>().ok()) {
+			Some(i) if !seen.contains(&i) => i,
+			_ => continue,
+		};
+		seen.insert(test_num);
+		let output_path = output_dir.join(format!("{:0>3}.out", test_num));
+		if !output_path.is_file() {
+			missing.push(test_num);
+			continue;
+		}
+		match get_penalty_from_file(output_path.to_str().unwrap()) {
+			Ok(penalty) => penalties.push((test_num, round(penalty))),
+			Err(e) => {
+				unreadable.push((test_num, e));
+				continue;
+			}
+		}
+		if bound {
+			match Grid::from_file(path.to_str().unwrap()) {
+				Ok(grid) => {
+					bounds.insert(test_num, grid.penalty_lower_bound());
+				}
+				Err(e) => warn!("no bound for test {:0>3}: {}", test_num, e),
+			}
+		}
+	}
+	// Highest penalty first, then by test number
+	penalties.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+	missing.sort();
+	unreadable.sort_by_key(|(test_num, _)| *test_num);
+
+	println!(
+		"{} outputs read ({} missing, {} unreadable)",
+		penalties.len(),
+		missing.len(),
+		unreadable.len()
+	);
+	if let (Some(&(max_test, max)), Some(&(min_test, min))) = (penalties.first(), penalties.last()) {
+		let n = penalties.len();
+		let total: f64 = penalties.iter().map(|(_, p)| p).sum();
+		let median = if n % 2 == 1 {
+			penalties[n / 2].1
+		} else {
+			(penalties[n / 2 - 1].1 + penalties[n / 2].1) / 2.
+		};
+		println!("Total: {}", round(total));
+		println!("Mean: {}", round(total / n as f64));
+		println!("Median: {}", round(median));
+		println!("Min: {} (Test {:0>3})", min, min_test);
+		println!("Max: {} (Test {:0>3})", max, max_test);
+		if bound {
+			// Over the outputs with a bound
+			let total_bound: f64 = bounds.values().sum();
+			let total_bounded: f64 = penalties
+				.iter()
+				.filter(|(test_num, _)| bounds.contains_key(test_num))
+				.map(|(_, p)| p)
+				.sum();
+			println!(
+				"Total bound: {}. Gap: {}",
+				round(total_bound),
+				gap(total_bounded, total_bound)
+			);
+		}
+
+		println!("\nWorst {}:", num_worst.min(n));
+		for (test_num, penalty) in penalties.iter().take(num_worst) {
+			match bounds.get(test_num) {
+				Some(&b) => println!(
+					"Test {:0>3}. Penalty: {}. Bound: {}. Gap: {}",
+					test_num,
+					penalty,
+					b,
+					gap(*penalty, b)
+				),
+				None => println!("Test {:0>3}. Penalty: {}", test_num, penalty),
+			}
+		}
+	}
+
+	if !missing.is_empty() {
+		println!("\n{} Missing:", missing.len());
+		for test_num in missing {
+			println!("Test {:0>3}", test_num);
+		}
+	}
+	if !unreadable.is_empty() {
+		println!("\n{} Unreadable:", unreadable.len());
+		for (test_num, e) in unreadable {
+			println!("Test {:0>3}: {}", test_num, e);
+		}
+	}
+	Ok(())
+}
+
+/// Formats how far above the lower bound a penalty is, as a percentage of the
+/// penalty.
+pub fn gap(penalty: f64, lower_bound: f64) -> String {
+	if penalty <= 0. {
+		return "0%".to_string();
+	}
+	format!("{:.1}%", 100. * (penalty - lower_bound) / penalty)
+}
+
+/// Compares the solutions in old_dir against those in new_dir, matched by their
+/// path relative to each directory, so both can be laid out like ./outputs or
+/// hold .out files directly. Prints the solutions that improved and regressed
+/// with their diffs, those only in one directory or without a readable penalty,
+/// and the totals over the solutions in both.
+pub fn diff(old_dir: &Path, new_dir: &Path) -> io::Result<()> {
+	let old = read_penalties(old_dir)?;
+	let mut new = read_penalties(new_dir)?;
+	// { relative path: (old_score, new_score), ... }
+	let mut regressed: HashMap<PathBuf, (f64, f64)> = HashMap::new();
+	let mut improved: HashMap<PathBuf, (f64, f64)> = HashMap::new();
+	let mut only_old: Vec<PathBuf> = Vec::new();
+	let mut unreadable: Vec<(PathBuf, ParseError)> = Vec::new();
+	let (mut num_unchanged, mut old_total, mut new_total) = (0, 0., 0.);
+
+	for (path, old_penalty) in old {
+		let new_penalty = match new.remove(&path) {
+			Some(new_penalty) => new_penalty,
+			None => {
+				only_old.push(path);
+				continue;
+			}
+		};
+		let (old_penalty, new_penalty) = match (old_penalty, new_penalty) {
+			(Ok(old_penalty), Ok(new_penalty)) => (round(old_penalty), round(new_penalty)),
+			(Err(e), _) => {
+				unreadable.push((old_dir.join(path), e));
+				continue;
+			}
+			(_, Err(e)) => {
+				unreadable.push((new_dir.join(path), e));
+				continue;
+			}
+		};
+		old_total += old_penalty;
+		new_total += new_penalty;
+
+		if new_penalty > old_penalty {
+			regressed.insert(path, (old_penalty, new_penalty));
+		} else if new_penalty < old_penalty {
+			improved.insert(path, (old_penalty, new_penalty));
+		} else {
+			num_unchanged += 1;
+		}
+	}
+	// Everything left was only in new_dir
+	let only_new: Vec<PathBuf> = new.into_keys().collect();
+
+	println!("{} Improved:", improved.len());
+	for (path, (old_penalty, new_penalty)) in sort_by_diff(improved) {
+		println!(
+			"{}. Old: {}. New: {}. Diff: {}",
+			path.display(),
+			old_penalty,
+			new_penalty,
+			round(old_penalty - new_penalty)
+		);
+	}
+
+	println!("\n{} Regressed:", regressed.len());
+	for (path, (old_penalty, new_penalty)) in sort_by_diff(regressed).into_iter().rev() {
+		println!(
+			"{}. Old: {}. New: {}. Diff: {}",
+			path.display(),
+			old_penalty,
+			new_penalty,
+			round(new_penalty - old_penalty)
+		);
+	}
+
+	println!("\n{} Unchanged", num_unchanged);
+	for (dir, paths) in [(old_dir, only_old), (new_dir, only_new)] {
+		if !paths.is_empty() {
+			println!("\n{} Only in {}:", paths.len(), dir.display());
+			for path in paths {
+				println!("{}", path.display());
+			}
+		}
+	}
+	if !unreadable.is_empty() {
+		println!("\n{} Unreadable:", unreadable.len());
+		for (path, e) in unreadable {
+			println!("{}: {}", path.display(), e);
+		}
+	}
+
+	println!(
+		"\nTotal. Old: {}. New: {}. Diff: {}",
+		round(old_total),
+		round(new_total),
+		round(new_total - old_total)
+	);
+	Ok(())
+}
+
+/// Returns the penalty of every .out file under dir (searching subdirectories),
+/// keyed by its path relative to dir.
+fn read_penalties(dir: &Path) -> io::Result<BTreeMap<PathBuf, Result<f64, ParseError>>> {
+	let mut penalties = BTreeMap::new();
+	let mut dirs = vec![dir.to_path_buf()];
+	while let Some(current) = dirs.pop() {
+		let entries =
+			fs::read_dir(&current).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", current.display(), e)))?;
+		for entry in entries {
+			let path = entry?.path();
+			if path.is_dir() {
+				dirs.push(path);
+			} else if path.extension().is_some_and(|ext| ext == "out") {
+				let penalty = get_penalty_from_file(path.to_str().unwrap());
+				penalties.insert(path.strip_prefix(dir).unwrap().to_path_buf(), penalty);
+			}
+		}
+	}
+	Ok(penalties)
+}
+
+/// Sorts scores (first, second) by second - first, breaking ties by key.
+fn sort_by_diff<K: Ord>(scores: HashMap<K, (f64, f64)>) -> Vec<(K, (f64, f64))> {
+	let mut vec = scores.into_iter().collect::<Vec<(K, (f64, f64))>>();
+	vec.sort_by(|a, b| (a.1 .1 - a.1 .0).total_cmp(&(b.1 .1 - b.1 .0)).then(a.0.cmp(&b.0)));
 	vec
 }
 
@@ -110,36 +641,84 @@ pub fn round(n: f64) -> f64 {
 	(n * 1000000.0).round() / 1000000.0
 }
 
-/// Gets our penalty from a specific file
-pub fn get_penalty_from_file(path: &str) -> Result<f64, &'static str> {
-	let file = File::open(path).unwrap();
-	let reader = BufReader::new(file);
-	let lines: Vec<String> = reader.lines().collect::<Result<_, _>>().unwrap();
-	let penalty_line = match lines.get(0) {
-		Some(line) => line,
-		None => return Err("Could not read line"),
-	}; // Penalty = xxx
-	let split_line: Vec<&str> = penalty_line.split_whitespace().collect();
-	let existing_penalty: f64 = split_line.get(3).unwrap().parse::<f64>().unwrap();
-	Ok(existing_penalty)
+/// Why get_penalty_from_file couldn't read the penalty of a solution file.
+#[derive(Debug)]
+pub enum ParseError {
+	Io(io::Error),
+	// No comment line has a Penalty token.
+	MissingPenalty,
+	// The token after Penalty isn't a finite number.
+	InvalidPenalty(String),
 }
 
-/// Returns the best leaderboard score for the given test case
-async fn get_best_leaderboard_score(test_num: u8, input_type: &str) -> Result<f64, String> {
-	let get_url = "https://project.cs170.dev/scoreboard/".to_string() + input_type + "/" + &test_num.to_string();
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ParseError::Io(e) => write!(f, "{}", e),
+			ParseError::MissingPenalty => write!(f, "no \"# Penalty = <value>\" header"),
+			ParseError::InvalidPenalty(s) => write!(f, "invalid penalty {:?}", s),
+		}
+	}
+}
 
-	let res = reqwest::get(get_url).await.unwrap();
+impl std::error::Error for ParseError {}
 
-	match res.status() {
-		reqwest::StatusCode::OK => {
-			match res.json::<APIResponse>().await {
-				Ok(parsed) => {
-					return Ok(get_min_score(parsed.Entries));
-				}
-				Err(_) => return Err("The response didn't match the shape we expected.".to_string()),
+impl From<io::Error> for ParseError {
+	fn from(e: io::Error) -> Self {
+		ParseError::Io(e)
+	}
+}
+
+/// Gets our penalty from a specific file, i.e. the value after the Penalty
+/// token of its first comment line that has one, as in "# Penalty = 123.45".
+/// The file is decompressed if it ends in .gz.
+pub fn get_penalty_from_file(path: &str) -> Result<f64, ParseError> {
+	let reader = open_file(path)?;
+	for line in reader.lines() {
+		let line = line?;
+		let comment = match line.trim().strip_prefix('#') {
+			Some(comment) => comment,
+			None => continue,
+		};
+		let mut tokens = comment
+			.split(|c: char| c.is_whitespace() || c == '=' || c == ':')
+			.filter(|t| !t.is_empty());
+		if tokens.any(|t| t.eq_ignore_ascii_case("penalty")) {
+			let value = tokens.next().unwrap_or("");
+			return match value.parse::<f64>() {
+				Ok(penalty) if penalty.is_finite() => Ok(penalty),
+				_ => Err(ParseError::InvalidPenalty(value.to_string())),
 			};
 		}
-		other => return Err("Other error occurred".to_string() + other.as_str()),
+	}
+	Err(ParseError::MissingPenalty)
+}
+
+/// Returns the best score on the leaderboard for the given test, from the
+/// cache if it has a fresh one.
+async fn get_best_leaderboard_score(
+	server: &str,
+	test_num: u8,
+	input_type: &str,
+	cache: &LeaderboardCache,
+) -> Result<f64, String> {
+	let get_url = server.trim_end_matches('/').to_string() + "/scoreboard/" + input_type + "/" + &test_num.to_string();
+	if let Some(score) = cache.get(&get_url) {
+		return Ok(score);
+	}
+
+	let res = reqwest::get(&get_url).await.map_err(|e| e.to_string())?;
+
+	match res.status() {
+		reqwest::StatusCode::OK => match res.json::<APIResponse>().await {
+			Ok(parsed) => {
+				let score = get_min_score(parsed.Entries);
+				cache.insert(&get_url, score);
+				Ok(score)
+			}
+			Err(_) => Err("The response didn't match the shape we expected.".to_string()),
+		},
+		other => Err("Other error occurred".to_string() + other.as_str()),
 	}
 }
 
@@ -163,13 +742,122 @@ pub fn input_size_from_string(input: &str) -> Result<InputType, String> {
 }
 
 /// Return whether our score is worse (higher) than the current highest on the
-/// leaderboard
-pub async fn is_score_worse_than_leader(path: &PathBuf) -> Result<bool, String> {
+/// leaderboard. The caller is responsible for saving the cache.
+pub async fn is_score_worse_than_leader(server: &str, path: &Path, cache: &LeaderboardCache) -> Result<bool, String> {
+	let (our_score, leaderboard_score) = scores_against_leader(server, path, cache).await?;
+	Ok(leaderboard_score < our_score)
+}
+
+/// Return whether our score is strictly better (lower) than the current highest
+/// on the leaderboard. The caller is responsible for saving the cache.
+pub async fn is_score_better_than_leader(server: &str, path: &Path, cache: &LeaderboardCache) -> Result<bool, String> {
+	let (our_score, leaderboard_score) = scores_against_leader(server, path, cache).await?;
+	Ok(our_score < leaderboard_score)
+}
+
+/// Returns our (rounded) score for the output at path, and the best one on the
+/// leaderboard.
+async fn scores_against_leader(server: &str, path: &Path, cache: &LeaderboardCache) -> Result<(f64, f64), String> {
 	let input_type = path.parent().unwrap().file_stem().unwrap().to_str().unwrap();
-	let test_num = path.file_stem().unwrap().to_str().unwrap().parse::<u8>().unwrap();
+	let test_num = path
+		.file_stem()
+		.unwrap()
+		.to_str()
+		.unwrap()
+		.parse::<u8>()
+		.map_err(|_| format!("{} is not named after a test number", path.display()))?;
 
-	let leaderboard_score = get_best_leaderboard_score(test_num, input_type).await?;
-	let our_score = get_penalty_from_file(path.to_str().unwrap())?;
+	let leaderboard_score = get_best_leaderboard_score(server, test_num, input_type, cache).await?;
+	let our_score = get_penalty_from_file(path.to_str().unwrap()).map_err(|e| e.to_string())?;
 
-	Ok(round(leaderboard_score) < round(our_score))
+	Ok((round(our_score), round(leaderboard_score)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::grid::tests::temp_path;
+
+	/// Writes an output with the given penalty header for each (size, id).
+	fn write_outputs(dir: &Path, outputs: &[(&str, u8, f64)]) {
+		for &(size, id, penalty) in outputs {
+			fs::create_dir_all(dir.join(size)).unwrap();
+			let contents = format!("# Penalty = {}\n0\n", penalty);
+			fs::write(dir.join(size).join(format!("{:0>3}.out", id)), contents).unwrap();
+		}
+	}
+
+	#[test]
+	fn regress_flags_worse_and_missing_outputs() {
+		let (baseline, ours) = (
+			PathBuf::from(temp_path("regress-baseline")),
+			PathBuf::from(temp_path("regress-ours")),
+		);
+		write_outputs(&baseline, &[("small", 1, 100.), ("small", 2, 200.)]);
+		write_outputs(&ours, &[("small", 1, 90.), ("small", 2, 200.)]);
+		assert!(!regress(&baseline, &ours, &InputType::Small));
+
+		write_outputs(&ours, &[("small", 2, 210.)]);
+		assert!(regress(&baseline, &ours, &InputType::Small));
+
+		write_outputs(&ours, &[("small", 2, 200.)]);
+		fs::remove_file(ours.join("small").join("001.out")).unwrap();
+		assert!(regress(&baseline, &ours, &InputType::Small));
+
+		fs::remove_dir_all(&baseline).unwrap();
+		fs::remove_dir_all(&ours).unwrap();
+	}
+
+	#[test]
+	fn record_comparison_classifies_outputs() {
+		let dir = PathBuf::from(temp_path("record-comparison"));
+		write_outputs(&dir, &[("small", 1, 90.), ("small", 2, 200.), ("small", 3, 310.)]);
+		fs::write(dir.join("small").join("004.out"), "0\n").unwrap();
+		let (mut better, mut worse) = (HashMap::new(), HashMap::new());
+		let mut record = |i, leaderboard| record_comparison(i, &dir.join("small"), leaderboard, &mut better, &mut worse);
+
+		let c = record(1, 100.);
+		assert_eq!((c.ours, c.diff, c.has_local_output), (Some(90.), Some(-10.), true));
+		let c = record(2, 200.);
+		assert_eq!((c.ours, c.diff), (Some(200.), Some(0.)));
+		record(3, 300.);
+		let c = record(4, 400.);
+		assert_eq!((c.ours, c.diff, c.has_local_output), (None, None, true));
+		let c = record(5, 500.);
+		assert_eq!((c.ours, c.has_local_output), (None, false));
+
+		assert_eq!(better, HashMap::from([(1, (90., 100.))]));
+		assert_eq!(worse, HashMap::from([(3, (310., 300.))]));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn sort_by_diff_puts_largest_gain_last() {
+		let scores = HashMap::from([(1, (90., 100.)), (2, (50., 100.)), (3, (95., 100.)), (4, (45., 50.))]);
+		let keys: Vec<u8> = sort_by_diff(scores).into_iter().map(|(k, _)| k).collect();
+		assert_eq!(keys, vec![3, 4, 1, 2]);
+
+		let scores = HashMap::from([(1, (90., 100.)), (2, (f64::NAN, 100.)), (3, (95., 100.))]);
+		assert_eq!(sort_by_diff(scores).len(), 3);
+	}
+
+	#[test]
+	fn get_penalty_from_file_reads_header_variants() {
+		let path = temp_path("penalty.out");
+		let penalty = |contents: &str| {
+			fs::write(&path, contents).unwrap();
+			get_penalty_from_file(&path)
+		};
+		assert_eq!(penalty("# Penalty = 123.45\n1\n0 0\n").unwrap(), 123.45);
+		assert_eq!(penalty("# solved by hand\n#penalty:170\n1\n0 0\n").unwrap(), 170.);
+		assert_eq!(penalty("1\n0 0\n# Penalty = 170\n").unwrap(), 170.);
+		assert!(matches!(penalty("1\n0 0\n"), Err(ParseError::MissingPenalty)));
+		assert!(matches!(penalty(""), Err(ParseError::MissingPenalty)));
+		assert!(matches!(penalty("# Penalty\n"), Err(ParseError::InvalidPenalty(s)) if s.is_empty()));
+		assert!(matches!(penalty("# Penalty = lots\n"), Err(ParseError::InvalidPenalty(s)) if s == "lots"));
+		assert!(matches!(penalty("# Penalty = NaN\n"), Err(ParseError::InvalidPenalty(s)) if s == "NaN"));
+		assert!(matches!(penalty("# Penalty = inf\n"), Err(ParseError::InvalidPenalty(s)) if s == "inf"));
+		fs::remove_file(&path).unwrap();
+		assert!(matches!(get_penalty_from_file(&path), Err(ParseError::Io(_))));
+	}
 }
@@ -0,0 +1,153 @@
+use std::io::{self, BufRead, Write};
+
+use crate::grid::Grid;
+use crate::point::Point;
+
+const HELP: &str = "Commands:
+	add x y           place a tower at (x, y)
+	rm x y            remove the tower at (x, y)
+	move x y x2 y2    move the tower at (x, y) to (x2, y2)
+	show [x0 y0 x1 y1]
+	                  print the grid, or the window between the two corners
+	penalty           print the penalty
+	valid             print whether all cities are covered
+	save [path]       write the solution (defaults to the output for this input)
+	help              print this message
+	quit              exit the editor";
+
+/// Runs a simple REPL reading commands from the given reader, editing the grid
+/// in place. Invalid commands print an error and are otherwise ignored.
+pub fn run<R: BufRead>(grid: &mut Grid, output_path: &str, reader: R) {
+	println!("{:#}", grid);
+	println!("{}", HELP);
+	prompt();
+
+	for line in reader.lines() {
+		let line = match line {
+			Ok(line) => line,
+			Err(_) => break,
+		};
+		let args: Vec<&str> = line.split_whitespace().collect();
+		match args.first() {
+			None => {}
+			Some(&"quit") | Some(&"exit") => break,
+			Some(_) => match run_command(grid, output_path, &args) {
+				Ok(true) => println!("{:#}", grid),
+				Ok(false) => {}
+				Err(e) => println!("Error: {}", e),
+			},
+		}
+		prompt();
+	}
+}
+
+fn prompt() {
+	print!("> ");
+	io::stdout().flush().unwrap();
+}
+
+/// Runs a single command, returning whether the grid was edited.
+fn run_command(grid: &mut Grid, output_path: &str, args: &[&str]) -> Result<bool, String> {
+	match args {
+		["add", x, y] => {
+			let p = parse_point(x, y)?;
+			grid.try_add_tower(p.x, p.y).map_err(|e| e.to_string())?;
+			Ok(true)
+		}
+		["rm", x, y] => {
+			let p = parse_point(x, y)?;
+			grid.try_remove_tower(p.x, p.y).map_err(|e| e.to_string())?;
+			Ok(true)
+		}
+		["move", x, y, x2, y2] => {
+			let p = parse_point(x, y)?;
+			let q = parse_point(x2, y2)?;
+			grid.try_move_tower(p, q).map_err(|e| e.to_string())?;
+			Ok(true)
+		}
+		["show"] => {
+			println!("{:#}", grid);
+			Ok(false)
+		}
+		["show", x0, y0, x1, y1] => {
+			let p = parse_point(x0, y0)?;
+			let q = parse_point(x1, y1)?;
+			print!(
+				"{}",
+				grid.display_region(p.x.min(q.x), p.y.min(q.y), p.x.max(q.x), p.y.max(q.y))
+			);
+			Ok(false)
+		}
+		["penalty"] => {
+			println!("{}", grid.penalty());
+			Ok(false)
+		}
+		["valid"] => {
+			println!("{}", grid.is_valid());
+			Ok(false)
+		}
+		["save"] | ["save", _] => {
+			if !grid.is_valid() {
+				return Err("Not a valid solution, some cities are uncovered".to_string());
+			}
+			let path = args.get(1).copied().unwrap_or(output_path);
+			grid
+				.write_output(path)
+				.map_err(|e| format!("Could not write {}: {}", path, e))?;
+			println!("Saved to {}", path);
+			Ok(false)
+		}
+		["help"] => {
+			println!("{}", HELP);
+			Ok(false)
+		}
+		_ => Err(format!(
+			"Unknown command {:?}, type help for a list of commands",
+			args.join(" ")
+		)),
+	}
+}
+
+fn parse_point(x: &str, y: &str) -> Result<Point, String> {
+	format!("{} {}", x, y).parse::<Point>()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+	use std::fs;
+	use std::io::Cursor;
+
+	use super::*;
+	use crate::grid::tests::{grid_with_cities, temp_path};
+
+	#[test]
+	fn run_applies_commands_until_quit() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (6, 6)]);
+		let path = temp_path("edit.out");
+		let commands = "add 1 1\nadd 5 5\n\nsave\nmove 5 5 6 6\nrm 9 9\nbogus\nsave\nquit\nadd 3 3\n";
+		run(&mut grid, &path, Cursor::new(commands));
+
+		// The first save is refused, the bad commands change nothing and
+		// everything after quit is ignored
+		assert_eq!(
+			grid.towers().collect::<HashSet<Point>>(),
+			[Point::new(1, 1), Point::new(6, 6)].into_iter().collect()
+		);
+		assert_eq!(Grid::towers_from_file(&path).unwrap(), grid.towers().collect());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn run_command_reports_errors() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1)]);
+		assert_eq!(run_command(&mut grid, "", &["add", "1", "1"]), Ok(true));
+		assert!(run_command(&mut grid, "", &["add", "1", "1"]).is_err());
+		assert!(run_command(&mut grid, "", &["add", "8", "1"]).is_err());
+		assert!(run_command(&mut grid, "", &["add", "x", "1"]).is_err());
+		assert!(run_command(&mut grid, "", &["rm", "2", "2"]).is_err());
+		assert!(run_command(&mut grid, "", &["move", "1", "1"]).is_err());
+		assert_eq!(run_command(&mut grid, "", &["valid"]), Ok(false));
+		assert_eq!(grid.tower_count(), 1);
+	}
+}
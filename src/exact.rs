@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::grid::Grid;
+use crate::point::Point;
+use crate::solvers;
+
+/// Exhaustive search over tower placements, keeping the best valid one found.
+struct BranchAndBound {
+	grid:         Grid,
+	// Mapping from <coordinates of cities, towers that would cover it>.
+	candidates:   HashMap<Point, Vec<Point>>,
+	// Towers excluded from the current branch, since the branches that place
+	// them have already been searched.
+	forbidden:    HashSet<Point>,
+	best_penalty: f64,
+	best_towers:  Option<Vec<Point>>,
+	nodes:        u64,
+	// Whether the search was interrupted before it could prove optimality.
+	stopped:      bool,
+}
+
+impl BranchAndBound {
+	fn new(grid: Grid, best_penalty: f64) -> Self {
+		let mut candidates = HashMap::new();
+		for c in grid.cities() {
+			let mut towers: Vec<Point> =
+				Point::points_within_radius(c, grid.service_radius(), grid.dimension(), grid.metric(), grid.wrap())
+					.unwrap()
+					.iter()
+					.copied()
+					.collect();
+			towers.push(c);
+			towers.sort();
+			candidates.insert(c, towers);
+		}
+
+		BranchAndBound {
+			grid,
+			candidates,
+			forbidden: HashSet::new(),
+			best_penalty,
+			best_towers: None,
+			nodes: 0,
+			stopped: false,
+		}
+	}
+
+	/// Searches every completion of the current towers. Since adding a tower
+	/// never lowers the penalty, the best solution is a cover with no redundant
+	/// towers, so it is enough to branch on which tower covers an uncovered
+	/// city. Returns early, keeping the best solution found so far, once the
+	/// solvers are asked to stop.
+	fn search(&mut self) {
+		if self.stopped || solvers::stop_requested() {
+			self.stopped = true;
+			return;
+		}
+		self.nodes += 1;
+		let penalty = self.grid.penalty();
+		let uncovered = self.grid.get_uncovered_cities();
+		if uncovered.is_empty() {
+			if penalty < self.best_penalty {
+				self.best_penalty = penalty;
+				self.best_towers = Some(self.grid.towers().collect());
+			}
+			return;
+		}
+
+		let mut options: Vec<(Point, Vec<(Point, f64)>)> = Vec::new();
+		for c in uncovered {
+			let allowed = self.allowed_candidates(c, penalty);
+			if allowed.is_empty() {
+				return; // c can't be covered in this branch
+			}
+			options.push((c, allowed));
+		}
+		if penalty + Self::added_penalty_bound(&mut options) >= self.best_penalty {
+			return;
+		}
+
+		// Branch on the city with the fewest towers left that could cover it
+		let (_, branches) = options
+			.into_iter()
+			.min_by(|a, b| a.1.len().cmp(&b.1.len()).then(a.0.cmp(&b.0)))
+			.unwrap();
+
+		let mut newly_forbidden = Vec::new();
+		for (t, _) in branches {
+			self.grid.add_tower(t.x, t.y);
+			self.search();
+			self.grid.remove_tower(t.x, t.y);
+
+			self.forbidden.insert(t);
+			newly_forbidden.push(t);
+		}
+		for t in newly_forbidden {
+			self.forbidden.remove(&t);
+		}
+	}
+
+	/// Returns the towers that could still cover the city in this branch, with
+	/// how much each would add to the current penalty, cheapest first.
+	fn allowed_candidates(&self, c: Point, penalty: f64) -> Vec<(Point, f64)> {
+		let mut options: Vec<(Point, f64)> = self.candidates[&c]
+			.iter()
+			.filter(|t| !self.forbidden.contains(t))
+			.map(|&t| (t, self.grid.penalty_if_added(t) - penalty))
+			.collect();
+		options.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+		options
+	}
+
+	/// Lower bound on how much covering the remaining cities adds to the
+	/// penalty, given each uncovered city's allowed towers (cheapest first).
+	///
+	/// Cities with pairwise disjoint allowed towers each need their own tower.
+	/// Each of those towers adds at least its cost on the current grid, since
+	/// placing more towers only makes later towers more expensive. The packing is
+	/// a feasible solution of the dual of the set cover LP relaxation, so this
+	/// bound is never stronger than the relaxation's optimum, and can be weaker
+	/// when cities share towers, but it costs no LP solve per node.
+	fn added_penalty_bound(options: &mut [(Point, Vec<(Point, f64)>)]) -> f64 {
+		// Pack the cities that are most expensive to cover first
+		options.sort_by(|a, b| b.1[0].1.total_cmp(&a.1[0].1).then(a.0.cmp(&b.0)));
+
+		let mut used: HashSet<Point> = HashSet::new();
+		let mut bound = 0.;
+		for (_, allowed) in options.iter() {
+			if allowed.iter().all(|(t, _)| !used.contains(t)) {
+				used.extend(allowed.iter().map(|(t, _)| *t));
+				bound += allowed[0].1;
+			}
+		}
+		bound
+	}
+}
+
+/// Finds a minimum penalty solution of the grid by branch and bound, starting
+/// from the existing solution (if any) as the incumbent. Leaves the best
+/// solution on the grid and writes it out if it beats the existing one. If
+/// asked to stop, the best solution found so far is used instead.
+pub fn run(grid: &mut Grid, output_path: &str) -> io::Result<()> {
+	grid.remove_all_towers();
+
+	let mut best_penalty = f64::INFINITY;
+	let mut best_towers: Vec<Point> = Vec::new();
+	if Path::new(output_path).is_file() {
+		let mut existing = grid.clone();
+		if let Err(e) = existing.apply_solution(output_path) {
+			warn!("Ignoring existing output: {}", e);
+		} else if existing.is_valid() {
+			best_penalty = existing.penalty();
+			best_towers = existing.towers().collect();
+		}
+	}
+
+	let mut bnb = BranchAndBound::new(grid.clone(), best_penalty);
+	bnb.search();
+	let improved = bnb.best_towers.is_some();
+	if let Some(towers) = bnb.best_towers {
+		best_towers = towers;
+	}
+
+	for t in best_towers {
+		grid.add_tower(t.x, t.y);
+	}
+	if bnb.stopped {
+		info!(
+			"Stopped after {} nodes, best penalty found {}",
+			bnb.nodes,
+			grid.penalty()
+		);
+	} else {
+		info!(
+			"Optimal penalty {} ({} nodes searched{})",
+			grid.penalty(),
+			bnb.nodes,
+			if improved { "" } else { ", existing solution is optimal" }
+		);
+	}
+	if grid.is_valid() {
+		grid.write_solution(output_path, false)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use rand::rngs::StdRng;
+	use rand::SeedableRng;
+
+	use super::*;
+	use crate::grid::tests::{brute_force_optimum, random_grid, temp_path};
+	use crate::solvers::{Exact, SolveError, Solver, SolverConfig};
+
+	#[test]
+	fn matches_brute_force() {
+		let mut rng = StdRng::seed_from_u64(1269);
+		for i in 0..6 {
+			let mut grid = random_grid(&mut rng, 4, 1, 2, 3);
+			let optimum = brute_force_optimum(&grid);
+			let path = temp_path(&format!("exact-{}.out", i));
+			run(&mut grid, &path).unwrap();
+			fs::remove_file(&path).unwrap();
+
+			assert!(grid.is_valid());
+			assert_eq!(grid.penalty(), optimum);
+		}
+	}
+
+	#[test]
+	fn refuses_large_grids() {
+		let path = temp_path("exact-large.out");
+		fs::write(&path, "existing").unwrap();
+		let exact = Exact { max_dimension: 3 };
+		let mut grid = Grid::new(4, 1, 2);
+		grid.add_city(1, 1);
+
+		let result = exact.solve(&mut grid, &path, &SolverConfig::default());
+		assert!(matches!(result, Err(SolveError::Other(_))));
+		assert_eq!(fs::read_to_string(&path).unwrap(), "existing");
+		fs::remove_file(&path).unwrap();
+	}
+}
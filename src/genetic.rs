@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use log::{debug, info, warn};
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+
+use crate::grid::Grid;
+use crate::point::Point;
+use crate::solvers::stop_requested;
+
+// Fraction of the initial population seeded from randomized LP solutions; the
+// rest are random covers.
+const LP_SEED_FRACTION: f64 = 0.25;
+// Max time in seconds of each seeding LP solve
+const LP_SEED_TIME: u32 = 1;
+// Added to the penalty for each uncovered city, so invalid individuals lose
+const INVALID_CITY_PENALTY: f64 = 1e9;
+// Number of individuals competing to be each parent
+const TOURNAMENT_SIZE: usize = 3;
+
+/// Parameters of the genetic algorithm.
+pub struct Params {
+	pub population_size: usize,
+	pub generations:     usize,
+	// Chance that a child is mutated
+	pub mutation_rate:   f64,
+}
+
+/// A set of towers, along with its fitness.
+#[derive(Clone)]
+struct Individual {
+	towers:  HashSet<Point>,
+	fitness: f64,
+}
+
+impl Individual {
+	/// Places the towers on a copy of the (tower free) base grid to score them.
+	fn new(base: &Grid, towers: HashSet<Point>) -> Self {
+		let grid = with_towers(base, &towers);
+		let fitness = grid.penalty() + INVALID_CITY_PENALTY * grid.get_uncovered_cities().len() as f64;
+		Individual { towers, fitness }
+	}
+}
+
+/// Returns a copy of the base grid with the given towers placed.
+fn with_towers(base: &Grid, towers: &HashSet<Point>) -> Grid {
+	let mut grid = base.clone();
+	for t in towers {
+		grid.add_tower(t.x, t.y);
+	}
+	grid
+}
+
+/// Adds towers near uncovered cities until every city is covered.
+fn repair(base: &Grid, towers: HashSet<Point>, rng: &mut impl Rng) -> HashSet<Point> {
+	let mut grid = with_towers(base, &towers);
+	grid.cover_remaining_greedily(rng);
+	grid.towers().collect()
+}
+
+/// Keeps the towers both parents share, and each other tower with even odds.
+fn crossover(a: &Individual, b: &Individual, rng: &mut impl Rng) -> HashSet<Point> {
+	let mut child: HashSet<Point> = a.towers.intersection(&b.towers).copied().collect();
+	let mut others: Vec<Point> = a.towers.symmetric_difference(&b.towers).copied().collect();
+	others.sort(); // so the result only depends on the rng
+	for t in others {
+		if rng.gen_bool(0.5) {
+			child.insert(t);
+		}
+	}
+	child
+}
+
+/// Moves, adds, or removes a random tower.
+fn mutate(base: &Grid, towers: &mut HashSet<Point>, rng: &mut impl Rng) {
+	let mut sorted: Vec<Point> = towers.iter().copied().collect();
+	sorted.sort();
+	let random_point = |rng: &mut _| {
+		let dim = base.dimension() as i32;
+		Point::new(Rng::gen_range(rng, 0..dim), Rng::gen_range(rng, 0..dim))
+	};
+
+	match rng.gen_range(0..3) {
+		// Move a tower within the service radius
+		0 if !sorted.is_empty() => {
+			let t = *sorted.choose(rng).unwrap();
+			let neighbors =
+				Point::points_within_radius(t, base.service_radius(), base.dimension(), base.metric(), base.wrap()).unwrap();
+			let mut neighbors: Vec<&Point> = neighbors.iter().collect();
+			neighbors.sort();
+			if let Some(&&q) = neighbors.iter().filter(|q| !towers.contains(q)).choose(rng) {
+				towers.remove(&t);
+				towers.insert(q);
+			}
+		}
+		// Remove a tower
+		1 if !sorted.is_empty() => {
+			towers.remove(sorted.choose(rng).unwrap());
+		}
+		// Add a tower
+		_ if base.dimension() > 0 => {
+			towers.insert(random_point(rng));
+		}
+		_ => {}
+	}
+}
+
+/// Returns the fittest of a few random individuals.
+fn select<'a>(population: &'a [Individual], rng: &mut impl Rng) -> &'a Individual {
+	population
+		.choose_multiple(rng, TOURNAMENT_SIZE)
+		.min_by(|a, b| a.fitness.total_cmp(&b.fitness))
+		.unwrap()
+}
+
+/// Evolves a population of tower sets, seeded from the existing solution (if
+/// any), randomized LP solutions, and random covers. Leaves the best solution
+/// on the grid and writes it out if it beats the existing one.
+pub fn run(grid: &mut Grid, output_path: &str, params: &Params, rng: &mut impl Rng) -> io::Result<()> {
+	grid.remove_all_towers();
+	let base = grid.clone();
+	let population_size = params.population_size.max(1);
+
+	let mut population: Vec<Individual> = Vec::with_capacity(population_size);
+	if Path::new(output_path).is_file() {
+		match Grid::towers_from_file(output_path) {
+			Ok(towers) => population.push(Individual::new(&base, towers)),
+			Err(e) => warn!("Ignoring existing output: {}", e),
+		}
+	}
+	let num_lp_seeds = ((population_size as f64 * LP_SEED_FRACTION) as usize).max(1);
+	for _ in 0..num_lp_seeds.min(population_size - population.len()) {
+		let mut seeded = base.clone();
+		// The rest of the population is seeded randomly anyway
+		if let Err(e) = seeded.random_lp_solve(LP_SEED_TIME, rng.gen_range(1..=u32::MAX), &[], None) {
+			warn!("LP seeding failed, {}", e);
+			break;
+		}
+		population.push(Individual::new(&base, seeded.towers().collect()));
+	}
+	while population.len() < population_size {
+		let towers = repair(&base, HashSet::new(), rng);
+		population.push(Individual::new(&base, towers));
+	}
+
+	let best_of = |population: &[Individual]| {
+		population
+			.iter()
+			.min_by(|a, b| a.fitness.total_cmp(&b.fitness))
+			.unwrap()
+			.clone()
+	};
+	let mut best = best_of(&population);
+	debug!("Initial best: {}", best.fitness);
+
+	for generation in 0..params.generations {
+		if stop_requested() {
+			break;
+		}
+		// Always keep the best individual
+		let mut next: Vec<Individual> = vec![best.clone()];
+		while next.len() < population_size {
+			let mut child = crossover(select(&population, rng), select(&population, rng), rng);
+			if rng.gen_bool(params.mutation_rate.clamp(0., 1.)) {
+				mutate(&base, &mut child, rng);
+			}
+			let child = repair(&base, child, rng);
+			next.push(Individual::new(&base, child));
+		}
+		population = next;
+
+		let generation_best = best_of(&population);
+		if generation_best.fitness < best.fitness {
+			debug!(
+				"Improvement in generation {}: {} -> {}",
+				generation, best.fitness, generation_best.fitness
+			);
+			best = generation_best;
+		}
+	}
+
+	for t in best.towers {
+		grid.add_tower(t.x, t.y);
+	}
+	info!("Best: {}", grid.penalty());
+	if grid.is_valid() {
+		grid.write_solution(output_path, false)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use rand::rngs::StdRng;
+	use rand::SeedableRng;
+
+	use super::*;
+	use crate::grid::tests::{random_grid, temp_path};
+
+	#[test]
+	fn run_is_no_worse_than_the_seeded_solution() {
+		let mut rng = StdRng::seed_from_u64(1271);
+		for i in 0..3 {
+			let mut grid = random_grid(&mut rng, 12, 2, 4, 15);
+			let mut seed = grid.clone();
+			seed.random_valid_solution(&mut rng);
+			let path = temp_path(&format!("genetic-{}.out", i));
+			seed.write_solution(&path, true).unwrap();
+
+			let params = Params {
+				population_size: 8,
+				generations:     10,
+				mutation_rate:   0.5,
+			};
+			run(&mut grid, &path, &params, &mut rng).unwrap();
+			let mut written = seed.clone();
+			written.remove_all_towers();
+			written.apply_solution(&path).unwrap();
+			fs::remove_file(&path).unwrap();
+
+			assert!(grid.is_valid());
+			assert!(written.is_valid());
+			assert_eq!(written.penalty(), grid.penalty());
+			assert!(grid.penalty() <= seed.penalty());
+		}
+	}
+
+	#[test]
+	fn crossover_and_repair_always_cover_every_city() {
+		let mut rng = StdRng::seed_from_u64(1271);
+		let base = random_grid(&mut rng, 12, 2, 4, 20);
+		let mut population: Vec<Individual> = (0..6)
+			.map(|_| Individual::new(&base, repair(&base, HashSet::new(), &mut rng)))
+			.collect();
+		// A parent that covers nothing
+		population.push(Individual::new(&base, HashSet::new()));
+
+		for _ in 0..50 {
+			let mut child = crossover(
+				population.choose(&mut rng).unwrap(),
+				population.choose(&mut rng).unwrap(),
+				&mut rng,
+			);
+			if rng.gen_bool(0.5) {
+				mutate(&base, &mut child, &mut rng);
+			}
+			let child = repair(&base, child, &mut rng);
+			assert!(with_towers(&base, &child).is_valid());
+		}
+	}
+}
@@ -1,14 +1,57 @@
 use std::collections::{HashMap, HashSet};
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::{fmt, io};
 
+use fixedbitset::FixedBitSet;
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use good_lp::ResolutionError;
+use log::warn;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
 use crate::api;
-use crate::lp::GridProblem;
-use crate::point::Point;
+use crate::lp::{GridProblem, LpStatus};
+use crate::point::{Metric, Point, PointMap, PointSet};
+
+// Suffix of the next temporary file write_output writes to.
+static NEXT_TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+// Held by write_solution from reading the existing solution until its own is
+// in place, so concurrent writers (e.g. the threads of rlp and rand_hillclimb)
+// can't rename a worse solution over a better one written in between.
+static WRITE_SOLUTION_LOCK: Mutex<()> = Mutex::new(());
+
+/// Opens the file at path for reading, decompressing it if it ends in .gz.
+pub fn open_file(path: &str) -> io::Result<Box<dyn BufRead>> {
+	let reader = BufReader::new(File::open(path)?);
+	if is_gzip_path(path) {
+		Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+	} else {
+		Ok(Box::new(reader))
+	}
+}
+
+/// Returns whether the file at path is (to be) gzip compressed.
+pub fn is_gzip_path(path: &str) -> bool {
+	Path::new(path).extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Returns the name of the input file at path without its .in or .in.gz
+/// extension, e.g. 001 for inputs/small/001.in.gz, or None if it isn't an
+/// input file.
+pub fn input_stem(path: &Path) -> Option<&str> {
+	let file_name = path.file_name()?.to_str()?;
+	file_name
+		.strip_suffix(".in")
+		.or_else(|| file_name.strip_suffix(".in.gz"))
+}
 
 // A Grid which we place towers and cities on.
 #[derive(Clone, Serialize, Deserialize)]
@@ -17,13 +60,201 @@ pub struct Grid {
 	service_radius: u8,
 	penalty_radius: u8,
 
+	// How distance is measured for both radii.
+	#[serde(default)]
+	metric: Metric,
+
+	// Whether the grid is a torus, so both radii wrap around the edges.
+	#[serde(default)]
+	wrap: bool,
+
 	// Mapping from <coordinates of towers, coordinates of other towers within penalty radius>.
 	// i.e. < (2, 3), {(5, 6), (7, 8)} >
-	towers: HashMap<Point, HashSet<Point>>,
+	towers: PointMap<PointSet>,
 
 	// Mapping from <coordinates of cities, towers that cover it>.
 	// i.e. < (4, 4), {(1, 2), (3, 4)} >
-	cities: HashMap<Point, HashSet<Point>>,
+	cities: PointMap<PointSet>,
+
+	// Running sum of e^(0.17 * w_j) over all towers j, kept up to date as towers
+	// are added and removed so the penalty doesn't need to be recomputed.
+	penalty_sum: f64,
+
+	// Number of cities no tower covers, kept up to date like penalty_sum so
+	// is_valid doesn't need to scan every city.
+	uncovered: usize,
+
+	// Whether each cell holds a tower, indexed by y * dimension + x, so adding
+	// or removing a tower only scans its neighborhoods, and only hashes the
+	// points in them that hold something.
+	tower_cells: Vec<bool>,
+
+	// City positions, which don't change once towers are placed, shared between
+	// clones so cloning only copies the tower state and coverage sets.
+	city_data: Arc<CityData>,
+}
+
+/// The parts of a Grid that only depend on its cities. Grids only write to it
+/// while adding cities (or building the city index), so clones share it.
+#[derive(Clone, Serialize, Deserialize)]
+struct CityData {
+	// Whether each cell holds a city, indexed like Grid::tower_cells.
+	cells: Vec<bool>,
+
+	// Mapping from <coordinates of cities, bit index of that city>, used for the
+	// bitset coverage representation. Empty until build_city_index is called.
+	#[serde(skip)]
+	index: HashMap<Point, usize>,
+}
+
+/// Snapshot of a Grid's coverage and penalty metrics, see Grid::report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoverageReport {
+	pub num_towers:           usize,
+	pub num_cities:           usize,
+	pub penalty:              f64,
+	// Fraction of cities covered by at least one tower (1 if there are none).
+	pub covered_fraction:     f64,
+	// Towers that are not the only tower covering any city.
+	pub num_redundant_towers: usize,
+	// Number of coverings beyond the first, summed over all cities.
+	pub coverage_surplus:     usize,
+	// Smallest uncovered city, if any.
+	pub first_uncovered_city: Option<Point>,
+}
+
+/// What Grid::write_solution did with the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+	// There was no solution yet, or ours is strictly better.
+	Written,
+	// The existing solution is identical or has the same penalty, so it was kept.
+	Unchanged,
+	// The existing solution is strictly better, so it was kept.
+	Worse,
+}
+
+/// Why a tower couldn't be added, removed or moved, see Grid::try_add_tower.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridError {
+	OffGrid(Point),
+	// There is already a tower at the point.
+	TowerPresent(Point),
+	// There is no tower at the point.
+	NoTower(Point),
+}
+
+impl fmt::Display for GridError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			GridError::OffGrid(p) => write!(f, "{} is off the grid", p),
+			GridError::TowerPresent(p) => write!(f, "there is already a tower at {}", p),
+			GridError::NoTower(p) => write!(f, "there is no tower at {}", p),
+		}
+	}
+}
+
+impl std::error::Error for GridError {}
+
+/// The towers of a Grid at some point, to restore it to later. See
+/// Grid::checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GridSnapshot {
+	towers: HashSet<Point>,
+}
+
+impl GridSnapshot {
+	pub fn towers(&self) -> &HashSet<Point> {
+		&self.towers
+	}
+}
+
+/// JSON form of a Grid, see Grid::to_json. Only the radii, distance settings,
+/// cities and towers are read back by Grid::from_json; the rest is derived
+/// from them, and is included for tooling that wants the full state.
+#[derive(Serialize, Deserialize)]
+struct GridJson {
+	dimension:      u8,
+	service_radius: u8,
+	penalty_radius: u8,
+	#[serde(default)]
+	metric:         Metric,
+	#[serde(default)]
+	wrap:           bool,
+	#[serde(default)]
+	penalty:        f64,
+	#[serde(default)]
+	valid:          bool,
+	cities:         Vec<CityJson>,
+	#[serde(default)]
+	towers:         Vec<TowerJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CityJson {
+	city:       Point,
+	// Towers covering the city.
+	#[serde(default)]
+	covered_by: Vec<Point>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TowerJson {
+	tower:        Point,
+	// Other towers within the penalty radius, i.e. w_j.
+	#[serde(default)]
+	penalized_by: Vec<Point>,
+}
+
+/// Why Grid::from_file couldn't read an input file.
+#[derive(Debug)]
+pub enum GridParseError {
+	Io(io::Error),
+	// Something is wrong with the given (1-indexed) line of the file.
+	Line { line: usize, kind: LineErrorKind },
+	// The file ended before the header (num_cities, dimension, radii) was read.
+	MissingHeader,
+	// The header promised a different number of cities than the file has.
+	CityCountMismatch { expected: usize, found: usize },
+}
+
+#[derive(Debug)]
+pub enum LineErrorKind {
+	// A header value that isn't a valid number.
+	InvalidNumber(String),
+	// A city line that isn't "x y".
+	InvalidPoint(String),
+	OffGrid(Point),
+	DuplicateCity(Point),
+}
+
+impl fmt::Display for GridParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			GridParseError::Io(e) => write!(f, "{}", e),
+			GridParseError::Line { line, kind } => {
+				write!(f, "line {}: ", line)?;
+				match kind {
+					LineErrorKind::InvalidNumber(s) => write!(f, "invalid number {:?}", s),
+					LineErrorKind::InvalidPoint(e) => write!(f, "{}", e),
+					LineErrorKind::OffGrid(p) => write!(f, "city {} is off the grid", p),
+					LineErrorKind::DuplicateCity(p) => write!(f, "duplicate city {}", p),
+				}
+			}
+			GridParseError::MissingHeader => write!(f, "file ended before the header was complete"),
+			GridParseError::CityCountMismatch { expected, found } => {
+				write!(f, "expected {} cities but found {}", expected, found)
+			}
+		}
+	}
+}
+
+impl std::error::Error for GridParseError {}
+
+impl From<io::Error> for GridParseError {
+	fn from(e: io::Error) -> Self {
+		GridParseError::Io(e)
+	}
 }
 
 impl fmt::Debug for Grid {
@@ -61,15 +292,83 @@ impl fmt::Debug for Grid {
 	}
 }
 
-/// Pretty printer for Grid.
+/// Pretty printer for Grid: t is a tower, c a city, ¢ a tower on a city, and ·
+/// an empty cell. (0, 0) is at the bottom left; the alternate form ({:#})
+/// labels every few rows and columns with their coordinate.
 impl fmt::Display for Grid {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let axes = f.alternate();
+		let max = self.dimension as i32 - 1;
 		write!(f, "Penalty: {}\n", self.penalty())?;
-		for y in (0..self.dimension).rev() {
-			for x in 0..self.dimension {
-				let p = Point::new(x as i32, y as i32);
+		self.write_region(f, (0, 0), (max, max), axes)
+	}
+}
+
+/// Grids are equal if they have the same configuration, cities and towers.
+/// Everything else (coverage, penalty, the city index) is derived from those,
+/// so it is not compared.
+impl PartialEq for Grid {
+	fn eq(&self, other: &Self) -> bool {
+		self.dimension == other.dimension
+			&& self.service_radius == other.service_radius
+			&& self.penalty_radius == other.penalty_radius
+			&& self.metric == other.metric
+			&& self.wrap == other.wrap
+			&& self.cities.len() == other.cities.len()
+			&& self.cities.keys().all(|c| other.cities.contains_key(c))
+			&& self.towers.len() == other.towers.len()
+			&& self.towers.keys().all(|t| other.towers.contains_key(t))
+	}
+}
+
+impl Eq for Grid {}
+
+impl Grid {
+	/// Returns the cells with x0 <= x <= x1 and y0 <= y <= y1 like Display, with
+	/// coordinate labels, to zoom in on part of a large grid. The window is
+	/// clamped to the grid.
+	pub fn display_region(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> String {
+		let mut s = String::new();
+		// Writing to a String can't fail
+		self.write_region(&mut s, (x0, y0), (x1, y1), true).unwrap();
+		s
+	}
+
+	fn write_region(&self, f: &mut impl fmt::Write, from: (i32, i32), to: (i32, i32), axes: bool) -> fmt::Result {
+		let max = self.dimension as i32 - 1;
+		let (x0, y0) = (from.0.max(0), from.1.max(0));
+		let (x1, y1) = (to.0.min(max), to.1.min(max));
+		if x0 > x1 || y0 > y1 {
+			return Ok(());
+		}
+		// Label every 5th row and column, or every 10th on wide windows
+		let step = if (x1 - x0).max(y1 - y0) >= 50 { 10 } else { 5 };
+
+		if axes {
+			// Each cell is two characters wide, after a four character row label
+			let mut header = String::from("    ");
+			for x in (x0..=x1).filter(|x| x % step == 0) {
+				let col = 4 + 2 * (x - x0) as usize;
+				while header.len() < col {
+					header.push(' ');
+				}
+				header += &x.to_string();
+			}
+			writeln!(f, "{}", header.trim_end())?;
+		}
+
+		for y in (y0..=y1).rev() {
+			if axes {
+				if y % step == 0 {
+					write!(f, "{:>3} ", y)?;
+				} else {
+					write!(f, "    ")?;
+				}
+			}
+			for x in x0..=x1 {
+				let p = Point::new(x, y);
 				if self.towers.contains_key(&p) && self.cities.contains_key(&p) {
-					// write!(f, "¢"); // city and tower at same point
+					write!(f, "¢")?; // city and tower at same point
 				} else if self.towers.contains_key(&p) {
 					write!(f, "t")?; // tower at this point
 				} else if self.cities.contains_key(&p) {
@@ -79,13 +378,11 @@ impl fmt::Display for Grid {
 				}
 				write!(f, " ")?;
 			}
-			write!(f, "\n")?;
+			writeln!(f)?;
 		}
 		Ok(())
 	}
-}
 
-impl Grid {
 	/// Creates and returns a new Grid of the given dimension, service_radius, and
 	/// penalty radius.
 	pub fn new(dimension: u8, service_radius: u8, penalty_radius: u8) -> Self {
@@ -93,17 +390,23 @@ impl Grid {
 			dimension,
 			service_radius,
 			penalty_radius,
-			towers: HashMap::new(),
-			cities: HashMap::new(),
+			metric: Metric::default(),
+			wrap: false,
+			towers: PointMap::default(),
+			cities: PointMap::default(),
+			penalty_sum: 0.,
+			uncovered: 0,
+			tower_cells: vec![false; dimension as usize * dimension as usize],
+			city_data: Arc::new(CityData {
+				cells: vec![false; dimension as usize * dimension as usize],
+				index: HashMap::new(),
+			}),
 		}
 	}
 
-	/// Deeply clone the grid
-	pub fn clone(&self) -> Self {
-		let mut new_grid = Grid::new(self.dimension, self.service_radius, self.penalty_radius);
-		new_grid.towers = self.towers.clone();
-		new_grid.cities = self.cities.clone();
-		new_grid
+	/// Returns the index of p in the dense cell arrays.
+	fn cell(&self, p: Point) -> usize {
+		p.y as usize * self.dimension as usize + p.x as usize
 	}
 
 	pub fn new_dummy_grid() -> Grid {
@@ -112,17 +415,179 @@ impl Grid {
 
 	/// Returns the total penalty P of this Grid.
 	pub fn penalty(&self) -> f64 {
+		let penalty = api::round(170.0 * self.penalty_sum);
+		if cfg!(debug_assertions) {
+			let actual = self.penalty_from_scratch();
+			assert!(
+				(penalty - actual).abs() < 1e-6,
+				"Cached penalty {} diverged from actual penalty {}",
+				penalty,
+				actual
+			);
+		}
+		penalty
+	}
+
+	/// Returns the total penalty P of this Grid, recomputed from every tower's
+	/// w_j rather than the running sum.
+	fn penalty_from_scratch(&self) -> f64 {
 		let mut penalty = 0.0;
 		for penalized in self.towers.values() {
-			let w_j = penalized.len() as f64;
-			penalty += (0.17 * w_j).exp();
+			penalty += Grid::tower_cost(penalized.len());
 		}
 		api::round(170.0 * penalty)
 	}
 
+	/// Returns the total penalty this Grid would have if a tower were added at
+	/// p, without modifying the Grid.
+	pub fn penalty_if_added(&self, p: Point) -> f64 {
+		assert!(!self.towers.contains_key(&p), "There is already a tower at {:?}.", p);
+		let penalized =
+			Point::points_within_radius(p, self.penalty_radius, self.dimension, self.metric, self.wrap).unwrap();
+
+		let mut sum = self.penalty_sum;
+		let mut w_p = 0;
+		for tower in penalized {
+			if self.tower_cells[self.cell(*tower)] {
+				let others = &self.towers[tower];
+				sum += Grid::tower_cost(others.len() + 1) - Grid::tower_cost(others.len());
+				w_p += 1;
+			}
+		}
+		sum += Grid::tower_cost(w_p);
+		api::round(170.0 * sum)
+	}
+
+	/// Returns how many currently uncovered cities a tower at p would cover.
+	/// A tower already at p covers nothing new.
+	pub fn coverage_gain(&self, p: Point) -> u32 {
+		let coverage = Point::points_within_radius(p, self.service_radius, self.dimension, self.metric, self.wrap).unwrap();
+		coverage
+			.iter()
+			.chain(std::iter::once(&p))
+			.filter(|&&c| self.city_data.cells[self.cell(c)] && self.cities[&c].is_empty())
+			.count() as u32
+	}
+
+	/// Returns the total penalty this Grid would have if the tower at p were
+	/// removed, without modifying the Grid.
+	pub fn penalty_if_removed(&self, p: Point) -> f64 {
+		let adj_towers = self
+			.towers
+			.get(&p)
+			.unwrap_or_else(|| panic!("There is no tower at {:?}.", p));
+
+		let mut sum = self.penalty_sum - Grid::tower_cost(adj_towers.len());
+		for tower in adj_towers {
+			let w = self.towers[tower].len();
+			sum += Grid::tower_cost(w - 1) - Grid::tower_cost(w);
+		}
+		api::round(170.0 * sum)
+	}
+
+	/// Returns the total penalty this Grid would have if the tower at p were
+	/// moved to q, without modifying the Grid.
+	pub fn penalty_if_moved(&self, p: Point, q: Point) -> f64 {
+		let adj_towers = self
+			.towers
+			.get(&p)
+			.unwrap_or_else(|| panic!("There is no tower at {:?}.", p));
+		assert!(!self.towers.contains_key(&q), "There is already a tower at {:?}.", q);
+
+		// Take the tower off p
+		let mut sum = self.penalty_sum - Grid::tower_cost(adj_towers.len());
+		for tower in adj_towers {
+			let w = self.towers[tower].len();
+			sum += Grid::tower_cost(w - 1) - Grid::tower_cost(w);
+		}
+
+		// and put it on q, counting the other towers' w_j without p
+		let penalized =
+			Point::points_within_radius(q, self.penalty_radius, self.dimension, self.metric, self.wrap).unwrap();
+		let mut w_q = 0;
+		for tower in penalized {
+			if *tower == p || !self.tower_cells[self.cell(*tower)] {
+				continue;
+			}
+			let others = &self.towers[tower];
+			let w = others.len() - others.contains(&p) as usize;
+			sum += Grid::tower_cost(w + 1) - Grid::tower_cost(w);
+			w_q += 1;
+		}
+		sum += Grid::tower_cost(w_q);
+		api::round(170.0 * sum)
+	}
+
+	/// Returns e^(0.17 * w_j), the (unscaled) penalty of a tower with w_j other
+	/// towers in its penalty radius.
+	pub fn tower_cost(w_j: usize) -> f64 {
+		(0.17 * w_j as f64).exp()
+	}
+
+	/// Returns each tower with its w_j (the number of other towers within its
+	/// penalty radius) and what it adds to the penalty, 170 * e^(0.17 * w_j),
+	/// most expensive first, so the worst clusters stand out.
+	pub fn penalty_breakdown(&self) -> Vec<(Point, usize, f64)> {
+		let mut breakdown: Vec<(Point, usize, f64)> = self
+			.towers
+			.iter()
+			.map(|(&t, penalized)| (t, penalized.len(), 170. * Grid::tower_cost(penalized.len())))
+			.collect();
+		breakdown.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+		breakdown
+	}
+
+	/// Returns a lower bound on the penalty of any valid solution of this Grid.
+	/// Every tower costs at least 170 (when no other tower is within its
+	/// penalty radius), so this is 170 times a lower bound on the number of
+	/// towers: the optimum of the LP relaxation of covering every city with as
+	/// few towers as possible, or the number of cities over the most cities one
+	/// tower can cover, whichever is larger.
+	pub fn penalty_lower_bound(&self) -> f64 {
+		// Seconds to spend solving the relaxation
+		const LP_TIME: u32 = 60;
+		if self.cities.is_empty() {
+			return 0.;
+		}
+
+		// A tower covers its own cell as well as the points within its radius
+		let center = Point::new(self.dimension as i32 / 2, self.dimension as i32 / 2);
+		let max_covered = Point::points_within_radius(center, self.service_radius, self.dimension, self.metric, self.wrap)
+			.unwrap()
+			.len()
+			+ 1;
+		let mut num_towers = self.cities.len() as f64 / max_covered as f64;
+
+		let cities = self.cities.keys().copied().collect();
+		match GridProblem::new_relaxed_cover(
+			self.dimension,
+			self.service_radius,
+			self.metric,
+			self.wrap,
+			cities,
+			LP_TIME,
+		)
+		.objective_value()
+		{
+			Ok(relaxed) => num_towers = num_towers.max(relaxed),
+			Err(e) => warn!("Couldn't solve the relaxed LP, using a weaker bound: {}", e),
+		}
+		// The number of towers is an integer, up to solver tolerance
+		api::round(170. * Grid::tower_cost(0) * (num_towers - 1e-6).ceil())
+	}
+
 	/// Returns whether the towers in this Grid cover all cities.
 	pub fn is_valid(&self) -> bool {
-		self.cities.values().all(|c| c.len() > 0)
+		if cfg!(debug_assertions) {
+			let actual = self.cities.values().filter(|ts| ts.is_empty()).count();
+			assert!(
+				self.uncovered == actual,
+				"Cached uncovered count {} diverged from actual count {}",
+				self.uncovered,
+				actual
+			);
+		}
+		self.uncovered == 0
 	}
 
 	/// Adds a city at (x, y) to this Grid, if it does not already exist.
@@ -136,82 +601,143 @@ impl Grid {
 			"Cannot add city at {:?} because it already exists.",
 			c
 		);
-		self.cities.insert(c, HashSet::new());
+		self.cities.insert(c, PointSet::default());
+		self.uncovered += 1;
+		let cell = self.cell(c);
+		let city_data = Arc::make_mut(&mut self.city_data);
+		city_data.cells[cell] = true;
+		city_data.index.clear(); // stale once the city set changes
 	}
 
-	/// Adds a tower at (x, y) to this Grid, if it does not already exist.
+	/// Adds a tower at (x, y) to this Grid. Panics if there already is one or
+	/// (x, y) is off the grid, see try_add_tower.
 	pub fn add_tower(&mut self, x: i32, y: i32) {
-		self.check_coordinates(x, y);
+		if let Err(e) = self.try_add_tower(x, y) {
+			panic!("Cannot add tower: {}", e);
+		}
+	}
+
+	/// Adds a tower at (x, y) to this Grid, failing without changing it if
+	/// there already is one or (x, y) is off the grid.
+	pub fn try_add_tower(&mut self, x: i32, y: i32) -> Result<(), GridError> {
 		let t: Point = Point::new(x, y);
-		assert!(
-			!self.towers.contains_key(&t),
-			"Cannot add tower at {:?} because it already exists.",
-			t
-		);
+		if !self.is_on_grid(x, y) {
+			return Err(GridError::OffGrid(t));
+		}
+		if self.towers.contains_key(&t) {
+			return Err(GridError::TowerPresent(t));
+		}
 		self.update_towers_add(t); // implicitly adds the tower to the grid
 		self.update_cities_add(t);
+		Ok(())
+	}
+
+	/// Adds each of the given towers that is on the grid and not already
+	/// present, returning why each of the others couldn't be added, in the
+	/// order they were given.
+	pub fn add_towers<I: IntoIterator<Item = Point>>(&mut self, towers: I) -> Vec<GridError> {
+		towers
+			.into_iter()
+			.filter_map(|t| self.try_add_tower(t.x, t.y).err())
+			.collect()
 	}
 
 	/// Used upon adding a tower T.
 	/// Updates the penalized towers for each tower within the penalty radius of
 	/// T.
 	fn update_towers_add(&mut self, p: Point) {
-		let penalized = Point::points_within_radius(p, self.penalty_radius, self.dimension).unwrap();
+		let penalized =
+			Point::points_within_radius(p, self.penalty_radius, self.dimension, self.metric, self.wrap).unwrap();
 
-		let mut adj_towers = HashSet::new();
-		for (&tower, set) in self.towers.iter_mut() {
-			if penalized.contains(&tower) && tower != p {
-				set.insert(p);
-				adj_towers.insert(tower);
+		let mut adj_towers = PointSet::default();
+		for &tower in penalized {
+			if !self.tower_cells[self.cell(tower)] {
+				continue;
 			}
+			let set = self.towers.get_mut(&tower).unwrap();
+			self.penalty_sum += Grid::tower_cost(set.len() + 1) - Grid::tower_cost(set.len());
+			set.insert(p);
+			adj_towers.insert(tower);
 		}
+		self.penalty_sum += Grid::tower_cost(adj_towers.len());
 		self.towers.insert(p, adj_towers);
+		let cell = self.cell(p);
+		self.tower_cells[cell] = true;
 	}
 
 	/// Used upon adding a tower T.
 	/// Adds T to the covering towers for each city within the service radius of
 	/// T.
 	fn update_cities_add(&mut self, t: Point) {
-		let coverage = Point::points_within_radius(t, self.service_radius, self.dimension).unwrap();
+		let coverage = Point::points_within_radius(t, self.service_radius, self.dimension, self.metric, self.wrap).unwrap();
 		// println!("t = {}, \n coverage = {:#?}", t, coverage);
 
-		for (c, ts) in self.cities.iter_mut() {
-			if (c == &t) || (coverage.contains(c) && !ts.contains(&t)) {
+		for &c in coverage.iter().chain(std::iter::once(&t)) {
+			if self.city_data.cells[self.cell(c)] {
+				let ts = self.cities.get_mut(&c).unwrap();
+				if ts.is_empty() {
+					self.uncovered -= 1;
+				}
 				ts.insert(t);
 			}
 		}
 	}
 
-	/// Removes the tower at (x, y) from this Grid, if it exists.
-	/// Also updates the respective tower and city coverage.
+	/// Removes the tower at (x, y) from this Grid, also updating the respective
+	/// tower and city coverage. Panics if there is no tower there, see
+	/// try_remove_tower.
 	pub fn remove_tower(&mut self, x: i32, y: i32) {
-		self.check_coordinates(x, y);
+		if let Err(e) = self.try_remove_tower(x, y) {
+			panic!("Cannot remove tower: {}", e);
+		}
+	}
+
+	/// Removes the tower at (x, y) from this Grid, failing without changing it
+	/// if there is no tower there or (x, y) is off the grid.
+	pub fn try_remove_tower(&mut self, x: i32, y: i32) -> Result<(), GridError> {
 		let p: Point = Point::new(x, y);
-		assert!(
-			self.towers.contains_key(&p),
-			"Cannot remove tower at {:?} because it does not exist.",
-			p
-		);
+		if !self.is_on_grid(x, y) {
+			return Err(GridError::OffGrid(p));
+		}
+		if !self.towers.contains_key(&p) {
+			return Err(GridError::NoTower(p));
+		}
 		self.update_towers_remove(p); // implicitly removes the tower from the grid
 		self.update_cities_remove(p);
+		Ok(())
 	}
 
 	/// Used upon removing a tower T.
 	/// Updates the penalized towers for each tower within the penalty radius of
 	/// T.
 	fn update_towers_remove(&mut self, t: Point) {
-		for (_t, others) in self.towers.iter_mut() {
+		let adj_towers = self.towers.remove(&t).unwrap();
+		let cell = self.cell(t);
+		self.tower_cells[cell] = false;
+		self.penalty_sum -= Grid::tower_cost(adj_towers.len());
+		for tower in adj_towers {
+			let others = self.towers.get_mut(&tower).unwrap();
+			self.penalty_sum += Grid::tower_cost(others.len() - 1) - Grid::tower_cost(others.len());
 			others.remove(&t);
 		}
-		self.towers.remove(&t);
+		// Avoid accumulating floating point drift
+		if self.towers.is_empty() {
+			self.penalty_sum = 0.;
+		}
 	}
 
 	/// Used upon removing a tower T.
 	/// Removes T from the covering towers for each city within the service radius
 	/// of T.
 	fn update_cities_remove(&mut self, t: Point) {
-		for (_c, ts) in self.cities.iter_mut() {
-			ts.remove(&t); // does nothing if called on city uncovered by T
+		let coverage = Point::points_within_radius(t, self.service_radius, self.dimension, self.metric, self.wrap).unwrap();
+		for &c in coverage.iter().chain(std::iter::once(&t)) {
+			if self.city_data.cells[self.cell(c)] {
+				let ts = self.cities.get_mut(&c).unwrap();
+				if ts.remove(&t) && ts.is_empty() {
+					self.uncovered += 1;
+				}
+			}
 		}
 	}
 
@@ -220,37 +746,264 @@ impl Grid {
 		self.towers.contains_key(&p)
 	}
 
-	/// Returns a set of uncovered cities.
+	/// Returns a set of uncovered cities, i.e. those is_valid requires to be
+	/// covered.
 	pub fn get_uncovered_cities(&self) -> HashSet<Point> {
-		let mut uncovered = HashSet::new();
-		for (c, ts) in self.cities.iter() {
-			if ts.len() == 0 {
-				uncovered.insert(*c);
+		self
+			.cities
+			.iter()
+			.filter(|(_, ts)| ts.is_empty())
+			.map(|(&c, _)| c)
+			.collect()
+	}
+
+	/// Returns if the city at c is not covered by any tower.
+	pub fn is_city_uncovered(&self, c: Point) -> bool {
+		self
+			.cities
+			.get(&c)
+			.unwrap_or_else(|| panic!("There is no city at {:?}.", c))
+			.is_empty()
+	}
+
+	/// Assigns each city a bit index (in sorted order) for the bitset coverage
+	/// representation.
+	pub fn build_city_index(&mut self) {
+		let mut sorted_cities: Vec<_> = self.cities.keys().copied().collect();
+		sorted_cities.sort();
+		Arc::make_mut(&mut self.city_data).index = sorted_cities.into_iter().enumerate().map(|(i, c)| (c, i)).collect();
+	}
+
+	/// Returns the set of cities a tower at p would cover, as a bitset over the
+	/// city index. Requires build_city_index to have been called.
+	pub fn tower_coverage_bits(&self, p: Point) -> FixedBitSet {
+		assert!(
+			self.city_data.index.len() == self.cities.len(),
+			"City index not built, call build_city_index first."
+		);
+		let mut bits = FixedBitSet::with_capacity(self.cities.len());
+		let coverage = Point::points_within_radius(p, self.service_radius, self.dimension, self.metric, self.wrap).unwrap();
+		for c in coverage.iter().chain(std::iter::once(&p)) {
+			if let Some(&i) = self.city_data.index.get(c) {
+				bits.insert(i);
+			}
+		}
+		bits
+	}
+
+	/// Returns the set of cities covered by the towers currently placed, as a
+	/// bitset over the city index. Requires build_city_index to have been called.
+	pub fn covered_city_bits(&self) -> FixedBitSet {
+		let mut bits = FixedBitSet::with_capacity(self.cities.len());
+		for &t in self.towers.keys() {
+			bits.union_with(&self.tower_coverage_bits(t));
+		}
+		bits
+	}
+
+	/// Returns the towers that are the only tower covering some city, i.e. the
+	/// towers that can never be removed without breaking validity.
+	pub fn critical_towers(&self) -> HashSet<Point> {
+		self.singly_covered_cities().into_values().collect()
+	}
+
+	/// Returns the cities covered by exactly one tower, mapped to that tower.
+	pub fn singly_covered_cities(&self) -> HashMap<Point, Point> {
+		self
+			.cities
+			.iter()
+			.filter(|(_, ts)| ts.len() == 1)
+			.map(|(&c, ts)| (c, *ts.iter().next().unwrap()))
+			.collect()
+	}
+
+	/// Returns whether every city would still be covered if the tower at t were
+	/// removed, i.e. this Grid is valid and t is not the only tower covering any
+	/// city. Panics if there is no tower at t.
+	pub fn is_removable(&self, t: Point) -> bool {
+		assert!(self.towers.contains_key(&t), "There is no tower at {:?}.", t);
+		if !self.is_valid() {
+			return false;
+		}
+		let coverage = Point::points_within_radius(t, self.service_radius, self.dimension, self.metric, self.wrap).unwrap();
+		coverage
+			.iter()
+			.chain(std::iter::once(&t))
+			.filter(|&&c| self.city_data.cells[self.cell(c)])
+			.all(|c| self.cities[c].len() > 1)
+	}
+
+	/// Returns whether every city would still be covered if the tower at p were
+	/// moved to q, without modifying the Grid, i.e. this Grid is valid and q
+	/// covers every city only p covers. Panics if there is no tower at p.
+	pub fn is_move_valid(&self, p: Point, q: Point) -> bool {
+		assert!(self.towers.contains_key(&p), "There is no tower at {:?}.", p);
+		if !self.is_valid() {
+			return false;
+		}
+		let covered_by_q =
+			Point::points_within_radius(q, self.service_radius, self.dimension, self.metric, self.wrap).unwrap();
+		let coverage = Point::points_within_radius(p, self.service_radius, self.dimension, self.metric, self.wrap).unwrap();
+		coverage
+			.iter()
+			.chain(std::iter::once(&p))
+			.filter(|&&c| self.city_data.cells[self.cell(c)] && self.cities[&c].len() == 1)
+			.all(|c| *c == q || covered_by_q.contains(c))
+	}
+
+	/// Removes towers whose cities are all covered by other towers until every
+	/// tower is critical, returning how many were removed. Each step removes the
+	/// redundant tower whose removal lowers the penalty the most, since removing
+	/// one tower can make another critical.
+	pub fn prune_redundant_towers(&mut self) -> usize {
+		let mut removed = 0;
+		loop {
+			let critical = self.critical_towers();
+			let redundant = self
+				.towers
+				.keys()
+				.filter(|t| !critical.contains(t))
+				.map(|&t| (t, self.penalty_if_removed(t)))
+				.min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+			match redundant {
+				Some((t, _)) => {
+					self.remove_tower(t.x, t.y);
+					removed += 1;
+				}
+				None => return removed,
 			}
 		}
-		uncovered
 	}
 
-	/// Returns if a city is not covered by any tower.
-	pub fn is_city_uncovered(&self, p: Point) -> bool {
-		self.cities.get(&p).unwrap().len() == 0
+	/// Returns a snapshot of the coverage and penalty metrics of this Grid,
+	/// computed in a single pass over the cities.
+	pub fn report(&self) -> CoverageReport {
+		let mut covered = 0;
+		let mut coverage_surplus = 0;
+		let mut first_uncovered_city: Option<Point> = None;
+		let mut critical_towers = HashSet::new();
+		for (&c, ts) in self.cities.iter() {
+			match ts.len() {
+				0 => {
+					first_uncovered_city = Some(first_uncovered_city.map_or(c, |f| f.min(c)));
+				}
+				n => {
+					covered += 1;
+					coverage_surplus += n - 1;
+					if n == 1 {
+						critical_towers.extend(ts.iter().copied());
+					}
+				}
+			}
+		}
+
+		let num_cities = self.cities.len();
+		CoverageReport {
+			num_towers: self.towers.len(),
+			num_cities,
+			penalty: self.penalty(),
+			covered_fraction: if num_cities == 0 {
+				1.
+			} else {
+				covered as f64 / num_cities as f64
+			},
+			num_redundant_towers: self.towers.len() - critical_towers.len(),
+			coverage_surplus,
+			first_uncovered_city,
+		}
+	}
+
+	/// Adds towers until this Grid is valid: for each uncovered city, in random
+	/// order, places a tower at a random point covering it if it is still
+	/// uncovered. Returns the towers added.
+	pub fn cover_remaining_greedily(&mut self, rng: &mut impl Rng) -> Vec<Point> {
+		let mut added = Vec::new();
+		// Sorted first so the result only depends on the rng
+		let mut uncovered: Vec<Point> = self.get_uncovered_cities().into_iter().collect();
+		uncovered.sort();
+		uncovered.shuffle(rng);
+
+		for c in uncovered {
+			if !self.is_city_uncovered(c) {
+				continue;
+			}
+			// None of these can hold a tower already, since c is uncovered
+			let mut candidates: Vec<Point> =
+				Point::points_within_radius(c, self.service_radius, self.dimension, self.metric, self.wrap)
+					.unwrap()
+					.iter()
+					.copied()
+					.collect();
+			candidates.push(c);
+			candidates.sort();
+
+			let t = *candidates.choose(rng).unwrap();
+			self.add_tower(t.x, t.y);
+			added.push(t);
+		}
+		added
+	}
+
+	/// Replaces the towers with a random valid solution, covering the cities one
+	/// at a time as in cover_remaining_greedily. Much cheaper than
+	/// random_lp_solve, though the solution is worse, so it suits restarting a
+	/// local search. Terminates since each step covers at least one more city.
+	pub fn random_valid_solution(&mut self, rng: &mut impl Rng) {
+		self.remove_all_towers();
+		self.cover_remaining_greedily(rng);
+		debug_assert!(self.is_valid());
+	}
+
+	/// Returns a snapshot of the towers currently placed, which is much cheaper
+	/// than cloning the whole Grid.
+	pub fn checkpoint(&self) -> GridSnapshot {
+		GridSnapshot {
+			towers: self.towers().collect(),
+		}
+	}
+
+	/// Puts the towers back the way they were when the snapshot was taken,
+	/// only updating coverage and penalty for the towers that changed since.
+	pub fn restore(&mut self, snapshot: &GridSnapshot) {
+		let removed: Vec<Point> = self
+			.towers
+			.keys()
+			.filter(|t| !snapshot.towers.contains(t))
+			.copied()
+			.collect();
+		for t in removed {
+			self.remove_tower(t.x, t.y);
+		}
+		let added: Vec<Point> = snapshot
+			.towers
+			.iter()
+			.filter(|t| !self.towers.contains_key(t))
+			.copied()
+			.collect();
+		for t in added {
+			self.add_tower(t.x, t.y);
+		}
 	}
 
 	/// Moves a tower from P = (x, y) to Q = (x', y').
-	/// Fails if tower at P does not exist or if tower at Q already exists.
+	/// Panics if tower at P does not exist or if tower at Q already exists, see
+	/// try_move_tower.
 	pub fn move_tower(&mut self, p: Point, q: Point) {
-		assert!(
-			self.towers.contains_key(&p),
-			"Cannot move tower from {:?} because it does not exist.",
-			p
-		);
-		assert!(
-			!self.towers.contains_key(&q),
-			"Cannot move tower to {:?} because there is already a tower there.",
-			q
-		);
-		self.remove_tower(p.x, p.y);
-		self.add_tower(q.x, q.y);
+		if let Err(e) = self.try_move_tower(p, q) {
+			panic!("Cannot move tower: {}", e);
+		}
+	}
+
+	/// Moves a tower from p to q, failing without changing this Grid if there is
+	/// no tower at p, or q is off the grid or already has a tower.
+	pub fn try_move_tower(&mut self, p: Point, q: Point) -> Result<(), GridError> {
+		self.try_remove_tower(p.x, p.y)?;
+		if let Err(e) = self.try_add_tower(q.x, q.y) {
+			// Put the tower back where it was
+			self.add_tower(p.x, p.y);
+			return Err(e);
+		}
+		Ok(())
 	}
 
 	/// Asserts that the given coordinates are within this Grid.
@@ -272,8 +1025,8 @@ impl Grid {
 	/// Returns the file output string of this entire Grid.
 	pub fn output(&self) -> String {
 		let mut res = format!("# Penalty = {}\n", self.penalty());
-		res += &(self.towers.len().to_string() + "\n");
-		let mut sorted_towers: Vec<_> = self.towers.keys().collect();
+		res += &(self.tower_count().to_string() + "\n");
+		let mut sorted_towers: Vec<Point> = self.towers().collect();
 		sorted_towers.sort();
 		for point in sorted_towers {
 			res += &(point.file_string() + "\n");
@@ -281,7 +1034,7 @@ impl Grid {
 		res
 	}
 
-	pub fn get_cities_ref(&self) -> &HashMap<Point, HashSet<Point>> {
+	pub fn get_cities_ref(&self) -> &PointMap<PointSet> {
 		&self.cities
 	}
 
@@ -297,11 +1050,60 @@ impl Grid {
 		self.dimension
 	}
 
-	pub fn get_towers_ref(&self) -> &HashMap<Point, HashSet<Point>> {
+	pub fn metric(&self) -> Metric {
+		self.metric
+	}
+
+	/// Sets how distance is measured for coverage and penalties. Euclidean
+	/// unless set otherwise.
+	pub fn set_metric(&mut self, metric: Metric) {
+		assert!(self.towers.is_empty(), "Cannot change the metric after placing towers.");
+		self.metric = metric;
+	}
+
+	pub fn wrap(&self) -> bool {
+		self.wrap
+	}
+
+	/// Sets whether the grid is a torus, i.e. whether towers cover and penalize
+	/// across the edges, as if the grid were tiled. Off unless set otherwise.
+	pub fn set_wrap(&mut self, wrap: bool) {
+		assert!(self.towers.is_empty(), "Cannot change wraparound after placing towers.");
+		self.wrap = wrap;
+	}
+
+	/// Returns the positions of the towers, in no particular order.
+	pub fn towers(&self) -> impl Iterator<Item = Point> + '_ {
+		self.towers.keys().copied()
+	}
+
+	/// Returns the positions of the cities, in no particular order.
+	pub fn cities(&self) -> impl Iterator<Item = Point> + '_ {
+		self.cities.keys().copied()
+	}
+
+	pub fn tower_count(&self) -> usize {
+		self.towers.len()
+	}
+
+	pub fn city_count(&self) -> usize {
+		self.cities.len()
+	}
+
+	pub fn get_towers_ref(&self) -> &PointMap<PointSet> {
 		&self.towers
 	}
 
-	pub fn replace_all_towers(&mut self, towers: HashMap<Point, HashSet<Point>>) {
+	/// Returns the other towers within the penalty radius of the given tower,
+	/// i.e. the towers counted in its w_j, or None if there is no tower there.
+	///
+	/// e.g. with towers at (0, 0), (1, 1) and (20, 20) and penalty radius 8,
+	/// `grid.penalty_neighbors(Point::new(0, 0))` is `Some({(1, 1)})`.
+	pub fn penalty_neighbors(&self, tower: Point) -> Option<&PointSet> {
+		self.towers.get(&tower)
+	}
+
+	pub fn replace_all_towers(&mut self, towers: PointMap<PointSet>) {
 		if self.towers == towers {
 			return;
 		}
@@ -319,105 +1121,291 @@ impl Grid {
 		self.penalty_radius = pen_radius;
 	}
 
+	/// Sets the dimension of this Grid. Can only be changed before any cities or
+	/// towers are placed.
 	pub fn set_dimension(&mut self, dim: u8) {
+		assert!(
+			self.towers.is_empty() && self.cities.is_empty(),
+			"Cannot change the dimension after placing cities or towers."
+		);
 		self.dimension = dim;
+		self.tower_cells = vec![false; dim as usize * dim as usize];
+		Arc::make_mut(&mut self.city_data).cells = vec![false; dim as usize * dim as usize];
 	}
 
 	pub fn remove_all_towers(&mut self) {
 		self.towers.clear();
+		self.tower_cells.fill(false);
+		self.penalty_sum = 0.;
 		for (_, covered) in self.cities.iter_mut() {
 			covered.clear();
 		}
+		self.uncovered = self.cities.len();
 	}
 
-	/// Returns the grid created from the passed in input file.
-	pub fn from_file(path: &str) -> io::Result<Grid> {
-		let mut g = Grid::new(0, 0, 0);
+	/// Returns each city mapped to the towers covering it, sorted. Uncovered
+	/// cities map to no towers.
+	pub fn coverage_map(&self) -> HashMap<Point, Vec<Point>> {
+		self
+			.cities
+			.iter()
+			.map(|(&city, ts)| {
+				let mut towers: Vec<Point> = ts.iter().copied().collect();
+				towers.sort();
+				(city, towers)
+			})
+			.collect()
+	}
 
-		let file = File::open(path)?;
-		let reader = BufReader::new(file);
-
-		let mut i: i32 = 0;
-		let mut num_cities: i32 = -1;
-		for line in reader.lines() {
-			if let Ok(l) = line {
-				let vec: Vec<&str> = l.split_whitespace().collect();
-				let first_val: &str = vec.get(0).unwrap();
-				if first_val.eq("#") {
-					continue;
-				}
-				match i {
-					0 => num_cities = first_val.parse::<i32>().unwrap(),
-					1 => g.set_dimension(first_val.parse::<u8>().unwrap()),
-					2 => g.set_service_radius(first_val.parse::<u8>().unwrap()),
-					3 => g.set_penalty_radius(first_val.parse::<u8>().unwrap()),
-					_ => {
-						if (4..(4 + num_cities)).contains(&i) {
-							let x = first_val.parse::<i32>().unwrap();
-							let y = vec.get(1).unwrap().parse::<i32>().unwrap();
-							g.add_city(x, y);
-						}
-					}
-				}
-				i += 1;
+	/// Returns the full state of this Grid as JSON: the radii, the penalty, and
+	/// every city and tower along with its coverage, all sorted.
+	pub fn to_json(&self) -> String {
+		let sorted = |set: &PointSet| {
+			let mut points: Vec<Point> = set.iter().copied().collect();
+			points.sort();
+			points
+		};
+		let mut cities: Vec<CityJson> = self
+			.cities
+			.iter()
+			.map(|(&city, ts)| CityJson {
+				city,
+				covered_by: sorted(ts),
+			})
+			.collect();
+		cities.sort_by_key(|c| c.city);
+		let mut towers: Vec<TowerJson> = self
+			.towers
+			.iter()
+			.map(|(&tower, ts)| TowerJson {
+				tower,
+				penalized_by: sorted(ts),
+			})
+			.collect();
+		towers.sort_by_key(|t| t.tower);
+
+		let json = GridJson {
+			dimension: self.dimension,
+			service_radius: self.service_radius,
+			penalty_radius: self.penalty_radius,
+			metric: self.metric,
+			wrap: self.wrap,
+			penalty: self.penalty(),
+			valid: self.is_valid(),
+			cities,
+			towers,
+		};
+		serde_json::to_string(&json).expect("Failed to serialize grid")
+	}
+
+	/// Returns the grid described by the JSON form from to_json. Only the radii,
+	/// metric, wraparound, cities and towers are read; coverage and penalty are
+	/// recomputed from them, so they can be left out. The metric and wraparound
+	/// default to Euclidean without wraparound.
+	pub fn from_json(s: &str) -> serde_json::Result<Grid> {
+		use serde::de::Error;
+
+		let json: GridJson = serde_json::from_str(s)?;
+		let mut g = Grid::new(json.dimension, json.service_radius, json.penalty_radius);
+		g.set_metric(json.metric);
+		g.set_wrap(json.wrap);
+		for CityJson { city: c, .. } in json.cities {
+			if !g.is_on_grid(c.x, c.y) {
+				return Err(serde_json::Error::custom(format!("city {} is off the grid", c)));
+			} else if g.cities.contains_key(&c) {
+				return Err(serde_json::Error::custom(format!("duplicate city {}", c)));
 			}
+			g.add_city(c.x, c.y);
+		}
+		for TowerJson { tower: t, .. } in json.towers {
+			if !g.is_on_grid(t.x, t.y) {
+				return Err(serde_json::Error::custom(format!("tower {} is off the grid", t)));
+			} else if g.towers.contains_key(&t) {
+				return Err(serde_json::Error::custom(format!("duplicate tower {}", t)));
+			}
+			g.add_tower(t.x, t.y);
 		}
 		Ok(g)
 	}
 
-	// Orders the points in the solution.
-	pub fn overwrite_with_sorted_solution(&self, output_path: &str) {
-		assert!(self.is_valid(), "Not a valid solution");
-		// Only overwrite if solution is better than what we currently have
-		if Path::new(output_path).is_file() {
-			let mut existing_penalty = 0.;
-			while existing_penalty == 0. {
-				existing_penalty = api::round(api::get_penalty_from_file(output_path).unwrap_or(0.));
+	/// Returns the grid created from the passed in input file, which is
+	/// decompressed if it ends in .gz. Blank lines and lines starting with # are
+	/// ignored.
+	pub fn from_file(path: &str) -> Result<Grid, GridParseError> {
+		Grid::from_reader(open_file(path)?)
+	}
+
+	/// Returns the grid read from the given input, in the same format as
+	/// from_file, e.g. to read an input from stdin.
+	pub fn from_reader<R: BufRead>(reader: R) -> Result<Grid, GridParseError> {
+		let mut g = Grid::new(0, 0, 0);
+
+		// num_cities, dimension, service radius, penalty radius
+		let mut header: Vec<usize> = Vec::new();
+		let mut num_cities_found = 0;
+		for (line_num, line) in reader.lines().enumerate() {
+			let line_num = line_num + 1;
+			let line = line?;
+			// trim also drops the \r of CRLF line endings
+			let l = line.trim();
+			if l.is_empty() || l.starts_with('#') {
+				continue;
 			}
 
-			if self.penalty() > existing_penalty {
-				return;
+			if header.len() < 4 {
+				let value = l
+					.split_whitespace()
+					.next()
+					.unwrap()
+					.parse::<usize>()
+					.ok()
+					.filter(|&v| header.is_empty() || v <= u8::MAX as usize)
+					.ok_or(GridParseError::Line {
+						line: line_num,
+						kind: LineErrorKind::InvalidNumber(l.to_string()),
+					})?;
+				header.push(value);
+				match header.len() {
+					2 => g.set_dimension(value as u8),
+					3 => g.set_service_radius(value as u8),
+					4 => g.set_penalty_radius(value as u8),
+					_ => {}
+				}
+				continue;
+			}
+
+			num_cities_found += 1;
+			if num_cities_found > header[0] {
+				continue; // counted for the mismatch error below
 			}
+			let c = l.parse::<Point>().map_err(|e| GridParseError::Line {
+				line: line_num,
+				kind: LineErrorKind::InvalidPoint(e),
+			})?;
+			let kind = if !g.is_on_grid(c.x, c.y) {
+				LineErrorKind::OffGrid(c)
+			} else if g.cities.contains_key(&c) {
+				LineErrorKind::DuplicateCity(c)
+			} else {
+				g.add_city(c.x, c.y);
+				continue;
+			};
+			return Err(GridParseError::Line { line: line_num, kind });
 		}
 
-		let data = self.output();
-		let mut f = OpenOptions::new()
-			.write(true)
-			.truncate(true)
-			.create(true)
-			.open(output_path)
-			.expect("Unable to open file");
-		f.write_all(data.as_bytes()).expect("Unable to write data");
+		if header.len() < 4 {
+			return Err(GridParseError::MissingHeader);
+		}
+		if num_cities_found != header[0] {
+			return Err(GridParseError::CityCountMismatch {
+				expected: header[0],
+				found:    num_cities_found,
+			});
+		}
+		Ok(g)
 	}
 
-	// Write self to a file as a solution
-	pub fn write_solution(&self, output_path: &str) {
+	// Writes the solution with the towers sorted (by x, then y) for
+	// deterministic, diff-friendly files. Always overwrites, since this is a
+	// normalization pass rather than an improvement pass.
+	pub fn overwrite_with_sorted_solution(&self, output_path: &str) -> io::Result<()> {
+		self.write_solution(output_path, true).map(|_| ())
+	}
+
+	/// Writes self to a file as a solution. Unless force is set, an existing
+	/// solution that is at least as good is kept, and the outcome says why.
+	/// With force the file is always written, e.g. after editing the input it
+	/// was solved for. Calls from different threads are serialized, so the
+	/// comparison always sees the latest solution.
+	pub fn write_solution(&self, output_path: &str, force: bool) -> io::Result<WriteOutcome> {
 		assert!(self.is_valid(), "Not a valid solution");
+		// A panic elsewhere while holding the lock leaves no partial write behind,
+		// so a poisoned lock is still safe to use
+		let _guard = WRITE_SOLUTION_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
 		// Only overwrite if solution is better than what we currently have
-		let mut existing_penalty = 0.;
-		if Path::new(output_path).is_file() {
-			while existing_penalty == 0. {
-				existing_penalty = api::round(api::get_penalty_from_file(output_path).unwrap_or(0.));
+		if !force && Path::new(output_path).is_file() {
+			// Leave the file untouched if it already holds exactly this solution
+			if Grid::towers_from_file(output_path).ok() == Some(self.towers().collect()) {
+				return Ok(WriteOutcome::Unchanged);
 			}
 
-			if self.penalty() >= existing_penalty {
-				return;
+			// An existing file without a readable penalty is corrupt, so it is
+			// replaced. Outputs are written atomically, so one read is enough.
+			if let Ok(existing_penalty) = api::get_penalty_from_file(output_path).map(api::round) {
+				if self.penalty() > existing_penalty {
+					return Ok(WriteOutcome::Worse);
+				} else if self.penalty() == existing_penalty {
+					return Ok(WriteOutcome::Unchanged);
+				}
 			}
 		}
 
+		self.write_output(output_path)?;
+		Ok(WriteOutcome::Written)
+	}
+
+	/// Writes the file output string of this Grid to the given path,
+	/// overwriting whatever is there, gzip compressed if the path ends in .gz.
+	/// The output is written to a temporary file next to it and renamed into
+	/// place, so readers (and runs killed mid-write) only ever see a complete
+	/// solution.
+	pub fn write_output(&self, output_path: &str) -> io::Result<()> {
 		let data = self.output();
-		let mut f = OpenOptions::new()
-			.write(true)
-			.truncate(true)
-			.create(true)
-			.open(output_path)
-			.expect("Unable to open file");
-		f.write_all(data.as_bytes()).expect("Unable to write data");
+		// Unique per write, since threads may write the same output concurrently
+		let temp_path = format!(
+			"{}.{}-{}.tmp",
+			output_path,
+			std::process::id(),
+			NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+		);
+		let mut f = File::create(&temp_path)?;
+		if is_gzip_path(output_path) {
+			let mut encoder = GzEncoder::new(f, Compression::default());
+			encoder.write_all(data.as_bytes())?;
+			f = encoder.finish()?;
+		} else {
+			f.write_all(data.as_bytes())?;
+		}
+		f.sync_all()?;
+		fs::rename(&temp_path, output_path)
+	}
+
+	/// Recomputes the penalty of the solution at the given path by placing its
+	/// towers on a copy of this (input) Grid, and compares it against the
+	/// `# Penalty = ...` header of the file. If they disagree beyond rounding
+	/// and `in_place` is set, the file is rewritten with the correct header.
+	/// Returns whether the header disagreed, or an error if the solution
+	/// couldn't be read.
+	pub fn validate_penalty_header(&self, solution_path: &str, in_place: bool) -> io::Result<bool> {
+		let mut solution = self.clone();
+		solution.remove_all_towers();
+		solution.apply_solution(solution_path)?;
+
+		let actual = solution.penalty();
+		let header = api::get_penalty_from_file(solution_path).map(api::round);
+		if header.ok() == Some(actual) {
+			return Ok(false);
+		}
+
+		if in_place {
+			solution.write_output(solution_path)?;
+		}
+		Ok(true)
 	}
 
 	/// Randomly solves the Grid using LP up until the max time and
-	/// returns penalty.
-	pub fn random_lp_solve(&mut self, max_time: u32, seed: u32) -> f64 {
+	/// returns penalty, along with how the LP solve ended. If the LP stopped at
+	/// the max time before covering every city, the rest are covered greedily.
+	/// The LP never returns any of the excluded tower configurations (or a
+	/// superset of one). If dump_model is given, the LP is written there before
+	/// solving. Fails if the LP couldn't be solved or the model written.
+	pub fn random_lp_solve(
+		&mut self,
+		max_time: u32,
+		seed: u32,
+		excluded: &[Vec<Point>],
+		dump_model: Option<&str>,
+	) -> Result<(f64, LpStatus), ResolutionError> {
 		let mut city_keys = HashSet::new();
 		for (&c, _) in self.cities.iter() {
 			city_keys.insert(c);
@@ -426,76 +1414,1180 @@ impl Grid {
 		// use rand::{thread_rng, Rng};
 		// let mut rng = thread_rng();
 		self.remove_all_towers();
+		// Without cities no towers is optimal, and there is nothing for the LP to
+		// solve
+		if self.cities.is_empty() {
+			return Ok((self.penalty(), LpStatus::Optimal));
+		}
 		let problem = GridProblem::new_randomized(
 			self.dimension,
 			self.service_radius,
 			self.penalty_radius,
+			self.metric,
+			self.wrap,
 			city_keys,
 			max_time,
 			seed,
-		);
-		let tower_soln = problem.tower_solution();
-		for t in tower_soln {
+		)
+		.with_excluded_solutions(excluded);
+		if let Some(path) = dump_model {
+			problem
+				.export_model(path)
+				.map_err(|e| ResolutionError::Str(format!("Unable to write LP model to {}: {}", path, e)))?;
+		}
+		let tower_soln = problem.tower_solution()?;
+		for t in tower_soln.towers {
 			self.add_tower(t.x, t.y);
 		}
-		self.penalty()
+		if !self.is_valid() {
+			self.cover_remaining_greedily(&mut StdRng::seed_from_u64(seed.into()));
+		}
+		Ok((self.penalty(), tower_soln.status))
 	}
 
 	/// Destructively (changes the grid's tower configuration) solves the Grid
-	/// using the LP.
-	pub fn lp_solve(&mut self, max_time: u32) {
+	/// using the LP, optionally placing at most max_towers towers, and returns
+	/// how the LP solve ended and its optimality gap. Fails if the LP could not
+	/// be solved, e.g. if max_towers is too small to cover every city, or if it
+	/// hit max_time before finding a valid solution, or if the model couldn't be
+	/// written. CBC only prints its log if verbose is set. If dump_model is
+	/// given, the LP is written there before solving.
+	pub fn lp_solve(
+		&mut self,
+		max_time: u32,
+		max_towers: Option<usize>,
+		verbose: bool,
+		dump_model: Option<&str>,
+	) -> Result<(LpStatus, Option<f64>), ResolutionError> {
 		assert!(
 			self.towers.len() == 0,
 			"Cannot solve a grid with towers already placed."
 		);
+		if self.cities.is_empty() {
+			return Ok((LpStatus::Optimal, Some(0.)));
+		}
 
 		let mut city_keys = HashSet::new();
 		for (&c, _) in self.cities.iter() {
 			city_keys.insert(c);
 		}
 
-		let problem = GridProblem::new(
+		let mut problem = GridProblem::new(
 			self.dimension,
 			self.service_radius,
 			self.penalty_radius,
+			self.metric,
+			self.wrap,
 			city_keys,
 			max_time,
-		);
+		)
+		.with_console_log(verbose);
+		if let Some(k) = max_towers {
+			problem = problem.with_max_towers(k);
+		}
+		if let Some(path) = dump_model {
+			problem
+				.export_model(path)
+				.map_err(|e| ResolutionError::Str(format!("Unable to write LP model to {}: {}", path, e)))?;
+		}
 
-		for t in problem.tower_solution() {
+		let solution = problem.tower_solution()?;
+		for t in solution.towers {
 			self.add_tower(t.x, t.y);
 		}
+		if !self.is_valid() {
+			self.remove_all_towers();
+			return Err(ResolutionError::Other(
+				"Stopped at the time limit without a valid solution",
+			));
+		}
+		Ok((solution.status, solution.gap))
 	}
 
-	pub fn towers_from_file(path: &str) -> HashSet<Point> {
+	/// Places the towers of the solution at the given path on this Grid. Fails
+	/// without placing any towers if the file can't be read, or if a tower is
+	/// off the grid or already placed.
+	pub fn apply_solution(&mut self, path: &str) -> io::Result<()> {
+		let towers = Grid::towers_from_file(path)?;
+		for t in towers.iter() {
+			let msg = if !self.is_on_grid(t.x, t.y) {
+				"is off the grid"
+			} else if self.towers.contains_key(t) {
+				"is already placed"
+			} else {
+				continue;
+			};
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("{}: tower {} {}", path, t, msg),
+			));
+		}
+
+		for t in towers {
+			self.add_tower(t.x, t.y);
+		}
+		Ok(())
+	}
+
+	/// Reads the towers of the solution at the given path. Blank lines and lines
+	/// starting with # (e.g. the penalty header) are ignored; the first other
+	/// line is the number of towers, and the rest are the towers as "x y". The
+	/// file is decompressed if it ends in .gz.
+	pub fn towers_from_file(path: &str) -> io::Result<HashSet<Point>> {
+		let reader = open_file(path)?;
+		let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, msg));
+
+		let mut num_towers: Option<usize> = None;
 		let mut towers = HashSet::new();
-		let file = File::open(path).expect("Unable to open file");
-		let reader = BufReader::new(file);
+		for (line_num, line) in reader.lines().enumerate() {
+			let line_num = line_num + 1;
+			let line = line?;
+			let l = line.trim();
+			if l.is_empty() || l.starts_with('#') {
+				continue;
+			}
+			if num_towers.is_none() {
+				let n = l
+					.parse()
+					.map_err(|_| invalid(format!("line {}: invalid tower count {:?}", line_num, l)))?;
+				num_towers = Some(n);
+				continue;
+			}
 
-		let mut i: i32 = 0;
-		for line in reader.lines() {
-			match i {
-				0 => {
-					i += 1;
-					continue;
-				}
-				1 => {
-					i += 1;
-					continue;
+			let t: Point = l.parse().map_err(|e| invalid(format!("line {}: {}", line_num, e)))?;
+			if !towers.insert(t) {
+				return Err(invalid(format!("line {}: duplicate tower {}", line_num, t)));
+			}
+		}
+
+		match num_towers {
+			None => Err(invalid("missing the number of towers".to_string())),
+			Some(n) if n != towers.len() => Err(invalid(format!("expected {} towers but found {}", n, towers.len()))),
+			Some(_) => Ok(towers),
+		}
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use super::*;
+
+	/// Returns a path in the temp directory unique to this test run and name.
+	pub fn temp_path(name: &str) -> String {
+		let path = std::env::temp_dir().join(format!("pengwin-{}-{}", std::process::id(), name));
+		path.to_str().unwrap().to_string()
+	}
+
+	/// Returns a dim x dim Grid with the given radii and cities.
+	pub fn grid_with_cities(dim: u8, r_s: u8, r_p: u8, cities: &[(i32, i32)]) -> Grid {
+		let mut g = Grid::new(dim, r_s, r_p);
+		for &(x, y) in cities {
+			g.add_city(x, y);
+		}
+		g
+	}
+
+	/// Returns a dim x dim Grid with the given radii and num_cities cities at
+	/// distinct random cells.
+	pub fn random_grid(rng: &mut impl Rng, dim: u8, r_s: u8, r_p: u8, num_cities: usize) -> Grid {
+		let mut cells: Vec<(i32, i32)> = (0..dim as i32)
+			.flat_map(|x| (0..dim as i32).map(move |y| (x, y)))
+			.collect();
+		cells.shuffle(rng);
+		grid_with_cities(dim, r_s, r_p, &cells[..num_cities])
+	}
+
+	/// Returns the lowest penalty of any valid tower placement on g by trying
+	/// every subset of the cells that cover some city (a tower covering nothing
+	/// only adds penalty).
+	pub fn brute_force_optimum(g: &Grid) -> f64 {
+		let mut candidates: Vec<Point> = Vec::new();
+		for c in g.cities() {
+			let coverage = Point::points_within_radius(c, g.service_radius(), g.dimension(), g.metric(), g.wrap()).unwrap();
+			candidates.extend(coverage.iter().copied().chain(std::iter::once(c)));
+		}
+		candidates.sort();
+		candidates.dedup();
+
+		let mut best = f64::INFINITY;
+		for mask in 0u32..(1 << candidates.len()) {
+			let mut h = g.clone();
+			h.remove_all_towers();
+			for (i, t) in candidates.iter().enumerate() {
+				if mask & (1 << i) != 0 {
+					h.add_tower(t.x, t.y);
 				}
-				_ => {
-					if let Ok(l) = line {
-						let vec: Vec<&str> = l.split_whitespace().collect();
-						if vec.len() == 0 || vec.get(0).unwrap().eq(&"#") {
+			}
+			if h.is_valid() {
+				best = best.min(h.penalty());
+			}
+		}
+		best
+	}
+
+	#[test]
+	fn penalty_if_moved_matches_move() {
+		let mut rng = StdRng::seed_from_u64(1312);
+		for metric in [Metric::Euclidean, Metric::Manhattan] {
+			for wrap in [false, true] {
+				let mut g = random_grid(&mut rng, 12, 2, 4, 20);
+				g.set_metric(metric);
+				g.set_wrap(wrap);
+				g.random_valid_solution(&mut rng);
+
+				let towers: Vec<Point> = g.towers().collect();
+				for &p in towers.iter() {
+					for _ in 0..10 {
+						let q = Point::new(rng.gen_range(0..12), rng.gen_range(0..12));
+						if g.is_tower_present(q) {
 							continue;
 						}
-						let x = vec.get(0).unwrap().parse::<i32>().unwrap();
-						let y = vec.get(1).unwrap().parse::<i32>().unwrap();
-						towers.insert(Point::new(x, y));
+						let mut moved = g.clone();
+						moved.move_tower(p, q);
+						assert_eq!(g.penalty_if_moved(p, q), moved.penalty(), "moving {} to {}", p, q);
+						assert_eq!(g.is_move_valid(p, q), moved.is_valid(), "moving {} to {}", p, q);
 					}
 				}
 			}
 		}
-		towers
+	}
+
+	#[test]
+	fn input_stem_strips_input_extensions() {
+		assert_eq!(input_stem(Path::new("inputs/small/001.in")), Some("001"));
+		assert_eq!(input_stem(Path::new("inputs/small/001.in.gz")), Some("001"));
+		assert_eq!(input_stem(Path::new("outputs/small/001.out")), None);
+		assert_eq!(input_stem(Path::new("inputs/small/README")), None);
+	}
+
+	#[test]
+	fn json_round_trip() {
+		let mut rng = StdRng::seed_from_u64(1280);
+		let mut g = random_grid(&mut rng, 10, 2, 3, 15);
+		g.set_metric(Metric::Manhattan);
+		g.set_wrap(true);
+		g.random_valid_solution(&mut rng);
+
+		let h = Grid::from_json(&g.to_json()).unwrap();
+		assert!(g == h);
+		assert_eq!(h.metric(), Metric::Manhattan);
+		assert!(h.wrap());
+		assert_eq!(h.penalty(), g.penalty());
+		assert_eq!(h.to_json(), g.to_json());
+	}
+
+	#[test]
+	fn json_defaults_and_errors() {
+		let g =
+			Grid::from_json(r#"{"dimension":5,"service_radius":1,"penalty_radius":2,"cities":[{"city":{"x":1,"y":1}}]}"#)
+				.unwrap();
+		assert_eq!(g.metric(), Metric::Euclidean);
+		assert!(!g.wrap());
+		assert_eq!(g.city_count(), 1);
+		assert_eq!(g.tower_count(), 0);
+
+		let off_grid = r#"{"dimension":5,"service_radius":1,"penalty_radius":2,"cities":[{"city":{"x":5,"y":1}}]}"#;
+		assert!(Grid::from_json(off_grid).is_err());
+		let duplicate = r#"{"dimension":5,"service_radius":1,"penalty_radius":2,"cities":[],
+			"towers":[{"tower":{"x":1,"y":1}},{"tower":{"x":1,"y":1}}]}"#;
+		assert!(Grid::from_json(duplicate).is_err());
+	}
+
+	#[test]
+	fn penalty_lower_bound_at_most_optimum() {
+		let fixtures: [&[(i32, i32)]; 4] = [
+			&[(2, 2)],
+			&[(0, 0), (3, 3)],
+			&[(0, 1), (1, 0), (1, 2), (2, 1)],
+			&[(0, 0), (0, 3), (3, 0), (3, 3), (1, 2)],
+		];
+		for cities in fixtures {
+			let g = grid_with_cities(4, 1, 2, cities);
+			let optimum = brute_force_optimum(&g);
+			let bound = g.penalty_lower_bound();
+			assert!(bound > 0., "{:?}: bound should be positive", cities);
+			assert!(
+				bound <= optimum,
+				"{:?}: bound {} exceeds optimum {}",
+				cities,
+				bound,
+				optimum
+			);
+		}
+	}
+
+	#[test]
+	fn penalty_lower_bound_counts_own_cell() {
+		// One tower at the center covers all five cities, so the bound is one tower
+		let g = grid_with_cities(3, 1, 1, &[(1, 1), (0, 1), (1, 0), (2, 1), (1, 2)]);
+		assert_eq!(brute_force_optimum(&g), 170.);
+		assert_eq!(g.penalty_lower_bound(), 170.);
+	}
+
+	#[test]
+	fn penalty_lower_bound_of_empty_grid_is_zero() {
+		assert_eq!(Grid::new(5, 1, 2).penalty_lower_bound(), 0.);
+	}
+
+	#[test]
+	fn validate_penalty_header_corrects_stale_header() {
+		let mut grid = grid_with_cities(6, 1, 3, &[(1, 1), (4, 4)]);
+		grid.add_tower(1, 1);
+		grid.add_tower(4, 4);
+		let path = temp_path("stale-header.out");
+		fs::write(&path, "# Penalty = 1\n2\n1 1\n4 4\n").unwrap();
+		grid.remove_all_towers();
+
+		assert!(grid.validate_penalty_header(&path, false).unwrap());
+		assert_eq!(fs::read_to_string(&path).unwrap(), "# Penalty = 1\n2\n1 1\n4 4\n");
+		assert!(grid.validate_penalty_header(&path, true).unwrap());
+		assert!(!grid.validate_penalty_header(&path, false).unwrap());
+		let header = api::get_penalty_from_file(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(api::round(header), 340.);
+	}
+
+	#[test]
+	fn coverage_bits_match_coverage() {
+		let mut rng = StdRng::seed_from_u64(1185);
+		let mut grid = random_grid(&mut rng, 10, 2, 3, 25);
+		grid.build_city_index();
+		grid.random_valid_solution(&mut rng);
+
+		let covered = grid.covered_city_bits();
+		assert_eq!(covered.count_ones(..), grid.city_count());
+		let towers: Vec<Point> = grid.towers().collect();
+		for t in towers {
+			let bits = grid.tower_coverage_bits(t);
+			let covers = grid.cities().filter(|c| grid.get_cities_ref()[c].contains(&t)).count();
+			assert_eq!(bits.count_ones(..), covers, "tower {}", t);
+			assert!(bits.is_subset(&covered));
+		}
+
+		grid.remove_all_towers();
+		assert_eq!(grid.covered_city_bits().count_ones(..), 0);
+	}
+
+	#[test]
+	fn report_summarizes_coverage() {
+		let mut grid = grid_with_cities(8, 1, 2, &[(1, 1), (1, 2), (6, 6), (0, 7)]);
+		grid.add_tower(1, 1);
+		grid.add_tower(1, 2);
+		assert_eq!(
+			grid.report(),
+			CoverageReport {
+				num_towers:           2,
+				num_cities:           4,
+				penalty:              grid.penalty(),
+				covered_fraction:     0.5,
+				num_redundant_towers: 2,
+				coverage_surplus:     2,
+				first_uncovered_city: Some(Point::new(0, 7)),
+			}
+		);
+
+		grid.add_tower(6, 7);
+		grid.add_tower(0, 6);
+		let report = grid.report();
+		assert_eq!(report.covered_fraction, 1.);
+		assert_eq!(report.num_redundant_towers, 2);
+		assert_eq!(report.first_uncovered_city, None);
+		assert_eq!(Grid::new(3, 1, 1).report().covered_fraction, 1.);
+	}
+
+	/// Returns a valid solution of a small grid, with one tower per city.
+	fn solved_grid() -> Grid {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		for c in [(1, 1), (2, 2), (6, 6)] {
+			grid.add_tower(c.0, c.1);
+		}
+		grid
+	}
+
+	#[test]
+	fn write_solution_keeps_identical_solution() {
+		let grid = solved_grid();
+		let path = temp_path("write-unchanged.out");
+		assert_eq!(grid.write_solution(&path, false).unwrap(), WriteOutcome::Written);
+		let written = fs::metadata(&path).unwrap().modified().unwrap();
+		assert_eq!(grid.write_solution(&path, false).unwrap(), WriteOutcome::Unchanged);
+		assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), written);
+
+		// A different solution with the same penalty is kept too
+		let mut moved = grid.clone();
+		moved.move_tower(Point::new(6, 6), Point::new(6, 7));
+		assert_eq!(moved.penalty(), grid.penalty());
+		assert_eq!(moved.write_solution(&path, false).unwrap(), WriteOutcome::Unchanged);
+		assert_eq!(Grid::towers_from_file(&path).unwrap(), grid.towers().collect());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn penalty_neighbors_are_symmetric() {
+		let mut grid = Grid::new(30, 1, 8);
+		for (x, y) in [(0, 0), (1, 1), (20, 20)] {
+			grid.add_tower(x, y);
+		}
+		let neighbors = |x, y| grid.penalty_neighbors(Point::new(x, y)).cloned();
+		assert_eq!(neighbors(0, 0), Some([Point::new(1, 1)].into_iter().collect()));
+		assert_eq!(neighbors(1, 1), Some([Point::new(0, 0)].into_iter().collect()));
+		assert_eq!(neighbors(20, 20), Some(PointSet::default()));
+		assert_eq!(neighbors(5, 5), None);
+	}
+
+	#[test]
+	fn write_solution_keeps_better_solution() {
+		let grid = solved_grid();
+		let path = temp_path("write-worse.out");
+		grid.write_solution(&path, false).unwrap();
+
+		// An extra tower only adds penalty
+		let mut worse = grid.clone();
+		worse.add_tower(1, 2);
+		assert!(worse.penalty() > grid.penalty());
+		assert_eq!(worse.write_solution(&path, false).unwrap(), WriteOutcome::Worse);
+		assert_eq!(Grid::towers_from_file(&path).unwrap(), grid.towers().collect());
+
+		// and the better one replaces it the other way around
+		worse.write_solution(&path, true).unwrap();
+		assert_eq!(grid.write_solution(&path, false).unwrap(), WriteOutcome::Written);
+		assert_eq!(Grid::towers_from_file(&path).unwrap(), grid.towers().collect());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn critical_towers_solely_cover_a_city() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		for (x, y) in [(1, 1), (2, 1), (6, 6)] {
+			grid.add_tower(x, y);
+		}
+		assert_eq!(
+			grid.critical_towers(),
+			[Point::new(2, 1), Point::new(6, 6)].into_iter().collect()
+		);
+
+		let mut rng = StdRng::seed_from_u64(1195);
+		for _ in 0..5 {
+			let mut grid = random_grid(&mut rng, 10, 2, 3, 25);
+			grid.random_valid_solution(&mut rng);
+			// Extra towers make some of the others redundant
+			for _ in 0..5 {
+				let t = Point::new(rng.gen_range(0..10), rng.gen_range(0..10));
+				if !grid.is_tower_present(t) {
+					grid.add_tower(t.x, t.y);
+				}
+			}
+			let critical = grid.critical_towers();
+			for t in grid.towers() {
+				assert_eq!(critical.contains(&t), !grid.is_removable(t), "tower {}", t);
+			}
+		}
+	}
+
+	#[test]
+	fn cover_remaining_greedily_keeps_existing_towers() {
+		let mut rng = StdRng::seed_from_u64(1197);
+		let mut grid = random_grid(&mut rng, 10, 2, 3, 25);
+		grid.add_tower(5, 5);
+		let added = grid.cover_remaining_greedily(&mut StdRng::seed_from_u64(1));
+		assert!(grid.is_valid());
+		assert!(grid.is_tower_present(Point::new(5, 5)));
+		assert_eq!(grid.tower_count(), added.len() + 1);
+		assert!(added.iter().all(|&t| grid.is_tower_present(t)));
+		assert!(grid.cover_remaining_greedily(&mut rng).is_empty());
+
+		// The towers added only depend on the rng
+		let mut again = grid.clone();
+		for &t in added.iter() {
+			again.remove_tower(t.x, t.y);
+		}
+		assert_eq!(again.cover_remaining_greedily(&mut StdRng::seed_from_u64(1)), added);
+	}
+
+	#[test]
+	fn cached_penalty_matches_from_scratch() {
+		let mut rng = StdRng::seed_from_u64(1251);
+		let mut g = random_grid(&mut rng, 12, 2, 4, 20);
+		for _ in 0..300 {
+			let p = Point::new(rng.gen_range(0..12), rng.gen_range(0..12));
+			if g.is_tower_present(p) {
+				g.remove_tower(p.x, p.y);
+			} else {
+				g.add_tower(p.x, p.y);
+			}
+			assert_eq!(api::round(170. * g.penalty_sum), g.penalty_from_scratch());
+		}
+		g.remove_all_towers();
+		assert_eq!(g.penalty(), 0.);
+	}
+
+	#[test]
+	fn penalty_if_added_and_removed_match_mutation() {
+		let mut rng = StdRng::seed_from_u64(1252);
+		for wrap in [false, true] {
+			let mut g = random_grid(&mut rng, 12, 2, 4, 20);
+			g.set_wrap(wrap);
+			g.random_valid_solution(&mut rng);
+			for _ in 0..50 {
+				let p = Point::new(rng.gen_range(0..12), rng.gen_range(0..12));
+				let mut h = g.clone();
+				if g.is_tower_present(p) {
+					h.remove_tower(p.x, p.y);
+					assert_eq!(g.penalty_if_removed(p), h.penalty(), "removing {}", p);
+				} else {
+					h.add_tower(p.x, p.y);
+					assert_eq!(g.penalty_if_added(p), h.penalty(), "adding {}", p);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn uncovered_cities_follow_towers() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		assert_eq!(grid.get_uncovered_cities(), grid.cities().collect());
+		grid.add_tower(2, 1);
+		assert_eq!(grid.get_uncovered_cities(), [Point::new(6, 6)].into_iter().collect());
+		assert!(!grid.is_city_uncovered(Point::new(1, 1)));
+		assert!(grid.is_city_uncovered(Point::new(6, 6)));
+		grid.add_tower(6, 6);
+		assert!(grid.get_uncovered_cities().is_empty());
+		grid.remove_tower(2, 1);
+		assert_eq!(
+			grid.get_uncovered_cities(),
+			[Point::new(1, 1), Point::new(2, 2)].into_iter().collect()
+		);
+	}
+
+	#[test]
+	fn overwrite_with_sorted_solution_always_writes() {
+		let grid = solved_grid();
+		let path = temp_path("sorted.out");
+		fs::write(&path, "# Penalty = 510\n3\n6 6\n2 2\n1 1\n").unwrap();
+		grid.overwrite_with_sorted_solution(&path).unwrap();
+		assert_eq!(fs::read_to_string(&path).unwrap(), grid.output());
+		assert!(grid.output().ends_with("3\n1 1\n2 2\n6 6\n"));
+
+		// even when the solution is worse
+		let mut worse = grid.clone();
+		worse.add_tower(0, 0);
+		worse.overwrite_with_sorted_solution(&path).unwrap();
+		assert_eq!(fs::read_to_string(&path).unwrap(), worse.output());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn from_reader_parses_comments_and_blank_lines() {
+		let g = Grid::from_reader("# comment\n2\n\n30\n3\r\n8\n1 1\n# another\n20 20\n".as_bytes()).unwrap();
+		assert_eq!((g.dimension(), g.service_radius(), g.penalty_radius()), (30, 3, 8));
+		assert_eq!(
+			g.cities().collect::<HashSet<Point>>(),
+			[Point::new(1, 1), Point::new(20, 20)].into_iter().collect()
+		);
+	}
+
+	#[test]
+	fn from_reader_rejects_malformed_inputs() {
+		let error = |s: &str| Grid::from_reader(s.as_bytes()).unwrap_err();
+		assert!(matches!(error("2\n30\n3\n"), GridParseError::MissingHeader));
+		assert!(matches!(
+			error("2\n30\n3\n8\n1 1\n"),
+			GridParseError::CityCountMismatch {
+				expected: 2,
+				found:    1,
+			}
+		));
+		assert!(matches!(
+			error("1\n30\n3\n8\n1 1\n2 2\n"),
+			GridParseError::CityCountMismatch {
+				expected: 1,
+				found:    2,
+			}
+		));
+		assert!(matches!(
+			error("1\n30\nthree\n8\n1 1\n"),
+			GridParseError::Line {
+				line: 3,
+				kind: LineErrorKind::InvalidNumber(_),
+			}
+		));
+		assert!(matches!(
+			error("1\n30\n300\n8\n1 1\n"),
+			GridParseError::Line {
+				line: 3,
+				kind: LineErrorKind::InvalidNumber(_),
+			}
+		));
+		assert!(matches!(
+			error("1\n30\n3\n8\n1\n"),
+			GridParseError::Line {
+				line: 5,
+				kind: LineErrorKind::InvalidPoint(_),
+			}
+		));
+		assert!(matches!(
+			error("1\n30\n3\n8\n30 1\n"),
+			GridParseError::Line {
+				line: 5,
+				kind: LineErrorKind::OffGrid(_),
+			}
+		));
+		assert!(matches!(
+			error("2\n30\n3\n8\n1 1\n\n1 1\n"),
+			GridParseError::Line {
+				line: 7,
+				kind: LineErrorKind::DuplicateCity(_),
+			}
+		));
+		assert_eq!(
+			error("1\n30\n3\n8\n30 1\n").to_string(),
+			"line 5: city (30, 1) is off the grid"
+		);
+		assert!(matches!(
+			Grid::from_file("./no/such/input.in"),
+			Err(GridParseError::Io(_))
+		));
+	}
+
+	#[test]
+	fn random_lp_solve_is_valid_at_the_time_limit() {
+		let mut rng = StdRng::seed_from_u64(1265);
+		let mut g = random_grid(&mut rng, 12, 2, 4, 20);
+		g.add_tower(0, 0);
+		let (penalty, _) = g.random_lp_solve(1, 7, &[], None).unwrap();
+		assert!(g.is_valid());
+		assert_eq!(penalty, g.penalty());
+
+		let mut empty = Grid::new(12, 2, 4);
+		assert_eq!(empty.random_lp_solve(1, 7, &[], None).unwrap(), (0., LpStatus::Optimal));
+	}
+
+	#[test]
+	fn prune_redundant_towers_leaves_only_critical_towers() {
+		let mut grid = solved_grid();
+		// (1, 2) covers both (1, 1) and (2, 2), so it replaces their towers
+		grid.add_tower(1, 2);
+		grid.add_tower(6, 7);
+		assert_eq!(grid.prune_redundant_towers(), 3);
+		assert!(grid.is_valid());
+		assert_eq!(grid.tower_count(), 2);
+		assert!(grid.is_tower_present(Point::new(1, 2)));
+		assert_eq!(grid.prune_redundant_towers(), 0);
+
+		let mut rng = StdRng::seed_from_u64(1272);
+		for _ in 0..5 {
+			let mut grid = random_grid(&mut rng, 10, 2, 3, 25);
+			grid.random_valid_solution(&mut rng);
+			for _ in 0..10 {
+				let t = Point::new(rng.gen_range(0..10), rng.gen_range(0..10));
+				if !grid.is_tower_present(t) {
+					grid.add_tower(t.x, t.y);
+				}
+			}
+			let (before, num_towers) = (grid.penalty(), grid.tower_count());
+			let removed = grid.prune_redundant_towers();
+			assert!(grid.is_valid());
+			assert_eq!(grid.tower_count(), num_towers - removed);
+			assert!(grid.penalty() <= before);
+			assert_eq!(grid.critical_towers().len(), grid.tower_count());
+		}
+	}
+
+	#[test]
+	fn apply_solution_places_nothing_on_error() {
+		let path = temp_path("apply.out");
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		fs::write(&path, "3\n1 1\n2 2\n8 8\n").unwrap();
+		let e = grid.apply_solution(&path).unwrap_err();
+		assert!(e.to_string().contains("tower (8, 8) is off the grid"), "{}", e);
+		assert_eq!(grid.tower_count(), 0);
+
+		grid.add_tower(2, 2);
+		fs::write(&path, "3\n1 1\n2 2\n6 6\n").unwrap();
+		let e = grid.apply_solution(&path).unwrap_err();
+		assert!(e.to_string().contains("tower (2, 2) is already placed"), "{}", e);
+		assert_eq!(grid.tower_count(), 1);
+
+		grid.remove_tower(2, 2);
+		grid.apply_solution(&path).unwrap();
+		assert_eq!(grid, solved_grid());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn towers_from_file_skips_comments_and_rejects_bad_lines() {
+		let path = temp_path("towers.out");
+		fs::write(
+			&path,
+			"# Penalty = 510\n\n# solved by hand\n3\n1 1\r\n\n2 2\n# last one\n6 6\n",
+		)
+		.unwrap();
+		assert_eq!(Grid::towers_from_file(&path).unwrap(), solved_grid().towers().collect());
+
+		let error = |contents: &str| {
+			fs::write(&path, contents).unwrap();
+			Grid::towers_from_file(&path).unwrap_err().to_string()
+		};
+		assert!(error("# Penalty = 0\n").contains("missing the number of towers"));
+		assert!(error("three\n1 1\n").contains("line 1: invalid tower count"));
+		assert!(error("2\n1 1\n").contains("expected 2 towers but found 1"));
+		assert!(error("1\n1 1\n2 2\n").contains("expected 1 towers but found 2"));
+		assert!(error("2\n1 1\n1 1\n").contains("line 3: duplicate tower (1, 1)"));
+		assert!(error("1\n1 x\n").contains("line 2:"));
+		fs::remove_file(&path).unwrap();
+		assert_eq!(
+			Grid::towers_from_file(&path).unwrap_err().kind(),
+			io::ErrorKind::NotFound
+		);
+	}
+
+	#[test]
+	fn write_output_leaves_no_temporary_files() {
+		let dir = temp_path("atomic");
+		fs::create_dir_all(&dir).unwrap();
+		let path = format!("{}/001.out", dir);
+		let grid = solved_grid();
+		let mut worse = grid.clone();
+		worse.add_tower(0, 0);
+
+		// Concurrent writers only ever leave one of the complete solutions
+		std::thread::scope(|s| {
+			for g in [&grid, &worse, &grid, &worse] {
+				s.spawn(|| g.write_output(&path).unwrap());
+			}
+		});
+		let contents = fs::read_to_string(&path).unwrap();
+		assert!(contents == grid.output() || contents == worse.output());
+		let entries: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+		assert_eq!(entries, vec!["001.out"]);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn write_solution_replaces_unreadable_output() {
+		let grid = solved_grid();
+		let path = temp_path("write-corrupt.out");
+		for contents in ["", "3\n1 1\n2 2\n", "# Penalty = \n"] {
+			fs::write(&path, contents).unwrap();
+			assert_eq!(grid.write_solution(&path, false).unwrap(), WriteOutcome::Written);
+			assert_eq!(fs::read_to_string(&path).unwrap(), grid.output());
+		}
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn restore_undoes_changes_since_checkpoint() {
+		let mut rng = StdRng::seed_from_u64(1281);
+		let mut g = random_grid(&mut rng, 12, 2, 4, 20);
+		g.random_valid_solution(&mut rng);
+		let snapshot = g.checkpoint();
+		assert_eq!(snapshot.towers(), &g.towers().collect::<HashSet<Point>>());
+		let before = g.clone();
+
+		for _ in 0..30 {
+			let p = Point::new(rng.gen_range(0..12), rng.gen_range(0..12));
+			if g.is_tower_present(p) {
+				g.remove_tower(p.x, p.y);
+			} else {
+				g.add_tower(p.x, p.y);
+			}
+		}
+		g.restore(&snapshot);
+		assert_eq!(g, before);
+		assert_eq!(g.penalty(), before.penalty());
+		assert_eq!(g.report(), before.report());
+
+		// Restoring onto an empty grid places every tower
+		g.remove_all_towers();
+		g.restore(&snapshot);
+		assert_eq!(g, before);
+	}
+
+	#[test]
+	fn uncovered_count_tracks_every_edit() {
+		let mut rng = StdRng::seed_from_u64(1283);
+		let mut g = random_grid(&mut rng, 12, 2, 4, 30);
+		assert_eq!(g.uncovered, 30);
+		for _ in 0..200 {
+			let p = Point::new(rng.gen_range(0..12), rng.gen_range(0..12));
+			let q = Point::new(rng.gen_range(0..12), rng.gen_range(0..12));
+			match (g.is_tower_present(p), g.is_tower_present(q)) {
+				(true, false) => g.move_tower(p, q),
+				(true, true) => g.remove_tower(p.x, p.y),
+				(false, _) => g.add_tower(p.x, p.y),
+			}
+			assert_eq!(g.uncovered, g.get_uncovered_cities().len());
+			assert_eq!(g.is_valid(), g.uncovered == 0);
+		}
+		g.remove_all_towers();
+		assert_eq!(g.uncovered, 30);
+		g.replace_all_towers(PointMap::default());
+		assert_eq!(g.uncovered, 30);
+	}
+
+	#[test]
+	fn grids_equal_by_configuration_cities_and_towers() {
+		let grid = solved_grid();
+		assert_eq!(grid, solved_grid());
+
+		// Placement order doesn't matter
+		let mut reordered = grid_with_cities(8, 1, 3, &[(6, 6), (2, 2), (1, 1)]);
+		for (x, y) in [(6, 6), (1, 1), (2, 2)] {
+			reordered.add_tower(x, y);
+		}
+		assert_eq!(grid, reordered);
+
+		let mut other = grid.clone();
+		other.move_tower(Point::new(6, 6), Point::new(6, 7));
+		assert_ne!(grid, other);
+		let mut other = grid.clone();
+		other.set_penalty_radius(4);
+		assert_ne!(grid, other);
+		let plain = grid_with_cities(8, 1, 3, &[(1, 1)]);
+		let mut other = plain.clone();
+		other.set_metric(Metric::Manhattan);
+		assert_ne!(plain, other);
+		let mut other = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 5)]);
+		for (x, y) in [(1, 1), (2, 2), (6, 6)] {
+			other.add_tower(x, y);
+		}
+		assert_ne!(grid, other);
+	}
+
+	#[test]
+	fn display_marks_towers_on_cities() {
+		let mut grid = grid_with_cities(3, 1, 1, &[(0, 0), (1, 1)]);
+		grid.add_tower(1, 1);
+		grid.add_tower(2, 2);
+		assert_eq!(grid.to_string(), "Penalty: 340\n· · t \n· ¢ · \nc · · \n");
+	}
+
+	#[test]
+	fn bench_fixtures_are_valid_solutions() {
+		for (size, dim) in [("small", 30), ("medium", 50), ("large", 100)] {
+			let input = format!("./benches/fixtures/{}.in", size);
+			let output = format!("./benches/fixtures/{}.out", size);
+			let mut grid = Grid::from_file(&input).unwrap();
+			assert_eq!(grid.dimension(), dim);
+			assert!(
+				!grid.validate_penalty_header(&output, false).unwrap(),
+				"stale header in {}",
+				output
+			);
+			grid.apply_solution(&output).unwrap();
+			assert!(grid.is_valid(), "{} doesn't cover {}", output, input);
+		}
+	}
+
+	#[test]
+	fn write_solution_with_force_always_writes() {
+		let grid = solved_grid();
+		let path = temp_path("write-force.out");
+		assert_eq!(grid.write_solution(&path, true).unwrap(), WriteOutcome::Written);
+		assert_eq!(grid.write_solution(&path, true).unwrap(), WriteOutcome::Written);
+
+		let mut worse = grid.clone();
+		worse.add_tower(0, 0);
+		assert_eq!(worse.write_solution(&path, true).unwrap(), WriteOutcome::Written);
+		assert_eq!(fs::read_to_string(&path).unwrap(), worse.output());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn add_towers_reports_each_failure() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		grid.add_tower(1, 1);
+		let p = Point::new;
+		let errors = grid.add_towers([p(2, 2), p(1, 1), p(8, 0), p(6, 6), p(2, 2), p(0, -1)]);
+		assert_eq!(
+			errors,
+			vec![
+				GridError::TowerPresent(p(1, 1)),
+				GridError::OffGrid(p(8, 0)),
+				GridError::TowerPresent(p(2, 2)),
+				GridError::OffGrid(p(0, -1)),
+			]
+		);
+		assert_eq!(grid, solved_grid());
+		assert!(grid.add_towers(Vec::new()).is_empty());
+	}
+
+	#[test]
+	fn try_edits_fail_without_changes() {
+		let mut grid = solved_grid();
+		let before = grid.clone();
+		let p = Point::new;
+		assert_eq!(grid.try_add_tower(1, 1), Err(GridError::TowerPresent(p(1, 1))));
+		assert_eq!(grid.try_add_tower(-1, 0), Err(GridError::OffGrid(p(-1, 0))));
+		assert_eq!(grid.try_remove_tower(3, 3), Err(GridError::NoTower(p(3, 3))));
+		assert_eq!(grid.try_remove_tower(0, 8), Err(GridError::OffGrid(p(0, 8))));
+		assert_eq!(grid.try_move_tower(p(3, 3), p(4, 4)), Err(GridError::NoTower(p(3, 3))));
+		assert_eq!(
+			grid.try_move_tower(p(1, 1), p(2, 2)),
+			Err(GridError::TowerPresent(p(2, 2)))
+		);
+		assert_eq!(grid.try_move_tower(p(1, 1), p(1, 8)), Err(GridError::OffGrid(p(1, 8))));
+		assert_eq!(grid, before);
+		assert_eq!(grid.penalty(), before.penalty());
+		assert!(grid.is_valid());
+
+		assert_eq!(grid.try_move_tower(p(6, 6), p(6, 7)), Ok(()));
+		assert_eq!(grid.try_remove_tower(6, 7), Ok(()));
+		assert_eq!(grid.try_add_tower(6, 6), Ok(()));
+		assert_eq!(grid, before);
+		assert_eq!(GridError::NoTower(p(3, 3)).to_string(), "there is no tower at (3, 3)");
+	}
+
+	#[test]
+	fn coverage_gain_counts_newly_covered_cities() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		// (1, 2) covers both (1, 1) and (2, 2), and a city's own cell covers it
+		assert_eq!(grid.coverage_gain(Point::new(1, 2)), 2);
+		assert_eq!(grid.coverage_gain(Point::new(6, 6)), 1);
+		assert_eq!(grid.coverage_gain(Point::new(4, 4)), 0);
+		grid.add_tower(1, 1);
+		assert_eq!(grid.coverage_gain(Point::new(1, 2)), 1);
+		assert_eq!(grid.coverage_gain(Point::new(1, 1)), 0);
+
+		let mut rng = StdRng::seed_from_u64(1308);
+		let mut g = random_grid(&mut rng, 10, 2, 3, 30);
+		for _ in 0..5 {
+			g.add_tower(rng.gen_range(0..10), rng.gen_range(0..10));
+			for x in 0..10 {
+				for y in 0..10 {
+					let p = Point::new(x, y);
+					let mut h = g.clone();
+					let expected = if h.try_add_tower(x, y).is_ok() {
+						g.get_uncovered_cities().len() - h.get_uncovered_cities().len()
+					} else {
+						0
+					};
+					assert_eq!(g.coverage_gain(p) as usize, expected, "gain of {}", p);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn singly_covered_cities_map_to_their_tower() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		assert!(grid.singly_covered_cities().is_empty());
+		for (x, y) in [(1, 1), (2, 1), (6, 6)] {
+			grid.add_tower(x, y);
+		}
+		let p = Point::new;
+		assert_eq!(
+			grid.singly_covered_cities(),
+			HashMap::from([(p(2, 2), p(2, 1)), (p(6, 6), p(6, 6))])
+		);
+		grid.add_tower(2, 3);
+		assert_eq!(grid.singly_covered_cities(), HashMap::from([(p(6, 6), p(6, 6))]));
+	}
+
+	#[test]
+	fn random_valid_solution_replaces_towers() {
+		let mut rng = StdRng::seed_from_u64(1310);
+		for metric in [Metric::Euclidean, Metric::Manhattan, Metric::Chebyshev] {
+			let mut g = random_grid(&mut rng, 10, 2, 3, 25);
+			g.set_metric(metric);
+			g.add_tower(0, 0);
+			g.random_valid_solution(&mut rng);
+			assert!(g.is_valid());
+			// At most one tower per city
+			assert!(g.tower_count() <= g.city_count());
+
+			let mut again = g.clone();
+			g.random_valid_solution(&mut StdRng::seed_from_u64(7));
+			again.random_valid_solution(&mut StdRng::seed_from_u64(7));
+			assert_eq!(g, again);
+		}
+		let mut empty = Grid::new(10, 2, 3);
+		empty.random_valid_solution(&mut rng);
+		assert_eq!(empty.tower_count(), 0);
+	}
+
+	#[test]
+	fn output_round_trips_through_a_reader() {
+		let input = "# from stdin\n3\n8\n1\n3\n1 1\n2 2\n6 6\n";
+		let mut grid = Grid::from_reader(io::Cursor::new(input)).unwrap();
+		assert_eq!(grid, grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]));
+		for (x, y) in [(6, 6), (2, 2), (1, 1)] {
+			grid.add_tower(x, y);
+		}
+		assert_eq!(
+			grid.output(),
+			format!("# Penalty = {}\n3\n1 1\n2 2\n6 6\n", grid.penalty())
+		);
+
+		let path = temp_path("round-trip.out");
+		fs::write(&path, grid.output()).unwrap();
+		assert_eq!(Grid::towers_from_file(&path).unwrap(), grid.towers().collect());
+		assert_eq!(api::get_penalty_from_file(&path).unwrap(), grid.penalty());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn gzip_files_round_trip() {
+		assert!(is_gzip_path("outputs/small/001.out.gz") && !is_gzip_path("outputs/small/001.out"));
+
+		let input_path = temp_path("gzip.in.gz");
+		let mut encoder = GzEncoder::new(File::create(&input_path).unwrap(), Compression::default());
+		encoder.write_all(b"3\n8\n1\n3\n1 1\n2 2\n6 6\n").unwrap();
+		encoder.finish().unwrap();
+		let mut grid = Grid::from_file(&input_path).unwrap();
+		assert_eq!(grid, grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]));
+
+		let output_path = temp_path("gzip.out.gz");
+		let solved = solved_grid();
+		solved.write_output(&output_path).unwrap();
+		// The file really is compressed
+		assert_eq!(&fs::read(&output_path).unwrap()[..2], &[0x1f, 0x8b]);
+		assert_eq!(api::get_penalty_from_file(&output_path).unwrap(), solved.penalty());
+		grid.apply_solution(&output_path).unwrap();
+		assert_eq!(grid, solved);
+		assert_eq!(
+			solved.write_solution(&output_path, false).unwrap(),
+			WriteOutcome::Unchanged
+		);
+		fs::remove_file(&input_path).unwrap();
+		fs::remove_file(&output_path).unwrap();
+	}
+
+	#[test]
+	fn validate_penalty_header_adds_missing_header() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		grid.add_tower(0, 0);
+		let path = temp_path("missing-header.out");
+		fs::write(&path, "3\n6 6\n2 2\n1 1\n").unwrap();
+		assert!(grid.validate_penalty_header(&path, true).unwrap());
+		assert_eq!(fs::read_to_string(&path).unwrap(), solved_grid().output());
+		// The grid itself is left alone
+		assert_eq!(grid.towers().collect::<Vec<Point>>(), vec![Point::new(0, 0)]);
+
+		fs::write(&path, "3\n6 6\n2 2\n").unwrap();
+		assert!(grid.validate_penalty_header(&path, true).is_err());
+		assert_eq!(fs::read_to_string(&path).unwrap(), "3\n6 6\n2 2\n");
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn wrapped_towers_cover_and_penalize_across_edges() {
+		let mut grid = grid_with_cities(10, 2, 3, &[(9, 9)]);
+		grid.set_wrap(true);
+		grid.add_tower(0, 0);
+		assert!(grid.is_valid());
+		grid.add_tower(8, 0);
+		assert_eq!(
+			grid.penalty_neighbors(Point::new(0, 0)),
+			Some(&[Point::new(8, 0)].into_iter().collect())
+		);
+
+		let mut flat = grid_with_cities(10, 2, 3, &[(9, 9)]);
+		flat.add_tower(0, 0);
+		assert!(!flat.is_valid());
+	}
+
+	#[test]
+	fn coverage_map_lists_sorted_covering_towers() {
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1), (2, 2), (6, 6)]);
+		for (x, y) in [(2, 1), (1, 1), (1, 2)] {
+			grid.add_tower(x, y);
+		}
+		let p = Point::new;
+		assert_eq!(
+			grid.coverage_map(),
+			HashMap::from([
+				(p(1, 1), vec![p(1, 1), p(1, 2), p(2, 1)]),
+				(p(2, 2), vec![p(1, 2), p(2, 1)]),
+				(p(6, 6), vec![]),
+			])
+		);
+	}
+
+	#[test]
+	fn penalty_breakdown_lists_most_expensive_first() {
+		let mut grid = Grid::new(8, 1, 3);
+		for (x, y) in [(6, 6), (2, 1), (1, 1), (1, 2)] {
+			grid.add_tower(x, y);
+		}
+		let breakdown = grid.penalty_breakdown();
+		let p = Point::new;
+		let cost = |w| 170. * Grid::tower_cost(w);
+		assert_eq!(
+			breakdown,
+			vec![
+				(p(1, 1), 2, cost(2)),
+				(p(1, 2), 2, cost(2)),
+				(p(2, 1), 2, cost(2)),
+				(p(6, 6), 0, 170.)
+			]
+		);
+		let total: f64 = breakdown.iter().map(|b| b.2).sum();
+		assert_eq!(api::round(total), grid.penalty());
+		assert!(Grid::new(8, 1, 3).penalty_breakdown().is_empty());
+	}
+
+	#[test]
+	fn lp_solves_fail_when_the_model_cant_be_written() {
+		let model = temp_path("missing-dir/model.lp");
+		let mut grid = solved_grid();
+		grid.remove_all_towers();
+		let e = grid.lp_solve(1, None, false, Some(&model)).unwrap_err();
+		assert!(e.to_string().contains("Unable to write LP model"), "{}", e);
+		let e = grid.random_lp_solve(1, 7, &[], Some(&model)).unwrap_err();
+		assert!(e.to_string().contains("Unable to write LP model"), "{}", e);
+		assert_eq!(grid.tower_count(), 0);
+	}
+
+	#[test]
+	fn concurrent_write_solution_keeps_the_best() {
+		let dir = temp_path("concurrent");
+		fs::create_dir_all(&dir).unwrap();
+		let path = format!("{}/001.out", dir);
+		// Solutions with increasing penalty, each with one more extra tower
+		let mut grids = vec![solved_grid()];
+		for t in [(0, 0), (7, 7), (0, 7), (7, 0), (4, 4)] {
+			let mut worse = grids.last().unwrap().clone();
+			worse.add_tower(t.0, t.1);
+			assert!(worse.penalty() > grids.last().unwrap().penalty());
+			grids.push(worse);
+		}
+		let outputs: Vec<String> = grids.iter().map(|g| g.output()).collect();
+
+		let writing = std::sync::atomic::AtomicBool::new(true);
+		std::thread::scope(|s| {
+			// Every file a reader sees is complete, and no worse than the last one
+			let reader = s.spawn(|| {
+				let mut last = f64::INFINITY;
+				while writing.load(Ordering::Relaxed) {
+					if let Ok(contents) = fs::read_to_string(&path) {
+						let i = outputs.iter().position(|o| *o == contents).expect("partial solution");
+						assert!(grids[i].penalty() <= last, "{} replaced {}", grids[i].penalty(), last);
+						last = grids[i].penalty();
+					}
+				}
+			});
+			let writers: Vec<_> = (0..grids.len())
+				.map(|i| {
+					let grids = &grids;
+					let path = &path;
+					s.spawn(move || {
+						for j in 0..20 {
+							let g = &grids[(i + j) % grids.len()];
+							g.write_solution(path, false).unwrap();
+						}
+					})
+				})
+				.collect();
+			for w in writers {
+				w.join().unwrap();
+			}
+			writing.store(false, Ordering::Relaxed);
+			reader.join().unwrap();
+		});
+		assert_eq!(fs::read_to_string(&path).unwrap(), outputs[0]);
+		let entries: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+		assert_eq!(entries, vec!["001.out"]);
+		fs::remove_dir_all(&dir).unwrap();
 	}
 }
@@ -0,0 +1,19 @@
+// Used to ignore unused code warnings.
+#![allow(dead_code)]
+
+// extern crates
+#[macro_use]
+extern crate lazy_static;
+extern crate num_cpus;
+
+pub mod annealing;
+pub mod api;
+pub mod edit;
+pub mod exact;
+pub mod genetic;
+pub mod grid;
+pub mod logger;
+pub mod lp;
+pub mod point;
+pub mod render;
+pub mod solvers;
@@ -0,0 +1,54 @@
+use colored::Colorize;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Prints the log records of this crate to stderr, so stdout is left for
+/// command output. Info records are printed as is, warnings and errors with a
+/// prefix like the rest of the command line, and debug and trace records
+/// dimmed.
+struct Logger;
+
+impl Log for Logger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		// Skip the records of dependencies (e.g. reqwest), which are noisy at
+		// debug and trace
+		metadata.level() <= log::max_level() && metadata.target().starts_with("pengwin")
+	}
+
+	fn log(&self, record: &Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+		match record.level() {
+			Level::Error => eprintln!("{} {}", "ERROR:".red().bold(), record.args()),
+			Level::Warn => eprintln!("{} {}", "WARNING:".red().bold(), record.args()),
+			Level::Info => eprintln!("{}", record.args()),
+			Level::Debug | Level::Trace => eprintln!("{}", record.args().to_string().dimmed()),
+		}
+	}
+
+	fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+/// Installs the logger, printing records up to the given level. Panics if a
+/// logger is already installed.
+pub fn init(level: LevelFilter) {
+	log::set_logger(&LOGGER).expect("Logger already initialized");
+	log::set_max_level(level);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn logs_only_this_crate_up_to_the_max_level() {
+		let metadata = |level, target| Metadata::builder().level(level).target(target).build();
+		log::set_max_level(LevelFilter::Info);
+		assert!(Logger.enabled(&metadata(Level::Info, "pengwin::solvers")));
+		assert!(Logger.enabled(&metadata(Level::Error, "pengwin")));
+		assert!(!Logger.enabled(&metadata(Level::Debug, "pengwin::solvers")));
+		assert!(!Logger.enabled(&metadata(Level::Error, "reqwest::connect")));
+	}
+}
@@ -1,10 +1,14 @@
 use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
 use good_lp::constraint::Constraint;
 use good_lp::variable::ProblemVariables;
-use good_lp::{coin_cbc, constraint, variable, variables, Expression, Solution, SolverModel, Variable};
+use good_lp::{
+	coin_cbc, constraint, variable, variables, Expression, ResolutionError, Solution, SolverModel, Variable,
+};
 
-use crate::point::Point;
+use crate::point::{Metric, Point};
 
 /// Idea: Because penalty is monotonic ish, can try to minimize a linear penalty
 /// to use LP.
@@ -30,44 +34,86 @@ use crate::point::Point;
 ///
 /// total number of variables is on the order of R^2 * d^2.
 
+/// How a solve of the LP ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LpStatus {
+	// The solution is proven optimal.
+	Optimal,
+	// Stopped at the time limit with the best solution found so far.
+	TimedOut,
+	// Stopped early for some other reason, e.g. a node limit.
+	Stopped,
+}
+
+/// Towers placed by solving the LP, and how good the solution is known to be.
+#[derive(Debug, Clone)]
+pub struct LpSolution {
+	pub towers:    HashSet<Point>,
+	pub status:    LpStatus,
+	// Objective value of the solution.
+	pub objective: f64,
+	// Relative gap between the objective and the best bound CBC proved, if known.
+	pub gap:       Option<f64>,
+}
+
 pub struct GridProblem {
 	vars:          ProblemVariables,
-	t:             Vec<Vec<Variable>>,
+	// t_ij, or None if a tower at (i, j) wouldn't cover any city, in which case
+	// it is never placed and treated as the constant 0.
+	t:             Vec<Vec<Option<Variable>>>,
+	// Column of each t_ij in the CBC model, i.e. its index among the variables.
+	t_columns:     Vec<Vec<Option<usize>>>,
 	constraints:   Vec<Constraint>,
 	total_penalty: Expression,
 	dim:           u8,
 	r_s:           u8,
 	r_p:           u8,
+	metric:        Metric,
+	wrap:          bool,
 	max_time:      u32, // in seconds
 	console_log:   u8,
 	seed:          u32,
+	// Whether the t_ij are relaxed to 0 <= t_ij <= 1 instead of binary.
+	relaxed:       bool,
 }
 
 impl GridProblem {
-	/// Adds a new tower variable t_ij at the given point (i, j) to the LP.
-	fn add_tower_variable(&mut self, _tower: Point) -> Variable {
+	/// Adds a new tower variable t_ij at the given point (i, j) to the LP,
+	/// returning it along with its column.
+	fn add_tower_variable(&mut self, _tower: Point) -> (Variable, usize) {
 		// let name = format!("t_{}_{}", tower.x, tower.y);
-		let is_tower = self.vars.add(variable().binary()); //.name(name));
-		is_tower
+		let column = self.vars.len();
+		let is_tower = if self.relaxed {
+			self.vars.add(variable().min(0).max(1))
+		} else {
+			self.vars.add(variable().binary()) //.name(name));
+		};
+		(is_tower, column)
 	}
 
 	/// Adds the penalty variable p_ijkl for point ij and tower kl to the LP.
+	/// Pairs where either tower can't be placed always have p_ijkl = 0, so they
+	/// are skipped.
 	fn add_penalty_variables(&mut self) {
 		for i in 0..(self.dim as usize) {
 			for j in 0..(self.dim as usize) {
+				let t_ij = match self.t[i][j] {
+					Some(t_ij) => t_ij,
+					None => continue,
+				};
 				let p = Point::new(i as i32, j as i32);
-				let coverage = Point::points_within_radius(p, self.r_p, self.dim).unwrap();
+				let coverage = Point::points_within_radius(p, self.r_p, self.dim, self.metric, self.wrap).unwrap();
 				for point in coverage {
-					let k = point.x as usize;
-					let l = point.y as usize;
+					let t_kl = match self.t[point.x as usize][point.y as usize] {
+						Some(t_kl) => t_kl,
+						None => continue,
+					};
 
 					// let name = format!("p_{}_{}_{}_{}", i, j, k, l);
 					let p_ijkl = self.vars.add(variable().binary()); //.name(name));
-					self.constraints.push(constraint!(p_ijkl <= self.t[i][j]));
-					self.constraints.push(constraint!(p_ijkl <= self.t[k][l]));
-					self
-						.constraints
-						.push(constraint!(p_ijkl >= self.t[i][j] + self.t[k][l] - 1));
+					self.constraints.push(constraint!(p_ijkl <= t_ij));
+					self.constraints.push(constraint!(p_ijkl <= t_kl));
+					self.constraints.push(constraint!(p_ijkl >= t_ij + t_kl - 1));
 
 					self.total_penalty += p_ijkl;
 				}
@@ -76,62 +122,224 @@ impl GridProblem {
 	}
 
 	/// Adds the city coverage constraints to the LP.
-	fn add_city_constraints(&mut self, cities: HashSet<Point>) {
+	fn add_city_constraints(&mut self, cities: &HashSet<Point>) {
+		let mut cities: Vec<Point> = cities.iter().copied().collect();
+		cities.sort();
 		for c in cities {
-			let coverage = Point::points_within_radius(c, self.r_s, self.dim).unwrap();
+			let coverage = Point::points_within_radius(c, self.r_s, self.dim, self.metric, self.wrap).unwrap();
 			let mut sum = Expression::with_capacity(coverage.len());
-			for point in coverage {
-				sum.add_mul(1, self.t[point.x as usize][point.y as usize]);
+			for point in coverage.iter().chain(std::iter::once(&c)) {
+				if let Some(t) = self.t[point.x as usize][point.y as usize] {
+					sum.add_mul(1, t);
+				}
 			}
 			self.constraints.push(sum.geq(1));
 		}
 	}
 
 	/// Creates a new grid for randomization solving.
-	pub fn new_randomized(dim: u8, r_s: u8, r_p: u8, cities: HashSet<Point>, max_time: u32, seed: u32) -> Self {
+	#[allow(clippy::too_many_arguments)]
+	pub fn new_randomized(
+		dim: u8,
+		r_s: u8,
+		r_p: u8,
+		metric: Metric,
+		wrap: bool,
+		cities: HashSet<Point>,
+		max_time: u32,
+		seed: u32,
+	) -> Self {
+		GridProblem::new_cover(dim, r_s, r_p, metric, wrap, cities, max_time, seed, false)
+	}
+
+	/// Creates the LP relaxation of covering every city with as few towers as
+	/// possible, ignoring penalty: towers may be partially placed, with
+	/// 0 <= t_ij <= 1. Its optimum is a lower bound on the number of towers of
+	/// any solution.
+	pub fn new_relaxed_cover(
+		dim: u8,
+		r_s: u8,
+		metric: Metric,
+		wrap: bool,
+		cities: HashSet<Point>,
+		max_time: u32,
+	) -> Self {
+		GridProblem::new_cover(dim, r_s, 0, metric, wrap, cities, max_time, 69420, true)
+	}
+
+	/// Creates an LP minimizing the number of towers covering every city.
+	#[allow(clippy::too_many_arguments)]
+	fn new_cover(
+		dim: u8,
+		r_s: u8,
+		r_p: u8,
+		metric: Metric,
+		wrap: bool,
+		cities: HashSet<Point>,
+		max_time: u32,
+		seed: u32,
+		relaxed: bool,
+	) -> Self {
 		let mut lp = GridProblem {
 			vars: variables![],
 			constraints: vec![],
 			t: vec![],
+			t_columns: vec![],
 			dim,
 			r_s,
 			r_p,
+			metric,
+			wrap,
 			total_penalty: 0.into(),
 			max_time,
 			console_log: 0,
 			seed,
+			relaxed,
 		};
 
+		// A tower only helps if it covers some city, so only add variables for
+		// positions in the coverage of some city
+		let mut potential_towers: HashSet<Point> = HashSet::new();
+		for &c in cities.iter() {
+			potential_towers.extend(Point::points_within_radius(c, r_s, dim, metric, wrap).unwrap());
+			// A tower on the city's own cell also covers it
+			potential_towers.insert(c);
+		}
+		// Sorted so the model (and so the solution for a given seed) doesn't
+		// depend on hash order
+		let mut potential_towers: Vec<Point> = potential_towers.into_iter().collect();
+		potential_towers.sort();
+
 		// add variables for each tower
-		let dummy = lp.add_tower_variable(Point::new(-69420, -69420));
-		lp.t = vec![vec![dummy; dim.into()]; dim.into()];
-		for i in 0..dim {
-			for j in 0..dim {
-				let potential_tower = Point::new(i as i32, j as i32);
-				lp.t[i as usize][j as usize] = lp.add_tower_variable(potential_tower);
-				lp.total_penalty += lp.t[i as usize][j as usize];
-			}
+		lp.t = vec![vec![None; dim.into()]; dim.into()];
+		lp.t_columns = vec![vec![None; dim.into()]; dim.into()];
+		for potential_tower in potential_towers {
+			let (t_ij, column) = lp.add_tower_variable(potential_tower);
+			let (i, j) = (potential_tower.x as usize, potential_tower.y as usize);
+			lp.t[i][j] = Some(t_ij);
+			lp.t_columns[i][j] = Some(column);
+			lp.total_penalty += t_ij;
 		}
 
 		// ignores penalty constraints for randomization
 
 		// add city constraints
-		lp.add_city_constraints(cities);
+		lp.add_city_constraints(&cities);
 
 		lp
 	}
 
 	/// Creates and returns a new GridProblem LP.
-	pub fn new(dim: u8, r_s: u8, r_p: u8, cities: HashSet<Point>, max_time: u32) -> Self {
-		let mut lp: GridProblem = GridProblem::new_randomized(dim, r_s, r_p, cities, max_time, 69420);
-		lp.console_log = 1;
+	pub fn new(dim: u8, r_s: u8, r_p: u8, metric: Metric, wrap: bool, cities: HashSet<Point>, max_time: u32) -> Self {
+		let mut lp: GridProblem = GridProblem::new_randomized(dim, r_s, r_p, metric, wrap, cities, max_time, 69420);
 		lp.add_penalty_variables();
 
 		lp
 	}
 
-	/// Assumes everything (variables, constraints) has been added already
-	fn solution(self) -> impl Solution {
+	/// Sets whether CBC prints its log while solving. Off by default, since on
+	/// large inputs it floods the terminal.
+	pub fn with_console_log(mut self, verbose: bool) -> Self {
+		self.console_log = verbose as u8;
+		self
+	}
+
+	/// Limits the total number of towers placed to at most k. The LP is
+	/// infeasible if k towers are not enough to cover every city.
+	pub fn with_max_towers(mut self, k: usize) -> Self {
+		let num_towers: Expression = self.t.iter().flatten().flatten().copied().sum();
+		self.constraints.push(num_towers.leq(k as f64));
+		self
+	}
+
+	/// Excludes each of the given tower configurations, and any configuration
+	/// containing one, from the solutions of the LP. This is a no-good cut
+	/// sum(t_ij for ij in S) <= |S| - 1 for each S.
+	pub fn with_excluded_solutions(mut self, solutions: &[Vec<Point>]) -> Self {
+		for towers in solutions {
+			let vars: Vec<Variable> = towers
+				.iter()
+				.filter_map(|t| self.t[t.x as usize][t.y as usize])
+				.collect();
+			if vars.is_empty() {
+				continue;
+			}
+			let n = vars.len();
+			let sum: Expression = vars.into_iter().sum();
+			self.constraints.push(sum.leq(n as f64 - 1.));
+		}
+		self
+	}
+
+	/// Writes the LP to path in CPLEX LP format, which CBC (and most other
+	/// solvers) can read back, e.g. `cbc model.lp solve`. Variables are named by
+	/// their column, so the t_ij are v0 up to the number of potential towers in
+	/// sorted order, followed by the p_ijkl.
+	pub fn export_model(&self, path: &str) -> io::Result<()> {
+		// good_lp writes negative terms as "+ -1 v3", which not every reader
+		// accepts
+		let display = |s: String| s.replace("+ -", "- ");
+
+		let mut w = BufWriter::new(File::create(path)?);
+		writeln!(
+			w,
+			"\\ dim {}, r_s {}, r_p {}, {:?}{}",
+			self.dim,
+			self.r_s,
+			self.r_p,
+			self.metric,
+			if self.wrap { ", wrapping" } else { "" }
+		)?;
+		writeln!(w, "Minimize")?;
+		writeln!(
+			w,
+			" penalty: {}",
+			display(self.vars.display(&self.total_penalty).to_string())
+		)?;
+		writeln!(w, "Subject To")?;
+		for (i, c) in self.constraints.iter().enumerate() {
+			writeln!(w, " c{}: {}", i, display(self.vars.display(c).to_string()))?;
+		}
+		if self.relaxed {
+			writeln!(w, "Bounds")?;
+			for i in 0..self.vars.len() {
+				writeln!(w, " 0 <= v{} <= 1", i)?;
+			}
+		} else {
+			// Every variable is binary
+			writeln!(w, "Binaries")?;
+			for start in (0..self.vars.len()).step_by(10) {
+				let names: Vec<String> = (start..(start + 10).min(self.vars.len()))
+					.map(|i| format!("v{}", i))
+					.collect();
+				writeln!(w, " {}", names.join(" "))?;
+			}
+		}
+		writeln!(w, "End")?;
+		w.flush()
+	}
+
+	/// Solves the LP to optimality and returns its objective value, e.g. the
+	/// number of (fractional) towers of a relaxed cover.
+	pub fn objective_value(self) -> Result<f64, ResolutionError> {
+		let objective = self.total_penalty.clone();
+		let mut model = self.vars.minimise(self.total_penalty).using(coin_cbc);
+		for c in self.constraints {
+			model = model.with(c);
+		}
+		model.set_parameter("sec", &self.max_time.to_string());
+		model.set_parameter("log", &self.console_log.to_string());
+
+		Ok(model.solve()?.eval(&objective))
+	}
+
+	/// Solves the LP and returns the towers placed along with how the solve
+	/// ended, or the reason the solver failed (e.g. infeasible under a tower
+	/// cap). Assumes everything (variables, constraints) has been added already.
+	///
+	/// If CBC hits the time limit, the towers are the best solution it found so
+	/// far, which may not cover every city if it found none.
+	pub fn tower_solution(self) -> Result<LpSolution, ResolutionError> {
 		let mut model = self.vars.minimise(self.total_penalty).using(coin_cbc);
 		for c in self.constraints {
 			model = model.with(c);
@@ -144,28 +352,186 @@ impl GridProblem {
 		// model.set_parameter("cutoff", "20");
 		// // model.set_parameter("node", "fewest");
 		// // model.set_parameter("multiple", "3");
-		// model.set_parameter("sec", &self.max_time.to_string());
+		model.set_parameter("sec", &self.max_time.to_string());
 
 		model.set_parameter("randomSeed", &self.seed.to_string());
 		model.set_parameter("randomC", &self.seed.to_string());
 		// model.set_parameter("randomI", "on");
-		model.set_parameter("log", &self.console_log.to_string()); // comment for less output
-		model.solve().unwrap()
-	}
+		model.set_parameter("log", &self.console_log.to_string());
+
+		// Solve the CBC model directly, since good_lp's solve throws away the best
+		// solution so far when CBC stops at the time limit
+		let solution = model.as_inner_mut().solve();
+		let raw = solution.raw();
+		if raw.is_proven_infeasible() {
+			return Err(ResolutionError::Infeasible);
+		} else if raw.is_continuous_unbounded() {
+			return Err(ResolutionError::Unbounded);
+		} else if raw.is_abandoned() {
+			return Err(ResolutionError::Other("Abandoned"));
+		}
+
+		let status = if raw.is_proven_optimal() {
+			LpStatus::Optimal
+		} else if raw.is_seconds_limit_reached() {
+			LpStatus::TimedOut
+		} else {
+			LpStatus::Stopped
+		};
+		let objective = raw.obj_value();
+		let gap = match status {
+			LpStatus::Optimal => Some(0.),
+			_ if objective.abs() > 0. => Some((objective - raw.best_possible_value()).abs() / objective.abs()),
+			_ => None,
+		};
 
-	pub fn tower_solution(self) -> HashSet<Point> {
 		const TOL: f64 = 1e-6;
+		let values = raw.col_solution();
 		let d = self.dim as usize;
-		let t = (&self.t).clone();
-		let solution = self.solution();
-		let mut result = HashSet::new();
+		let mut towers = HashSet::new();
 		for i in 0..d {
 			for j in 0..d {
-				if (solution.value(t[i][j]) - 1.).abs() < TOL {
-					result.insert(Point::new(i as i32, j as i32));
+				if let Some(column) = self.t_columns[i][j] {
+					if (values[column] - 1.).abs() < TOL {
+						towers.insert(Point::new(i as i32, j as i32));
+					}
 				}
 			}
 		}
-		result
+		Ok(LpSolution {
+			towers,
+			status,
+			objective,
+			gap,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+	use crate::grid::tests::temp_path;
+
+	/// Returns the LP as written by export_model.
+	fn exported(problem: &GridProblem, name: &str) -> String {
+		let path = temp_path(name);
+		problem.export_model(&path).unwrap();
+		let model = fs::read_to_string(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		model
+	}
+
+	#[test]
+	fn max_towers_adds_a_cap() {
+		let cities = HashSet::from([Point::new(1, 1), Point::new(6, 6)]);
+		let uncapped = GridProblem::new(8, 1, 2, Metric::Euclidean, false, cities.clone(), 10);
+		let capped = GridProblem::new(8, 1, 2, Metric::Euclidean, false, cities, 10).with_max_towers(3);
+		assert_eq!(capped.constraints.len(), uncapped.constraints.len() + 1);
+
+		let model = exported(&capped, "lp-max-towers.lp");
+		let cap = model.lines().find(|l| l.contains("<= 3")).expect("no tower cap");
+		// Every tower variable appears in the cap
+		assert_eq!(cap.matches('v').count(), 10);
+	}
+
+	#[test]
+	fn tower_columns_match_tower_variables() {
+		let cities = HashSet::from([Point::new(1, 1), Point::new(6, 6), Point::new(2, 5)]);
+		let problem = GridProblem::new(8, 1, 2, Metric::Euclidean, false, cities, 10);
+		let mut columns = HashSet::new();
+		for i in 0..8 {
+			for j in 0..8 {
+				assert_eq!(problem.t[i][j].is_some(), problem.t_columns[i][j].is_some());
+				if let Some(column) = problem.t_columns[i][j] {
+					assert!(column < problem.vars.len());
+					assert!(columns.insert(column), "column {} is shared", column);
+				}
+			}
+		}
+		assert_eq!(columns.len(), 15);
+	}
+
+	#[test]
+	fn city_constraints_include_own_cell() {
+		// With service radius 0 a city can only be covered by a tower on it
+		let cities = HashSet::from([Point::new(1, 1), Point::new(6, 6)]);
+		let problem = GridProblem::new(8, 0, 2, Metric::Euclidean, false, cities, 10);
+		assert!(problem.t[1][1].is_some() && problem.t[6][6].is_some());
+		// good_lp writes sum >= 1 as -sum <= -1
+		let model = exported(&problem, "lp-own-cell.lp");
+		let covers: Vec<&str> = model.lines().filter(|l| l.contains("<= -1")).collect();
+		assert_eq!(covers.len(), 2);
+		assert!(covers.iter().all(|l| l.matches('v').count() == 1));
+
+		// A corner city is covered by its own cell and two neighbors
+		let problem = GridProblem::new(8, 1, 2, Metric::Euclidean, false, HashSet::from([Point::new(0, 0)]), 10);
+		let model = exported(&problem, "lp-own-cell-corner.lp");
+		let cover = model.lines().find(|l| l.contains("<= -1")).unwrap();
+		assert_eq!(cover.matches('v').count(), 3);
+	}
+
+	#[test]
+	fn export_model_writes_lp_format() {
+		let cities = HashSet::from([Point::new(1, 1), Point::new(2, 2)]);
+		let model = exported(
+			&GridProblem::new(8, 1, 2, Metric::Manhattan, true, cities.clone(), 10),
+			"lp-export.lp",
+		);
+		let lines: Vec<&str> = model.lines().collect();
+		assert_eq!(lines[0], "\\ dim 8, r_s 1, r_p 2, Manhattan, wrapping");
+		assert_eq!(lines[1], "Minimize");
+		assert!(lines[2].starts_with(" penalty: "));
+		assert_eq!(lines[3], "Subject To");
+		assert_eq!(*lines.last().unwrap(), "End");
+		assert!(model.contains("\nBinaries\n") && !model.contains("Bounds"));
+		assert!(!model.contains("+ -"));
+		// Every variable is declared binary, ten to a line
+		let binaries = model.split("Binaries\n").nth(1).unwrap();
+		let num_binaries: usize = binaries
+			.lines()
+			.map(|l| l.split_whitespace().filter(|v| v.starts_with('v')).count())
+			.sum();
+		assert!(num_binaries > 0);
+		assert!(binaries
+			.lines()
+			.all(|l| l == "End" || l.split_whitespace().count() <= 10));
+
+		let relaxed = exported(
+			&GridProblem::new_relaxed_cover(8, 1, Metric::Euclidean, false, cities, 10),
+			"lp-export-relaxed.lp",
+		);
+		assert!(relaxed.starts_with("\\ dim 8, r_s 1, r_p 0, Euclidean\n"));
+		assert!(relaxed.contains("\nBounds\n 0 <= v0 <= 1\n") && !relaxed.contains("Binaries"));
+	}
+
+	#[test]
+	fn excluded_solutions_add_no_good_cuts() {
+		let cities = HashSet::from([Point::new(1, 1), Point::new(6, 6)]);
+		let plain = GridProblem::new(8, 1, 2, Metric::Euclidean, false, cities.clone(), 10);
+		let excluded = vec![
+			vec![Point::new(1, 1), Point::new(6, 6)],
+			vec![Point::new(0, 1), Point::new(6, 5), Point::new(6, 7)],
+			// A tower that can't be placed is never part of a solution
+			vec![Point::new(4, 4)],
+		];
+		let problem = GridProblem::new(8, 1, 2, Metric::Euclidean, false, cities, 10).with_excluded_solutions(&excluded);
+		assert_eq!(problem.constraints.len(), plain.constraints.len() + 2);
+
+		let model = exported(&problem, "lp-excluded.lp");
+		// The cuts are the last constraints
+		let constraints: Vec<&str> = model.lines().filter(|l| l.starts_with(" c")).collect();
+		let cuts = &constraints[constraints.len() - 2..];
+		assert!(
+			cuts[0].ends_with("<= 1") && cuts[0].matches('v').count() == 2,
+			"{}",
+			cuts[0]
+		);
+		assert!(
+			cuts[1].ends_with("<= 2") && cuts[1].matches('v').count() == 3,
+			"{}",
+			cuts[1]
+		);
 	}
 }
@@ -1,40 +1,25 @@
 // Used to ignore unused code warnings.
 #![allow(dead_code)]
 
-// extern crates
-#[macro_use]
-extern crate lazy_static;
-extern crate num_cpus;
-
-mod annealing;
-mod api;
-mod grid;
-mod lp;
-mod point;
-mod solvers;
 use std::collections::HashSet;
-use std::fs;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use api::{get_api_result, is_score_worse_than_leader, InputType};
 use clap::{Parser, Subcommand};
-use grid::Grid;
-use phf::phf_map;
-use solvers::*;
-
-// Define solver functions
-type SolverFn = fn(&mut Grid, &str);
-
-
-static SOLVERS: phf::Map<&'static str, SolverFn> = phf_map! {
-	"benchmark" => benchmark_greedy,
-	"greedy" => greedy,
-	"rlp" => randomize_valid_solution_with_lp_threaded,
-	"hillclimb" => hillclimb,
-	"rand_hillclimb" => rand_hillclimb_threaded,
-	"sort_and_read_penalty" => sort_and_read_penalty,
-	"annealing" => simulated_annealing,
+use colored::Colorize;
+use log::{debug, error, info, warn, LevelFilter};
+use pengwin::api::{
+	self, get_api_result, is_score_better_than_leader, is_score_worse_than_leader, InputType, LeaderboardCache,
 };
+use pengwin::grid::{input_stem, Grid, GridParseError, WriteOutcome};
+use pengwin::point::{self, Point};
+use pengwin::render::RenderOptions;
+use pengwin::solvers::*;
+use pengwin::{edit, logger};
 
 
 // Define command line arguments
@@ -43,6 +28,50 @@ static SOLVERS: phf::Map<&'static str, SolverFn> = phf_map! {
 struct Args {
 	#[clap(subcommand)]
 	command: Commands,
+
+	/// Base url of the scoreboard server (api, solve -w)
+	#[clap(long, global = true, env = "PENGUIN_SERVER", default_value = api::DEFAULT_SERVER)]
+	server: String,
+
+	/// Directory of the inputs, laid out as <size>/<id>.in (solve, verify,
+	/// benchmark, edit, stats)
+	#[clap(long, global = true, default_value = "./inputs")]
+	input_dir: PathBuf,
+
+	/// Directory of the outputs, laid out like the inputs (solve, verify,
+	/// benchmark, edit, api, regress, stats)
+	#[clap(long, global = true, default_value = "./outputs")]
+	output_dir: PathBuf,
+
+	/// Print more solver progress, or even more with -vv
+	#[clap(short = 'v', global = true, parse(from_occurrences))]
+	verbosity: u8,
+
+	/// Only print warnings and errors
+	#[clap(short, long, global = true, conflicts_with = "verbosity")]
+	quiet: bool,
+}
+
+impl Args {
+	/// Returns the input and output paths of each of the given inputs, exiting
+	/// if a size directory can't be read.
+	fn paths(&self, inputs: &[Inputs]) -> Vec<Vec<(PathBuf, PathBuf)>> {
+		inputs
+			.iter()
+			.map(|i| match i.paths(&self.input_dir, &self.output_dir) {
+				Ok(paths) => paths,
+				Err(e) => {
+					println!("{} {}", "ERROR:".red().bold(), e);
+					std::process::exit(1);
+				}
+			})
+			.collect()
+	}
+
+	/// Returns the input and output path of a single input.
+	fn single_path(&self, input: &Inputs) -> (PathBuf, PathBuf) {
+		self.paths(std::slice::from_ref(input)).remove(0).remove(0)
+	}
 }
 
 
@@ -52,11 +81,132 @@ enum Commands {
 	#[clap(alias = "ls")]
 	List,
 
+	/// Generate the preprocessed points within radii
+	#[clap(alias = "pre")]
+	Preprocess,
+
 	/// Query the API
 	#[clap(alias = "q")]
 	Api {
 		#[clap(default_value = "s", parse(try_from_str=api::input_size_from_string))]
 		size: InputType,
+
+		/// Number of leaderboard scores to fetch at once
+		#[clap(long, default_value = "5")]
+		concurrency: usize,
+
+		/// Fetch every leaderboard score again instead of using the cache
+		#[clap(long)]
+		refresh: bool,
+
+		/// Seconds a cached leaderboard score is used for
+		#[clap(long, default_value = "3600")]
+		cache_ttl: u64,
+
+		/// Also write the comparison to a .json or .csv file
+		#[clap(long, parse(try_from_str=get_report_path))]
+		output: Option<PathBuf>,
+	},
+
+	/// Compare our outputs against a saved baseline, failing if any regressed
+	Regress {
+		/// Directory laid out like ./outputs to compare against
+		baseline_dir: PathBuf,
+
+		#[clap(default_value = "s", parse(try_from_str=api::input_size_from_string))]
+		size: InputType,
+	},
+
+	/// Summarize the penalties of our outputs
+	Stats {
+		#[clap(default_value = "s", parse(try_from_str=api::input_size_from_string))]
+		size: InputType,
+
+		/// Number of highest penalty outputs to list
+		#[clap(long, default_value = "10")]
+		worst: usize,
+
+		/// Also compute a lower bound on the penalty of each input, to show how
+		/// far from optimal the outputs can be (solves an LP per input)
+		#[clap(long)]
+		bound: bool,
+	},
+
+	/// Compare the penalties of two directories of solutions
+	Diff {
+		/// Directory of the old solutions, laid out like ./outputs or holding .out
+		/// files directly
+		old_dir: PathBuf,
+
+		/// Directory of the new solutions, laid out like old_dir
+		new_dir: PathBuf,
+	},
+
+	/// Check that solutions cover every city and have the right penalty header
+	Verify {
+		/// Inputs to verify <size>/<id>
+		#[clap(required = true, parse(try_from_str=get_inputs))]
+		paths: Vec<Inputs>,
+
+		/// Also draw each solution as a png next to its .out file
+		#[clap(long)]
+		render: bool,
+
+		/// Also print a lower bound on the penalty of each input and the gap to
+		/// it (solves an LP per input)
+		#[clap(long)]
+		bound: bool,
+
+		/// Write which towers cover each city of every solution to this json
+		/// file, keyed by input
+		#[clap(long)]
+		coverage: Option<PathBuf>,
+
+		/// Also print what each tower adds to the penalty, most expensive first
+		#[clap(long)]
+		verbose: bool,
+	},
+
+	/// Recompute the penalty of solutions, rewriting (with sorted towers) those
+	/// whose penalty header is wrong
+	Normalize {
+		/// Inputs whose solutions to normalize <size>/<id>
+		#[clap(required = true, parse(try_from_str=get_inputs))]
+		paths: Vec<Inputs>,
+	},
+
+	/// Run several solvers on the same inputs and compare their results,
+	/// without touching ./outputs
+	#[clap(alias = "bench", arg_required_else_help = true)]
+	Benchmark {
+		/// Inputs to benchmark on <size>/<id>
+		#[clap(required = true, parse(try_from_str=get_inputs))]
+		paths: Vec<Inputs>,
+
+		/// Solver to compare, can be given several times (defaults to all)
+		#[clap(long = "solver", short, parse(try_from_str=get_solver))]
+		solvers: Vec<&'static dyn Solver>,
+
+		/// Seconds each solver may spend per input (rlp) or per LP solve (lp,
+		/// pipeline)
+		#[clap(long, default_value = "10")]
+		time_limit: u64,
+
+		/// Start each solver from a copy of the existing output, so solvers that
+		/// improve an output (hillclimb, tabu, ...) can be compared
+		#[clap(long)]
+		from_existing: bool,
+
+		/// Seed for the random number generators, see solve --seed
+		#[clap(long)]
+		seed: Option<u64>,
+	},
+
+	/// Edit the solution for an input by hand
+	Edit {
+		/// Input to edit <size>/<id>
+		#[clap(parse(try_from_str=get_single_input))]
+		input: Inputs,
 	},
 
 	/// Run a solver on several specified inputs
@@ -64,18 +214,102 @@ enum Commands {
 	Solve {
 		/// Solver to use
 		#[clap(short, parse(try_from_str=get_solver))]
-		solver: SolverFn,
+		solver: &'static dyn Solver,
 
 		/// Inputs to the solver <size>/<id>
 		///
-		/// large/1..4 OR large OR large/1..4 small/5
-		#[clap(required = true,	parse(try_from_str=get_paths))]
-		paths: Vec<Vec<(PathBuf, PathBuf)>>,
+		/// large/1..4 OR large OR large/1..4 small/5 OR 'small/01?'
+		#[clap(required_unless_present = "stdin", parse(try_from_str=get_inputs))]
+		paths: Vec<Inputs>,
+
+		/// Read a single input from stdin and print its solution to stdout,
+		/// instead of using the input and output directories
+		#[clap(long, conflicts_with = "paths")]
+		stdin: bool,
 
 		// Vec allows for multiple inputs in the after the solver name
 		/// Only run solver on worse inputs
 		#[clap(long, short)]
 		worse: bool,
+
+		/// Skip inputs whose existing output already beats the best score on the
+		/// leaderboard
+		#[clap(long)]
+		skip_if_beating_leaderboard: bool,
+
+		/// Skip inputs that already have a valid output
+		#[clap(long)]
+		skip_if_solved: bool,
+
+		/// Cap on the number of towers the LP solver may place
+		#[clap(long)]
+		max_towers: Option<usize>,
+
+		/// Number of annealing runs to take the best of
+		#[clap(long, default_value = "1")]
+		restarts: usize,
+
+		/// Seconds to spend per input (rlp) or per LP solve (lp, pipeline)
+		#[clap(long)]
+		time_limit: Option<u64>,
+
+		/// How far towers may be moved (hillclimb, rand_hillclimb, tabu, pipeline)
+		#[clap(long)]
+		radius: Option<u8>,
+
+		/// Seed for the random number generators, making runs reproducible (rlp,
+		/// hillclimb, rand_hillclimb, pipeline, annealing, genetic, multi_greedy,
+		/// tabu)
+		#[clap(long)]
+		seed: Option<u64>,
+
+		/// Iterations per thread (rand_hillclimb), per run (annealing), in total
+		/// (tabu), generations (genetic), or starts (multi_greedy)
+		#[clap(long)]
+		iterations: Option<usize>,
+
+		/// How many iterations a vacated position stays tabu (tabu)
+		#[clap(long)]
+		tenure: Option<usize>,
+
+		/// Number of individuals in each generation (genetic)
+		#[clap(long)]
+		population: Option<usize>,
+
+		/// Chance from 0 to 1 that a child is mutated (genetic)
+		#[clap(long)]
+		mutation_rate: Option<f64>,
+
+		/// Number of threads to use (rlp, rand_hillclimb), defaults to one per cpu
+		#[clap(long)]
+		threads: Option<usize>,
+
+		/// Warn and exit non-zero if a solver ends up worse than the existing
+		/// solution
+		#[clap(long)]
+		strict: bool,
+
+		/// Append per-input solution metrics to this TSV file
+		#[clap(long)]
+		metrics_tsv: Option<PathBuf>,
+
+		/// Also write each solution as json (full grid state including coverage)
+		/// or svg (a drawing of the grid) next to its .out file
+		#[clap(long, default_value = "out", parse(try_from_str=get_format))]
+		format: OutputFormat,
+
+		/// Also draw each solution as a png next to its .out file
+		#[clap(long)]
+		render: bool,
+
+		/// Print the solver log of each LP solve (lp, pipeline)
+		#[clap(long)]
+		verbose: bool,
+
+		/// Write the LP model of each input (lp, rlp, pipeline) in LP format to a
+		/// .lp file next to its .out file, for debugging
+		#[clap(long)]
+		dump_model: bool,
 	},
 }
 
@@ -85,48 +319,297 @@ async fn main() {
 	// return;
 
 	let args = Args::parse();
+	logger::init(match (args.quiet, args.verbosity) {
+		(true, _) => LevelFilter::Warn,
+		(false, 0) => LevelFilter::Info,
+		(false, 1) => LevelFilter::Debug,
+		(false, _) => LevelFilter::Trace,
+	});
 
 	match &args.command {
 		// -- LIST --
 		Commands::List => {
 			println!("List of solvers:");
-			for (name, _) in SOLVERS.entries() {
-				println!("\t{}", name);
+			let mut solvers: Vec<&dyn Solver> = SOLVERS.to_vec();
+			solvers.sort_by(|a, b| a.name().cmp(b.name()));
+			for solver in solvers {
+				println!("\t{}: {}", solver.name(), solver.description());
+				let start = if solver.needs_existing_output() {
+					"Improves the existing output"
+				} else {
+					"Builds a solution from scratch"
+				};
+				let options = match solver.options() {
+					[] => "none".to_string(),
+					options => options.join(", "),
+				};
+				println!("\t\t{}. Options: {}", start, options);
 			}
 		}
 
+		// -- PREPROCESS --
+		Commands::Preprocess => {
+			point::preprocess::setup_persistence();
+		}
+
 		// -- API --
-		Commands::Api { size } => {
-			get_api_result(size).await;
+		Commands::Api {
+			size,
+			concurrency,
+			refresh,
+			cache_ttl,
+			output,
+		} => {
+			let cache = LeaderboardCache::load(Duration::from_secs(*cache_ttl), *refresh);
+			if let Err(e) = get_api_result(
+				&args.server,
+				size,
+				&args.output_dir,
+				*concurrency,
+				&cache,
+				output.as_deref(),
+			)
+			.await
+			{
+				println!("{} {}", "ERROR:".red().bold(), e);
+				std::process::exit(1);
+			}
 		}
 
-		// -- SOLVE --
-		Commands::Solve { solver, paths, worse } => {
-			// Prevent solving multiple identical inputs
-			let mut path_list: HashSet<&PathBuf> = HashSet::new();
-
-			// Run the solver on each input
-			for path_set in paths {
-				for (input, output) in path_set {
-					let mut is_worse = false;
-					if *worse {
-						is_worse = is_score_worse_than_leader(output).await.unwrap();
+		// -- REGRESS --
+		Commands::Regress { baseline_dir, size } => {
+			if api::regress(baseline_dir, &args.output_dir, size) {
+				std::process::exit(1);
+			}
+		}
+
+		// -- STATS --
+		Commands::Stats { size, worst, bound } => {
+			if let Err(e) = api::stats(&args.input_dir, &args.output_dir, size, *worst, *bound) {
+				println!("{} {}", "ERROR:".red().bold(), e);
+				std::process::exit(1);
+			}
+		}
+
+		// -- DIFF --
+		Commands::Diff { old_dir, new_dir } => {
+			if let Err(e) = api::diff(old_dir, new_dir) {
+				println!("{} {}", "ERROR:".red().bold(), e);
+				std::process::exit(1);
+			}
+		}
+
+		// -- VERIFY --
+		Commands::Verify {
+			paths,
+			render,
+			bound,
+			coverage,
+			verbose,
+		} => {
+			let paths = args.paths(paths);
+			let mut num_failed = 0;
+			let mut coverage_maps = serde_json::Map::new();
+			for (input, output) in paths.iter().flatten() {
+				let coverage_maps = coverage.as_ref().map(|_| &mut coverage_maps);
+				if !verify(input, output, *bound, *verbose, coverage_maps) {
+					num_failed += 1;
+				}
+				if *render && output.is_file() {
+					if let Err(e) = write_png(input, output) {
+						println!("{} failed to render {}: {}", "ERROR:".red().bold(), output.display(), e);
 					}
-					if path_list.contains(&input) || (*worse && !is_worse) {
+				}
+			}
+			if let Some(coverage) = coverage {
+				let json = serde_json::to_string_pretty(&coverage_maps).unwrap();
+				fs::write(coverage, json).expect("Failed to write coverage");
+			}
+			if num_failed > 0 {
+				println!(
+					"{} {} solution(s) failed verification",
+					"ERROR:".red().bold(),
+					num_failed
+				);
+				std::process::exit(1);
+			}
+		}
+
+		// -- NORMALIZE --
+		Commands::Normalize { paths } => {
+			let paths = args.paths(paths);
+			let mut num_corrected = 0;
+			let mut num_failed = 0;
+			for (input, output) in paths.iter().flatten() {
+				let name = format!(
+					"{}/{}",
+					input.parent().unwrap().file_stem().unwrap().to_str().unwrap(),
+					output.file_stem().unwrap().to_str().unwrap()
+				);
+				if !output.is_file() {
+					println!("{}: {}", name, "missing output".red());
+					continue;
+				}
+				let old_header = api::get_penalty_from_file(output.to_str().unwrap())
+					.map(|h| h.to_string())
+					.unwrap_or_else(|e| e.to_string());
+				let grid = match load_grid(input) {
+					Ok(grid) => grid,
+					Err(e) => {
+						num_failed += 1;
+						println!("{}: {}", name, format!("invalid input, {}", e).red());
 						continue;
 					}
-					path_list.insert(&input);
-					println!(
-						"Solving input {}/{}",
-						input.parent().unwrap().file_stem().unwrap().to_str().unwrap(),
-						input.file_stem().unwrap().to_str().unwrap()
-					);
+				};
+				match grid.validate_penalty_header(output.to_str().unwrap(), true) {
+					Ok(true) => {
+						num_corrected += 1;
+						let new_header = api::get_penalty_from_file(output.to_str().unwrap()).unwrap();
+						println!("{}: {} {} -> {}", name, "corrected".yellow(), old_header, new_header);
+					}
+					Ok(false) => println!("{}: {}", name, "ok".green()),
+					Err(e) => {
+						num_failed += 1;
+						println!("{}: {}", name, e.to_string().red());
+					}
+				}
+			}
+			println!("{} solution(s) corrected", num_corrected);
+			if num_failed > 0 {
+				println!("{} {} solution(s) couldn't be read", "ERROR:".red().bold(), num_failed);
+				std::process::exit(1);
+			}
+		}
+
+		// -- BENCHMARK --
+		Commands::Benchmark {
+			paths,
+			solvers,
+			time_limit,
+			from_existing,
+			seed,
+		} => {
+			let paths = &args.paths(paths);
+			let mut solvers: Vec<&'static dyn Solver> = if solvers.is_empty() {
+				SOLVERS.to_vec()
+			} else {
+				solvers.clone()
+			};
+			solvers.sort_by(|a, b| a.name().cmp(b.name()));
+			solvers.dedup_by(|a, b| a.name() == b.name());
+			let config = SolverConfig {
+				time_limit: Some(*time_limit),
+				seed: *seed,
+				..SolverConfig::default()
+			};
+			if let Err(e) = benchmark(&solvers, paths, &config, *from_existing) {
+				println!("{} {}", "ERROR:".red().bold(), e);
+				std::process::exit(1);
+			}
+		}
+
+		// -- EDIT --
+		Commands::Edit { input } => {
+			let (input, output) = &args.single_path(input);
+			let mut grid = match load_grid(input) {
+				Ok(grid) => grid,
+				Err(e) => {
+					println!("{} invalid input {}: {}", "ERROR:".red().bold(), input.display(), e);
+					std::process::exit(1);
+				}
+			};
+			// Start from the existing solution, if there is one, skipping any towers
+			// that can't be placed so they can be fixed by hand
+			if output.is_file() {
+				let mut towers: Vec<point::Point> = match Grid::towers_from_file(output.to_str().unwrap()) {
+					Ok(towers) => towers.into_iter().collect(),
+					Err(e) => {
+						println!("{} {}", "ERROR:".red().bold(), e);
+						std::process::exit(1);
+					}
+				};
+				towers.sort();
+				for e in grid.add_towers(towers) {
+					println!("{} skipping a tower, {}", "WARNING:".red().bold(), e);
+				}
+			}
+			edit::run(&mut grid, output.to_str().unwrap(), io::stdin().lock());
+		}
+
+		// -- SOLVE --
+		Commands::Solve {
+			solver,
+			paths,
+			stdin,
+			worse,
+			skip_if_beating_leaderboard,
+			skip_if_solved,
+			max_towers,
+			restarts,
+			time_limit,
+			radius,
+			seed,
+			iterations,
+			tenure,
+			population,
+			mutation_rate,
+			threads,
+			strict,
+			metrics_tsv,
+			format,
+			render,
+			verbose,
+			dump_model,
+		} => {
+			let config = SolverConfig {
+				time_limit:    *time_limit,
+				radius:        *radius,
+				seed:          *seed,
+				iterations:    *iterations,
+				tenure:        *tenure,
+				population:    *population,
+				mutation_rate: *mutation_rate,
+				threads:       *threads,
+				max_towers:    *max_towers,
+				restarts:      *restarts,
+				verbose:       *verbose,
+				dump_model:    *dump_model,
+			};
 
-					let mut grid = Grid::from_file(input.to_str().unwrap())
-						.expect(format!("Failed to load grid from {}", input.to_str().unwrap()).as_str());
+			let paths = args.paths(paths);
 
-					solver(&mut grid, output.to_str().unwrap());
+			// On Ctrl-C, let the solver write its best solution and skip the
+			// remaining inputs. A second Ctrl-C quits right away.
+			tokio::spawn(async {
+				tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl-C");
+				warn!("Interrupted, stopping after the current step, press Ctrl-C again to quit now");
+				request_stop();
+				tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl-C");
+				std::process::exit(130);
+			});
+
+			if *stdin {
+				if let Err(e) = solve_stdin(*solver, &config) {
+					error!("{}", e);
+					std::process::exit(1);
 				}
+				return;
+			}
+
+			let options = SolveOptions {
+				server:                      &args.server,
+				worse:                       *worse,
+				skip_if_beating_leaderboard: *skip_if_beating_leaderboard,
+				skip_if_solved:              *skip_if_solved,
+				strict:                      *strict,
+				metrics_tsv:                 metrics_tsv.as_deref(),
+				format:                      *format,
+				render:                      *render,
+			};
+			let summary = solve_inputs(*solver, &config, &paths, &options).await;
+			summary.print();
+			if !summary.failed_inputs.is_empty() || !summary.worse_inputs.is_empty() {
+				std::process::exit(1);
 			}
 		}
 	}
@@ -135,7 +618,7 @@ async fn main() {
 // -- Input parsing and validation --
 
 fn check_id_range(id: u8) -> Result<bool, String> {
-	const ID_RANGE: std::ops::RangeInclusive<u8> = 1..=239;
+	const ID_RANGE: RangeInclusive<u8> = 1..=239;
 
 	if !ID_RANGE.contains(&id) {
 		Err(format!(
@@ -148,22 +631,138 @@ fn check_id_range(id: u8) -> Result<bool, String> {
 	}
 }
 
-/// Converts input string to a list of input and output paths
-fn get_paths(input: &str) -> Result<Vec<(PathBuf, PathBuf)>, String> {
-	// Assuming run from root directory
-	let mut paths: Vec<(PathBuf, PathBuf)> = Vec::new();
+/// Inputs given on the command line, resolved to input and output paths
+/// once the input and output directories are known.
+#[derive(Debug, Clone)]
+struct Inputs {
+	size: String,
+	ids:  Ids,
+}
 
-	let size = input
-		.split(std::path::MAIN_SEPARATOR)
-		.next()
-		.ok_or("Error parsing input")?;
-	let id = input.split(std::path::MAIN_SEPARATOR).skip(1).next();
+/// Which inputs of a size were given.
+#[derive(Debug, Clone)]
+enum Ids {
+	// Every input of the size
+	All,
+	Range(RangeInclusive<u8>),
+	// Inputs whose name (without .in) matches a shell-style pattern
+	Glob(String),
+}
 
-	let in_path = Path::new("./inputs").join(size);
-	let out_path = Path::new("./outputs").join(size);
+impl Inputs {
+	/// Returns the input and output path of each input, in the size directory
+	/// of input_dir and output_dir respectively.
+	fn paths(&self, input_dir: &Path, output_dir: &Path) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+		let in_path = input_dir.join(&self.size);
+		let out_path = output_dir.join(&self.size);
 
-	match id {
-		// Return a subset of the files in the directory
+		match &self.ids {
+			Ids::Range(ids) => Ok(
+				ids
+					.clone()
+					.map(|i| {
+						let name = format!("{:0>3}", i);
+						// Fall back to a compressed input if there's no plain one
+						let mut input = in_path.join(&name).with_extension("in");
+						let compressed = in_path.join(&name).with_extension("in.gz");
+						if !input.is_file() && compressed.is_file() {
+							input = compressed;
+						}
+						(input, out_path.join(&name).with_extension("out"))
+					})
+					.collect(),
+			),
+			Ids::All => {
+				// Every file in the directory
+				let dir = fs::read_dir(&in_path).map_err(|e| format!("Error reading {}: {}", in_path.display(), e))?;
+				let mut paths = Vec::new();
+				for path in dir {
+					let path = path
+						.map_err(|e| format!("Error reading {}: {}", in_path.display(), e))?
+						.path();
+					// path will be in the form of "<input_dir>/size/id.in"
+					let current_out = out_path.join(path.file_stem().unwrap()).with_extension("out");
+					paths.push((path, current_out));
+				}
+				Ok(paths)
+			}
+			Ids::Glob(pattern) => {
+				let dir = fs::read_dir(&in_path).map_err(|e| format!("Error reading {}: {}", in_path.display(), e))?;
+				let mut paths = Vec::new();
+				for path in dir {
+					let path = path
+						.map_err(|e| format!("Error reading {}: {}", in_path.display(), e))?
+						.path();
+					// Inputs are named <name>.in, or <name>.in.gz if compressed
+					let stem = match input_stem(&path) {
+						Some(stem) => stem,
+						None => continue,
+					};
+					if glob_match(pattern, stem) {
+						let current_out = out_path.join(stem).with_extension("out");
+						paths.push((path, current_out));
+					}
+				}
+				paths.sort();
+				Ok(paths)
+			}
+		}
+	}
+}
+
+/// Returns whether name matches the shell-style pattern, where * matches any
+/// run of characters, ? any single character, and [...] any single character
+/// listed inside, e.g. [0-4], or [!9] for anything but 9.
+fn glob_match(pattern: &str, name: &str) -> bool {
+	fn matches(p: &[char], n: &[char]) -> bool {
+		match p.first() {
+			None => n.is_empty(),
+			Some('*') => (0..=n.len()).any(|i| matches(&p[1..], &n[i..])),
+			Some('?') => !n.is_empty() && matches(&p[1..], &n[1..]),
+			// An unclosed [ is matched literally
+			Some('[') if p.iter().skip(2).any(|&c| c == ']') => {
+				let end = 2 + p[2..].iter().position(|&c| c == ']').unwrap();
+				match n.first() {
+					Some(&c) => in_class(&p[1..end], c) && matches(&p[end + 1..], &n[1..]),
+					None => false,
+				}
+			}
+			Some(c) => n.first() == Some(c) && matches(&p[1..], &n[1..]),
+		}
+	}
+
+	fn in_class(class: &[char], c: char) -> bool {
+		let (negated, class) = match class.first() {
+			Some('!') | Some('^') => (true, &class[1..]),
+			_ => (false, class),
+		};
+		let mut found = false;
+		let mut i = 0;
+		while i < class.len() {
+			if i + 2 < class.len() && class[i + 1] == '-' {
+				found |= (class[i]..=class[i + 2]).contains(&c);
+				i += 3;
+			} else {
+				found |= class[i] == c;
+				i += 1;
+			}
+		}
+		found != negated
+	}
+
+	let pattern: Vec<char> = pattern.chars().collect();
+	let name: Vec<char> = name.chars().collect();
+	matches(&pattern, &name)
+}
+
+/// Parses <size>/<id>, <size>/<start>..<end>, <size>/<pattern> or <size> into
+/// Inputs
+fn get_inputs(input: &str) -> Result<Inputs, String> {
+	let mut parts = input.split(std::path::MAIN_SEPARATOR);
+	let size = parts.next().ok_or("Error parsing input")?.to_string();
+
+	let ids = match parts.next() {
+		Some(id) if id.contains(['*', '?', '[']) => Ids::Glob(id.to_string()),
 		Some(id) => {
 			let mut id_range = id.split("..");
 			let id_start = id_range
@@ -171,70 +770,917 @@ fn get_paths(input: &str) -> Result<Vec<(PathBuf, PathBuf)>, String> {
 				.ok_or("Error parsing input")?
 				.parse::<u8>()
 				.map_err(|_| "id must be an integer")?;
-			let id_end = id_range.next();
-
 			check_id_range(id_start)?;
 
-			match id_end {
+			let id_end = match id_range.next() {
 				Some(id_end) => {
-					// Given a range
 					let id_end = id_end.parse::<u8>().map_err(|_| "id must be an integer")?;
-
 					check_id_range(id_end)?;
 					// Check that id start <= id end
 					(id_start <= id_end)
 						.then(|| 1)
 						.ok_or("start id must be less than end id")?;
+					id_end
+				}
+				None => id_start,
+			};
+			Ids::Range(id_start..=id_end)
+		}
+		None => Ids::All,
+	};
+	Ok(Inputs { size, ids })
+}
+
+/// Parses a single input <size>/<id>
+fn get_single_input(input: &str) -> Result<Inputs, String> {
+	let inputs = get_inputs(input)?;
+	match &inputs.ids {
+		Ids::Range(ids) if ids.start() == ids.end() => Ok(inputs),
+		_ => Err("Expected a single input <size>/<id>".to_string()),
+	}
+}
+
+/// Validates that a report path is a .json or .csv file
+fn get_report_path(path: &str) -> Result<PathBuf, String> {
+	let path = PathBuf::from(path);
+	match path.extension().and_then(|ext| ext.to_str()) {
+		Some("json") | Some("csv") => Ok(path),
+		_ => Err("Report must be a .json or .csv file".to_string()),
+	}
+}
+
+/// Validates and converts a string to a solver
+fn get_solver(solver: &str) -> Result<&'static dyn Solver, String> {
+	find_solver(solver).ok_or("Solver not found, run list to see possible solvers".to_string())
+}
 
-					for i in id_start..=id_end {
-						let mut current_in = in_path.clone();
-						let mut current_out = out_path.clone();
+/// Loads the grid for the given input, or returns why the file is malformed.
+fn load_grid(input: &Path) -> Result<Grid, GridParseError> {
+	Grid::from_file(input.to_str().unwrap())
+}
+
+/// Returns the message a panic was raised with, if it was a string.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+	if let Some(s) = payload.downcast_ref::<&str>() {
+		s.to_string()
+	} else if let Some(s) = payload.downcast_ref::<String>() {
+		s.clone()
+	} else {
+		"unknown panic".to_string()
+	}
+}
 
-						// https://stackoverflow.com/questions/50458144/
-						current_in.push(format!("{:0>3}", i));
-						current_in.set_extension("in");
-						current_out.push(format!("{:0>3}", i));
-						current_out.set_extension("out");
+// -- Verification --
 
-						paths.push((current_in, current_out));
+/// Checks the solution at output against the given input, printing whether it
+/// covers every city and whether its penalty header matches the recomputed
+/// penalty. Returns whether both hold. If coverage is given, the coverage map
+/// of the solution is added to it under the input's name.
+fn verify(
+	input: &Path,
+	output: &Path,
+	bound: bool,
+	verbose: bool,
+	coverage: Option<&mut serde_json::Map<String, serde_json::Value>>,
+) -> bool {
+	let name = format!(
+		"{}/{}",
+		input.parent().unwrap().file_stem().unwrap().to_str().unwrap(),
+		output.file_stem().unwrap().to_str().unwrap()
+	);
+	if !output.is_file() {
+		println!("{}: {}", name, "missing output".red());
+		return false;
+	}
+
+	let mut grid = match load_grid(input) {
+		Ok(grid) => grid,
+		Err(e) => {
+			println!("{}: {}", name, format!("invalid input, {}", e).red());
+			return false;
+		}
+	};
+	if let Err(e) = grid.apply_solution(output.to_str().unwrap()) {
+		println!("{}: {}", name, e.to_string().red());
+		return false;
+	}
+	let valid = grid.is_valid();
+	let penalty = grid.penalty();
+	let header = api::get_penalty_from_file(output.to_str().unwrap()).map(api::round);
+	let header_matches = matches!(header, Ok(h) if h == penalty);
+
+	println!(
+		"{}: {}. Penalty: {}. Header: {}",
+		name,
+		if valid { "valid".green() } else { "INVALID".red() },
+		penalty,
+		match header {
+			Ok(h) if header_matches => h.to_string().green(),
+			Ok(h) => format!("{} (mismatch)", h).red(),
+			Err(e) => e.to_string().red(),
+		}
+	);
+	if bound {
+		let lower_bound = grid.penalty_lower_bound();
+		println!("  Bound: {}. Gap: {}", lower_bound, api::gap(penalty, lower_bound));
+	}
+	if verbose {
+		for (tower, w_j, cost) in grid.penalty_breakdown() {
+			println!("  Tower {}: w_j {}, adds {}", tower, w_j, api::round(cost));
+		}
+	}
+	if let Some(coverage) = coverage {
+		let mut cities: Vec<(Point, Vec<Point>)> = grid.coverage_map().into_iter().collect();
+		cities.sort();
+		let cities: Vec<serde_json::Value> = cities
+			.into_iter()
+			.map(|(city, towers)| serde_json::json!({ "city": city, "covered_by": towers }))
+			.collect();
+		coverage.insert(name, cities.into());
+	}
+	valid && header_matches
+}
+
+/// Returns whether output is a solution for input that covers every city.
+fn has_valid_output(input: &Path, output: &Path) -> bool {
+	if !output.is_file() {
+		return false;
+	}
+	match Grid::from_file(input.to_str().unwrap()) {
+		Ok(mut grid) => grid.apply_solution(output.to_str().unwrap()).is_ok() && grid.is_valid(),
+		Err(_) => false,
+	}
+}
+
+// -- Solve --
+
+/// Options of the solve command deciding which inputs are solved and what is
+/// written besides each solution.
+struct SolveOptions<'a> {
+	// Base url of the scoreboard server, for worse and
+	// skip_if_beating_leaderboard
+	server:                      &'a str,
+	// Only solve inputs where we are worse than the leaderboard
+	worse:                       bool,
+	skip_if_beating_leaderboard: bool,
+	skip_if_solved:              bool,
+	// Flag inputs the solver did worse on than the existing solution
+	strict:                      bool,
+	metrics_tsv:                 Option<&'a Path>,
+	format:                      OutputFormat,
+	render:                      bool,
+}
+
+/// What happened to the inputs of a solve run.
+#[derive(Debug, Default)]
+struct Summary {
+	num_solved:    usize,
+	// Inputs whose output the solver improved (or wrote for the first time)
+	num_improved:  usize,
+	num_skipped:   usize,
+	// Inputs that couldn't be solved, along with why
+	failed_inputs: Vec<(String, SolveError)>,
+	// Inputs the solver did worse on, for strict mode
+	worse_inputs:  Vec<String>,
+}
+
+impl Summary {
+	/// Prints how many inputs were solved, skipped and failed, and why each
+	/// failure failed.
+	fn print(&self) {
+		let num_panicked = self
+			.failed_inputs
+			.iter()
+			.filter(|(_, e)| matches!(e, SolveError::Panicked(_)))
+			.count();
+		info!(
+			"\n{} input(s) solved ({} improved), {} skipped, {} failed ({} panicked)",
+			self.num_solved,
+			self.num_improved,
+			self.num_skipped,
+			self.failed_inputs.len(),
+			num_panicked
+		);
+		for (name, e) in &self.failed_inputs {
+			info!("  {} {}", name.red(), e);
+		}
+		if !self.worse_inputs.is_empty() {
+			warn!("solver did worse on {} input(s)", self.worse_inputs.len());
+		}
+	}
+}
+
+/// Runs the solver on each input, writing its solution next to the input's
+/// output path. A failure, or even a panic, only loses that input and the rest
+/// still get solved, so every failure ends up in the returned summary.
+async fn solve_inputs(
+	solver: &dyn Solver,
+	config: &SolverConfig,
+	paths: &[Vec<(PathBuf, PathBuf)>],
+	options: &SolveOptions<'_>,
+) -> Summary {
+	let mut summary = Summary::default();
+	// Prevent solving multiple identical inputs
+	let mut path_list: HashSet<&PathBuf> = HashSet::new();
+	let cache = LeaderboardCache::load(api::LEADERBOARD_CACHE_TTL, false);
+
+	'inputs: for path_set in paths {
+		for (input, output) in path_set {
+			if stop_requested() {
+				warn!("Interrupted, skipping the remaining inputs");
+				break 'inputs;
+			}
+			let mut is_worse = false;
+			if options.worse {
+				is_worse = is_score_worse_than_leader(options.server, output, &cache)
+					.await
+					.unwrap();
+				cache.save();
+			}
+			if path_list.contains(&input) || (options.worse && !is_worse) {
+				continue;
+			}
+			path_list.insert(input);
+			let name = format!(
+				"{}/{}",
+				input.parent().unwrap().file_stem().unwrap().to_str().unwrap(),
+				output.file_stem().unwrap().to_str().unwrap()
+			);
+
+			if options.skip_if_solved && has_valid_output(input, output) {
+				info!("Skipping {}, it already has a valid output", name);
+				summary.num_skipped += 1;
+				continue;
+			}
+			if options.skip_if_beating_leaderboard {
+				// Solve anyway if there's no output or the leaderboard can't be
+				// reached
+				let beating = is_score_better_than_leader(options.server, output, &cache).await;
+				cache.save();
+				match beating {
+					Ok(true) => {
+						info!("Skipping {}, it already beats the leaderboard", name);
+						summary.num_skipped += 1;
+						continue;
 					}
+					Ok(false) => {}
+					Err(e) => debug!("Couldn't compare {} against the leaderboard: {}", name, e),
 				}
-				None => {
-					// Given a single id
-					let mut current_in = in_path.clone();
-					let mut current_out = out_path.clone();
-
-					current_in.push(format!("{:0>3}", id));
-					current_in.set_extension("in");
-					current_out.push(format!("{:0>3}", id));
-					current_out.set_extension("out");
+			}
+			info!("Solving input {}", name);
 
-					paths.push((current_in, current_out));
+			// The grid is loaded fresh inside, so a panic can't leave a half updated
+			// grid behind
+			let result = panic::catch_unwind(AssertUnwindSafe(|| {
+				let mut grid = Grid::from_file(input.to_str().unwrap())?;
+				solver.solve(&mut grid, output.to_str().unwrap(), config)
+			}));
+			let report = match result {
+				Ok(Ok(solved)) => solved,
+				Ok(Err(e)) => {
+					error!("{}: {}", name, e);
+					summary.failed_inputs.push((name, e));
+					continue;
+				}
+				Err(payload) => {
+					let e = SolveError::Panicked(panic_message(&*payload));
+					error!("{}: {}", name, e);
+					summary.failed_inputs.push((name, e));
+					continue;
 				}
+			};
+
+			// Writing what was asked for besides the solution can fail too, e.g. on
+			// a full disk
+			if let Err(e) = write_extras(input, output, solver.name(), report.elapsed, options) {
+				error!("{}: {}", name, e);
+				summary.failed_inputs.push((name, e));
+				continue;
+			}
+
+			summary.num_solved += 1;
+			if report.improved() {
+				summary.num_improved += 1;
+				info!("{}: {} (improved)", name, report.penalty);
+			} else {
+				info!("{}: {}", name, report.penalty);
+			}
+
+			if options.strict && report.outcome == WriteOutcome::Worse {
+				warn!(
+					"solver result {} is worse than the existing {}",
+					report.penalty,
+					output.to_str().unwrap()
+				);
+				summary.worse_inputs.push(name);
 			}
-			// Return the created vector
-			Ok(paths)
 		}
-		None => {
-			// Return all files the directory
-			let dir = fs::read_dir(in_path).map_err(|_| "Error reading directory")?;
+	}
+	summary
+}
+
+/// Writes what the options ask for besides the solution at output: a row of
+/// metrics, the solution as json or svg, and a png drawing of it.
+fn write_extras(
+	input: &Path,
+	output: &Path,
+	solver_name: &str,
+	elapsed: Duration,
+	options: &SolveOptions,
+) -> Result<(), SolveError> {
+	if let Some(metrics_path) = options.metrics_tsv {
+		append_metrics(metrics_path, input, output, solver_name, elapsed)
+			.map_err(|e| SolveError::Other(format!("failed to write metrics, {}", e)))?;
+	}
+
+	match options.format {
+		OutputFormat::Out => {}
+		OutputFormat::Json => {
+			write_json(input, output).map_err(|e| SolveError::Other(format!("failed to write json, {}", e)))?
+		}
+		OutputFormat::Svg => {
+			write_svg(input, output).map_err(|e| SolveError::Other(format!("failed to write svg, {}", e)))?
+		}
+	}
+
+	if options.render {
+		write_png(input, output).map_err(|e| SolveError::Other(format!("failed to render solution, {}", e)))?;
+	}
+	Ok(())
+}
 
-			for path in dir {
-				let path = path.map_err(|_| "Error reading directory")?.path();
-				// path will be in the form of "inputs/size/id.in"
-				let mut current_out = out_path.clone().join(path.file_stem().unwrap());
-				current_out.set_extension("out");
-				paths.push((path, current_out));
+// -- Stdin --
+
+/// Solves the input read from stdin and prints the solution to stdout. The
+/// solver writes to a scratch output, which starts out empty, so solvers that
+/// improve on the existing output start from scratch.
+fn solve_stdin(solver: &dyn Solver, config: &SolverConfig) -> Result<(), SolveError> {
+	let mut grid = Grid::from_reader(io::stdin().lock())?;
+	let scratch = std::env::temp_dir().join(format!("pengwin-stdin-{}.out", std::process::id()));
+	let solution = solver
+		.solve(&mut grid, scratch.to_str().unwrap(), config)
+		.and_then(|_| {
+			if !scratch.is_file() {
+				return Err(SolveError::Other("the solver didn't write a solution".to_string()));
 			}
-			Ok(paths)
+			Ok(fs::read_to_string(&scratch)?)
+		});
+	// Clean up even if the solver failed
+	let _ = fs::remove_file(&scratch);
+	print!("{}", solution?);
+	Ok(())
+}
+
+// -- Benchmark --
+
+/// Runs each solver on each input, starting from the same grid and writing to a
+/// scratch output instead of ./outputs, then prints the penalty each one ended
+/// with, how long it took and which won. If from_existing, each scratch output
+/// starts as a copy of the existing output.
+fn benchmark(
+	solvers: &[&'static dyn Solver],
+	paths: &[Vec<(PathBuf, PathBuf)>],
+	config: &SolverConfig,
+	from_existing: bool,
+) -> io::Result<()> {
+	let scratch_dir = std::env::temp_dir().join(format!("pengwin-benchmark-{}", std::process::id()));
+	fs::create_dir_all(&scratch_dir)?;
+	// Number of inputs each solver won, ties counting for every solver involved
+	let mut wins: Vec<usize> = vec![0; solvers.len()];
+	let mut seen: HashSet<&PathBuf> = HashSet::new();
+
+	for (input, output) in paths.iter().flatten() {
+		if !seen.insert(input) {
+			continue;
 		}
+		let name = format!(
+			"{}/{}",
+			input.parent().unwrap().file_stem().unwrap().to_str().unwrap(),
+			output.file_stem().unwrap().to_str().unwrap()
+		);
+		let base = match load_grid(input) {
+			Ok(grid) => grid,
+			Err(e) => {
+				error!("{}: invalid input, {}", name, e);
+				continue;
+			}
+		};
+
+		// Penalty of the valid solution each solver wrote, if any, and its time
+		let mut results: Vec<(Option<f64>, Duration)> = Vec::with_capacity(solvers.len());
+		for solver in solvers {
+			info!("Running {} on {}", solver.name(), name);
+			let scratch = scratch_dir.join(solver.name()).with_extension("out");
+			if from_existing && output.is_file() {
+				fs::copy(output, &scratch)?;
+			} else if scratch.is_file() {
+				fs::remove_file(&scratch)?;
+			}
+
+			let mut grid = base.clone();
+			let elapsed = match solver.solve(&mut grid, scratch.to_str().unwrap(), config) {
+				Ok(report) => report.elapsed,
+				Err(e) => {
+					error!("{}", e);
+					Duration::ZERO
+				}
+			};
+
+			let penalty = match stored_solution(input, &scratch) {
+				Ok(solution) if scratch.is_file() && solution.is_valid() => Some(solution.penalty()),
+				_ => None,
+			};
+			results.push((penalty, elapsed));
+		}
+
+		let best = results
+			.iter()
+			.filter_map(|(penalty, _)| *penalty)
+			.fold(f64::INFINITY, f64::min);
+		println!("\n{}", name.bold());
+		println!("  {:<24}{:>12}{:>12}", "solver", "penalty", "time");
+		for (i, (solver, (penalty, elapsed))) in solvers.iter().zip(&results).enumerate() {
+			let won = *penalty == Some(best);
+			if won {
+				wins[i] += 1;
+			}
+			let penalty = match penalty {
+				Some(p) => format!("{:>12}", p),
+				None => format!("{:>12}", "-"),
+			};
+			println!(
+				"  {:<24}{}{:>11.2}s",
+				solver.name(),
+				if won { penalty.green() } else { penalty.normal() },
+				elapsed.as_secs_f64()
+			);
+		}
+		let winners: Vec<&str> = solvers
+			.iter()
+			.zip(&results)
+			.filter(|(_, (penalty, _))| *penalty == Some(best))
+			.map(|(solver, _)| solver.name())
+			.collect();
+		if winners.is_empty() {
+			println!("  No solver found a valid solution\n");
+		} else {
+			println!("  Winner: {}\n", winners.join(", "));
+		}
+	}
+
+	println!("{}", "Wins".bold());
+	for (solver, wins) in solvers.iter().zip(wins) {
+		println!("  {:<24}{:>4}", solver.name(), wins);
+	}
+	fs::remove_dir_all(&scratch_dir)
+}
+
+// -- Json --
+
+/// Formats solutions can be written in, besides the .out file solvers use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+	Out,
+	Json,
+	Svg,
+}
+
+fn get_format(format: &str) -> Result<OutputFormat, String> {
+	match format {
+		"out" => Ok(OutputFormat::Out),
+		"json" => Ok(OutputFormat::Json),
+		"svg" => Ok(OutputFormat::Svg),
+		_ => Err(format!("Unknown format {:?}, expected out, json or svg", format)),
+	}
+}
+
+/// Writes the solution at output (of the given input) as json next to it,
+/// e.g. outputs/small/001.json.
+fn write_json(input: &Path, output: &Path) -> io::Result<()> {
+	// Write the solution as stored, since solvers may leave the grid in any state
+	let solution = stored_solution(input, output)?;
+	fs::write(output.with_extension("json"), solution.to_json())
+}
+
+/// Draws the solution at output (of the given input) as an svg next to it,
+/// e.g. outputs/small/001.svg.
+fn write_svg(input: &Path, output: &Path) -> io::Result<()> {
+	let solution = stored_solution(input, output)?;
+	fs::write(output.with_extension("svg"), solution.to_svg())
+}
+
+/// Draws the solution at output (of the given input) as a png next to it,
+/// e.g. outputs/small/001.png.
+fn write_png(input: &Path, output: &Path) -> image::ImageResult<()> {
+	let solution = stored_solution(input, output)?;
+	solution.render_png(output.with_extension("png").to_str().unwrap(), &RenderOptions::DEFAULT)
+}
+
+/// Returns the grid of the given input with the solution at output placed on
+/// it, if there is one.
+fn stored_solution(input: &Path, output: &Path) -> io::Result<Grid> {
+	let mut solution =
+		Grid::from_file(input.to_str().unwrap()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	if output.is_file() {
+		solution.apply_solution(output.to_str().unwrap())?;
 	}
+	Ok(solution)
 }
 
-/// Validates and converts a string to a solver function
-fn get_solver(solver: &str) -> Result<SolverFn, String> {
-	SOLVERS
-		.get(solver)
-		.cloned()
-		.ok_or("Solver not found, run list to see possible solvers".to_string())
+// -- Metrics --
+
+/// Appends a row of metrics for the solution at output (of the given input) to
+/// the TSV file at metrics_path, writing the header first if the file is new.
+fn append_metrics(
+	metrics_path: &Path,
+	input: &Path,
+	output: &Path,
+	solver_name: &str,
+	duration: Duration,
+) -> io::Result<()> {
+	const HEADER: &str =
+		"size\tid\tsolver\tpenalty\tnum_towers\tredundant_towers\tcoverage_surplus\tduration_ms\tvalid\n";
+
+	// Measure the solution as stored, since solvers may leave the grid in any state
+	let solution = stored_solution(input, output)?;
+	let report = solution.report();
+
+	let is_new = fs::metadata(metrics_path).map(|m| m.len() == 0).unwrap_or(true);
+	let mut file = OpenOptions::new().append(true).create(true).open(metrics_path)?;
+	if is_new {
+		file.write_all(HEADER.as_bytes())?;
+	}
+	writeln!(
+		file,
+		"{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+		input.parent().unwrap().file_stem().unwrap().to_str().unwrap(),
+		output.file_stem().unwrap().to_str().unwrap(),
+		solver_name,
+		report.penalty,
+		report.num_towers,
+		report.num_redundant_towers,
+		report.coverage_surplus,
+		duration.as_millis(),
+		solution.is_valid()
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Returns a path in the temp directory unique to this test run and name.
+	fn temp_path(name: &str) -> PathBuf {
+		std::env::temp_dir().join(format!("pengwin-main-{}-{}", std::process::id(), name))
+	}
+
+	/// Writes a small input (with one uncovered city) and a solution for it into
+	/// dir/small, returning their paths.
+	fn write_solved_input(dir: &Path, id: u8) -> (PathBuf, PathBuf) {
+		fs::create_dir_all(dir.join("small")).unwrap();
+		let (input, output) = (
+			dir.join("small").join(format!("{:0>3}.in", id)),
+			dir.join("small").join(format!("{:0>3}.out", id)),
+		);
+		fs::write(&input, "2\n30\n3\n8\n1 1\n20 20\n").unwrap();
+		fs::write(&output, "1\n1 1\n").unwrap();
+		(input, output)
+	}
+
+	#[test]
+	fn append_metrics_writes_header_once() {
+		let dir = temp_path("metrics");
+		let (input, output) = write_solved_input(&dir, 7);
+		let metrics = dir.join("metrics.tsv");
+		append_metrics(&metrics, &input, &output, "greedy", Duration::from_millis(12)).unwrap();
+		append_metrics(&metrics, &input, &output, "greedy", Duration::from_millis(34)).unwrap();
+
+		let contents = fs::read_to_string(&metrics).unwrap();
+		let lines: Vec<&str> = contents.lines().collect();
+		assert_eq!(lines.len(), 3);
+		assert!(lines[0].starts_with("size\tid\tsolver\t"));
+		assert_eq!(lines[1], "small\t007\tgreedy\t170\t1\t0\t0\t12\tfalse");
+		assert_eq!(lines[2], "small\t007\tgreedy\t170\t1\t0\t0\t34\tfalse");
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn panic_message_reads_string_payloads() {
+		let payload = panic::catch_unwind(|| panic!("static message")).unwrap_err();
+		assert_eq!(panic_message(&*payload), "static message");
+		let payload = panic::catch_unwind(|| panic!("formatted {}", 1321)).unwrap_err();
+		assert_eq!(panic_message(&*payload), "formatted 1321");
+		let payload = panic::catch_unwind(|| panic::panic_any(1321)).unwrap_err();
+		assert_eq!(panic_message(&*payload), "unknown panic");
+	}
+
+	#[test]
+	fn inputs_resolve_in_the_given_directories() {
+		let (input_dir, output_dir) = (temp_path("paths-in"), temp_path("paths-out"));
+		for id in [1, 2] {
+			let (_, output) = write_solved_input(&input_dir, id);
+			fs::remove_file(output).unwrap();
+		}
+		fs::remove_file(input_dir.join("small").join("002.in")).unwrap();
+		fs::write(input_dir.join("small").join("002.in.gz"), "").unwrap();
+		let (small_in, small_out) = (input_dir.join("small"), output_dir.join("small"));
+
+		let inputs = get_inputs("small/1..3").unwrap();
+		assert_eq!(
+			inputs.paths(&input_dir, &output_dir).unwrap(),
+			vec![
+				(small_in.join("001.in"), small_out.join("001.out")),
+				(small_in.join("002.in.gz"), small_out.join("002.out")),
+				(small_in.join("003.in"), small_out.join("003.out")),
+			]
+		);
+
+		let mut all = get_inputs("small").unwrap().paths(&input_dir, &output_dir).unwrap();
+		all.sort();
+		assert_eq!(
+			all,
+			vec![
+				(small_in.join("001.in"), small_out.join("001.out")),
+				(small_in.join("002.in.gz"), small_out.join("002.out")),
+			]
+		);
+		assert!(get_inputs("medium").unwrap().paths(&input_dir, &output_dir).is_err());
+		fs::remove_dir_all(&input_dir).unwrap();
+	}
+
+	#[test]
+	fn get_inputs_rejects_bad_ids() {
+		assert!(matches!(get_inputs("small/5").unwrap().ids, Ids::Range(r) if r == (5..=5)));
+		assert!(matches!(get_inputs("small/0?").unwrap().ids, Ids::Glob(p) if p == "0?"));
+		assert!(get_inputs("small/0").is_err());
+		assert!(get_inputs("small/240").is_err());
+		assert!(get_inputs("small/x").is_err());
+		assert!(get_inputs("small/9..3").is_err());
+		assert!(get_single_input("small/3..4").is_err());
+		assert!(get_single_input("small").is_err());
+	}
+
+	#[test]
+	fn glob_match_supports_wildcards_and_classes() {
+		assert!(glob_match("*", "") && glob_match("*", "001"));
+		assert!(glob_match("01?", "012") && !glob_match("01?", "01") && !glob_match("01?", "0123"));
+		assert!(glob_match("0*9", "009") && glob_match("0*9", "0119") && !glob_match("0*9", "0190"));
+		assert!(glob_match("00[1-3]", "002") && !glob_match("00[1-3]", "004"));
+		assert!(glob_match("00[!1-3]", "004") && !glob_match("00[^1-3]", "001"));
+		assert!(glob_match("0[13]0", "030") && !glob_match("0[13]0", "020"));
+		// An unclosed [ is literal
+		assert!(glob_match("a[b", "a[b") && !glob_match("a[b", "ab"));
+	}
+
+	#[test]
+	fn glob_inputs_match_names_without_extension() {
+		let (input_dir, output_dir) = (temp_path("glob-in"), temp_path("glob-out"));
+		for id in [1, 12, 13, 21] {
+			write_solved_input(&input_dir, id);
+		}
+		fs::rename(
+			input_dir.join("small").join("013.in"),
+			input_dir.join("small").join("013.in.gz"),
+		)
+		.unwrap();
+
+		let paths = get_inputs("small/01?").unwrap().paths(&input_dir, &output_dir).unwrap();
+		let (small_in, small_out) = (input_dir.join("small"), output_dir.join("small"));
+		assert_eq!(
+			paths,
+			vec![
+				(small_in.join("012.in"), small_out.join("012.out")),
+				(small_in.join("013.in.gz"), small_out.join("013.out")),
+			]
+		);
+		fs::remove_dir_all(&input_dir).unwrap();
+	}
+
+	#[test]
+	fn has_valid_output_checks_coverage() {
+		let dir = temp_path("valid-output");
+		let (input, output) = write_solved_input(&dir, 1);
+		// (20, 20) is uncovered
+		assert!(!has_valid_output(&input, &output));
+		fs::write(&output, "2\n1 1\n20 20\n").unwrap();
+		assert!(has_valid_output(&input, &output));
+		fs::write(&output, "2\n1 1\n").unwrap();
+		assert!(!has_valid_output(&input, &output));
+		fs::remove_file(&output).unwrap();
+		assert!(!has_valid_output(&input, &output));
+		fs::write(&output, "2\n1 1\n20 20\n").unwrap();
+		fs::write(&input, "2\n30\n").unwrap();
+		assert!(!has_valid_output(&input, &output));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	/// Solves with greedy, except that it panics on input 002.
+	struct PanicsOnSecond;
+
+	impl Solver for PanicsOnSecond {
+		fn name(&self) -> &str {
+			"panics_on_second"
+		}
+
+		fn description(&self) -> &str {
+			"Greedy, except that it panics on input 002"
+		}
+
+		fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+			if output_path.ends_with("002.out") {
+				panic!("deliberate panic");
+			}
+			get_solver("greedy").unwrap().solve(grid, output_path, config)
+		}
+	}
+
+	/// Returns solve options that only solve, writing nothing else.
+	fn plain_options() -> SolveOptions<'static> {
+		SolveOptions {
+			server:                      api::DEFAULT_SERVER,
+			worse:                       false,
+			skip_if_beating_leaderboard: false,
+			skip_if_solved:              false,
+			strict:                      false,
+			metrics_tsv:                 None,
+			format:                      OutputFormat::Out,
+			render:                      false,
+		}
+	}
+
+	/// Writes inputs 001 to 003 into dir/small without outputs, returning their
+	/// input and output paths.
+	fn write_unsolved_inputs(dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+		(1..=3)
+			.map(|id| {
+				let (input, output) = write_solved_input(dir, id);
+				fs::remove_file(&output).unwrap();
+				(input, output)
+			})
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn unreadable_input_fails_only_itself() {
+		let dir = temp_path("solve-unreadable");
+		let paths = write_unsolved_inputs(&dir);
+		fs::write(&paths[1].0, "2\n30\n3\n8\n1 1\n").unwrap();
+
+		let solver = get_solver("greedy").unwrap();
+		let summary = solve_inputs(
+			solver,
+			&SolverConfig::default(),
+			std::slice::from_ref(&paths),
+			&plain_options(),
+		)
+		.await;
+		assert_eq!((summary.num_solved, summary.num_improved), (2, 2));
+		assert_eq!(summary.failed_inputs.len(), 1);
+		assert_eq!(summary.failed_inputs[0].0, "small/002");
+		assert!(matches!(summary.failed_inputs[0].1, SolveError::Parse(_)));
+		assert!(has_valid_output(&paths[0].0, &paths[0].1));
+		assert!(!paths[1].1.exists());
+		assert!(has_valid_output(&paths[2].0, &paths[2].1));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn panicking_solver_fails_only_its_input() {
+		let dir = temp_path("solve-panic");
+		let paths = write_unsolved_inputs(&dir);
+
+		let summary = solve_inputs(
+			&PanicsOnSecond,
+			&SolverConfig::default(),
+			std::slice::from_ref(&paths),
+			&plain_options(),
+		)
+		.await;
+		assert_eq!(summary.num_solved, 2);
+		assert_eq!(summary.failed_inputs.len(), 1);
+		assert_eq!(summary.failed_inputs[0].0, "small/002");
+		assert!(matches!(&summary.failed_inputs[0].1, SolveError::Panicked(m) if m == "deliberate panic"));
+		assert!(has_valid_output(&paths[0].0, &paths[0].1));
+		assert!(!paths[1].1.exists());
+		assert!(has_valid_output(&paths[2].0, &paths[2].1));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn unwritable_metrics_fail_each_input() {
+		let dir = temp_path("solve-metrics");
+		let paths = write_unsolved_inputs(&dir);
+		// A directory can't be appended to
+		let metrics = dir.join("metrics.tsv");
+		fs::create_dir_all(&metrics).unwrap();
+
+		let options = SolveOptions {
+			metrics_tsv: Some(&metrics),
+			..plain_options()
+		};
+		let solver = get_solver("greedy").unwrap();
+		let summary = solve_inputs(solver, &SolverConfig::default(), std::slice::from_ref(&paths), &options).await;
+		assert_eq!(summary.num_solved, 0);
+		assert_eq!(summary.failed_inputs.len(), 3);
+		for ((name, e), (input, output)) in summary.failed_inputs.iter().zip(&paths) {
+			assert!(e.to_string().starts_with("failed to write metrics"), "{}: {}", name, e);
+			// The solution itself was still written
+			assert!(has_valid_output(input, output));
+		}
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	/// Solves inputs 001 to 003 with greedy and the given options, after making
+	/// the blocked_extension file of input 002 a directory so it can't be
+	/// written. Returns the summary, expecting only input 002 to fail.
+	async fn solve_with_blocked_extra(name: &str, options: SolveOptions<'_>, blocked_extension: &str) -> Summary {
+		let dir = temp_path(name);
+		let paths = write_unsolved_inputs(&dir);
+		fs::create_dir_all(paths[1].1.with_extension(blocked_extension)).unwrap();
+
+		let solver = get_solver("greedy").unwrap();
+		let summary = solve_inputs(solver, &SolverConfig::default(), std::slice::from_ref(&paths), &options).await;
+		assert_eq!(summary.num_solved, 2);
+		assert_eq!(summary.failed_inputs.len(), 1);
+		assert_eq!(summary.failed_inputs[0].0, "small/002");
+		for (_, output) in [&paths[0], &paths[2]] {
+			assert!(output.with_extension(blocked_extension).is_file());
+		}
+		fs::remove_dir_all(&dir).unwrap();
+		summary
+	}
+
+	#[tokio::test]
+	async fn unwritable_json_fails_only_its_input() {
+		let options = SolveOptions {
+			format: OutputFormat::Json,
+			..plain_options()
+		};
+		let summary = solve_with_blocked_extra("solve-json", options, "json").await;
+		assert!(summary.failed_inputs[0]
+			.1
+			.to_string()
+			.starts_with("failed to write json"));
+	}
+
+	#[tokio::test]
+	async fn unwritable_svg_fails_only_its_input() {
+		let options = SolveOptions {
+			format: OutputFormat::Svg,
+			..plain_options()
+		};
+		let summary = solve_with_blocked_extra("solve-svg", options, "svg").await;
+		assert!(summary.failed_inputs[0]
+			.1
+			.to_string()
+			.starts_with("failed to write svg"));
+	}
+
+	#[tokio::test]
+	async fn unwritable_png_fails_only_its_input() {
+		let options = SolveOptions {
+			render: true,
+			..plain_options()
+		};
+		let summary = solve_with_blocked_extra("solve-png", options, "png").await;
+		assert!(summary.failed_inputs[0]
+			.1
+			.to_string()
+			.starts_with("failed to render solution"));
+	}
+
+	#[tokio::test]
+	async fn strict_mode_flags_worse_results() {
+		let dir = temp_path("solve-strict");
+		let (input, output) = write_solved_input(&dir, 1);
+		// One tower at (1, 3) covers both cities, but benchmark places one on each
+		fs::write(&input, "2\n30\n3\n8\n1 1\n1 5\n").unwrap();
+		let mut best = Grid::from_file(input.to_str().unwrap()).unwrap();
+		best.add_tower(1, 3);
+		best.write_solution(output.to_str().unwrap(), true).unwrap();
+		let written = fs::read_to_string(&output).unwrap();
+
+		let solver = get_solver("benchmark").unwrap();
+		let paths = [vec![(input.clone(), output.clone())]];
+		let summary = solve_inputs(solver, &SolverConfig::default(), &paths, &plain_options()).await;
+		assert_eq!((summary.num_solved, summary.num_improved), (1, 0));
+		assert!(summary.worse_inputs.is_empty());
+
+		let options = SolveOptions {
+			strict: true,
+			..plain_options()
+		};
+		let summary = solve_inputs(solver, &SolverConfig::default(), &paths, &options).await;
+		assert_eq!(summary.worse_inputs, vec!["small/001"]);
+		assert!(summary.failed_inputs.is_empty());
+		assert_eq!(fs::read_to_string(&output).unwrap(), written);
+
+		// Matching the existing solution isn't worse
+		best.remove_all_towers();
+		best.add_towers([Point::new(1, 1), Point::new(1, 5)]);
+		best.write_solution(output.to_str().unwrap(), true).unwrap();
+		let summary = solve_inputs(solver, &SolverConfig::default(), &paths, &options).await;
+		assert!(summary.worse_inputs.is_empty());
+		fs::remove_dir_all(&dir).unwrap();
+	}
 }
@@ -1,30 +1,50 @@
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use std::sync::RwLock;
 
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 
+/// Map and set keyed by points, using the Fx hasher since SipHash is overkill
+/// for small integer keys.
+pub type PointMap<V> = FxHashMap<Point, V>;
+pub type PointSet = FxHashSet<Point>;
+
+/// Tables of points within radii computed on the fly, keyed by (metric, wrap,
+/// dim, r).
+type ComputedTables = HashMap<(Metric, bool, u8, u8), &'static PointMap<PointSet>>;
+
 // Static preprocessed data for points within radii.
 lazy_static! {
-	static ref PEN_S: HashMap<Point, HashSet<Point>> = preprocess::load("small", "penalty");
-	static ref PEN_M: HashMap<Point, HashSet<Point>> = preprocess::load("medium", "penalty");
-	static ref PEN_L: HashMap<Point, HashSet<Point>> = preprocess::load("large", "penalty");
-	static ref SVC_S: HashMap<Point, HashSet<Point>> = preprocess::load("small", "service");
-	static ref SVC_M: HashMap<Point, HashSet<Point>> = preprocess::load("medium", "service");
-	static ref SVC_L: HashMap<Point, HashSet<Point>> = preprocess::load("large", "service");
+	static ref PEN_S: PointMap<PointSet> = preprocess::load("small", "penalty");
+	static ref PEN_M: PointMap<PointSet> = preprocess::load("medium", "penalty");
+	static ref PEN_L: PointMap<PointSet> = preprocess::load("large", "penalty");
+	static ref SVC_S: PointMap<PointSet> = preprocess::load("small", "service");
+	static ref SVC_M: PointMap<PointSet> = preprocess::load("medium", "service");
+	static ref SVC_L: PointMap<PointSet> = preprocess::load("large", "service");
+	// Tables for the (metric, wrap, dim, r) that aren't preprocessed. Each table
+	// is built for every point at once and leaked so it can be handed out as
+	// &'static like the preprocessed ones.
+	static ref COMPUTED: RwLock<ComputedTables> = RwLock::new(HashMap::new());
 }
 
 // Preprocessing module for points within radii.
 pub mod preprocess {
-	use std::fs::{File, OpenOptions};
+	use std::fs::File;
 	use std::io::prelude::*;
-	use std::io::BufReader;
+	use std::io::{BufReader, BufWriter};
 	use std::path::Path;
 
+	use log::info;
+
 	use super::*;
 
 
-	/// Writes out the preprocessing data for all combinations of size and cover.
+	/// Writes out the preprocessing data for all combinations of size and cover,
+	/// skipping any that already exist.
 	pub fn setup_persistence() {
 		let options = vec![
 			("small", "penalty"),
@@ -35,21 +55,31 @@ pub mod preprocess {
 			("large", "service"),
 		];
 		for (size, cover) in options {
+			let output_path = path(size, cover, "bin");
+			if Path::new(&output_path).exists() {
+				info!("Skipping {}, already exists", output_path);
+				continue;
+			}
 			create(size, cover);
+			info!("Wrote {}", output_path);
+		}
+	}
+
+	/// Returns the path of the preprocessing data for the given size and cover,
+	/// with the given extension.
+	fn path(size: &str, cover: &str, extension: &str) -> String {
+		match (size, cover) {
+			("small" | "medium" | "large", "penalty" | "service") => {
+				format!("./preprocess/{}/{}.{}", cover, size, extension)
+			}
+			_ => panic!("Invalid size or cover"),
 		}
 	}
+
 	/// Writes out the coverage points for the given size and cover, i.e. penalty
-	/// or service.
+	/// or service. Only the Euclidean metric without wraparound is preprocessed.
 	fn create(size: &str, cover: &str) {
-		let output_path = match (size, cover) {
-			("small", "penalty") => "./preprocess/penalty/small.txt",
-			("medium", "penalty") => "./preprocess/penalty/medium.txt",
-			("large", "penalty") => "./preprocess/penalty/large.txt",
-			("small", "service") => "./preprocess/service/small.txt",
-			("medium", "service") => "./preprocess/service/medium.txt",
-			("large", "service") => "./preprocess/service/large.txt",
-			_ => panic!("Invalid size or cover"),
-		};
+		let output_path = path(size, cover, "bin");
 
 		let r: u8 = match (size, cover) {
 			("small", "penalty") => 8,
@@ -69,84 +99,153 @@ pub mod preprocess {
 		};
 
 		assert!(
-			!Path::new(output_path).exists(),
+			!Path::new(&output_path).exists(),
 			"Point preprocessing for {} already exists.",
 			size
 		);
 
-		let mut map: HashMap<Point, HashSet<Point>> = HashMap::new();
+		let mut map: PointMap<PointSet> = PointMap::default();
 		for i in 0..dim {
 			for j in 0..dim {
 				let p = Point::new(i.into(), j.into());
-				let mut points_within = Point::points_within_naive(p, r, dim);
+				let mut points_within = Point::points_within_naive(p, r, dim, Metric::Euclidean, false);
 				points_within.remove(&p);
 				map.insert(p, points_within);
 			}
 		}
-		let s = format! {"{:#?}", map};
-		let mut file = OpenOptions::new().write(true).create(true).open(output_path).unwrap();
-		file.write_all(s.as_bytes()).unwrap();
+		write(&output_path, &map);
+	}
+
+	/// Serializes the map to the given path.
+	fn write(output_path: &str, map: &PointMap<PointSet>) {
+		let file = File::create(output_path).unwrap();
+		bincode::serialize_into(BufWriter::new(file), map).unwrap();
 	}
 
 	/// Loads the preprocessed points for the given size (small, medium, large)
-	/// and cover, i.e. penalty or service
-	pub fn load(size: &str, cover: &str) -> HashMap<Point, HashSet<Point>> {
-		let input_path = match (size, cover) {
-			("small", "penalty") => "./preprocess/penalty/small.txt",
-			("medium", "penalty") => "./preprocess/penalty/medium.txt",
-			("large", "penalty") => "./preprocess/penalty/large.txt",
-			("small", "service") => "./preprocess/service/small.txt",
-			("medium", "service") => "./preprocess/service/medium.txt",
-			("large", "service") => "./preprocess/service/large.txt",
-			_ => panic!("Invalid size or cover"),
-		};
+	/// and cover, i.e. penalty or service. If only the old Debug-format .txt
+	/// file exists, it is parsed and migrated to .bin.
+	pub fn load(size: &str, cover: &str) -> PointMap<PointSet> {
+		let input_path = path(size, cover, "bin");
+		if !Path::new(&input_path).exists() {
+			let legacy_path = path(size, cover, "txt");
+			assert!(
+				Path::new(&legacy_path).exists(),
+				"Input path does not exist: {}",
+				input_path
+			);
+			let result = load_legacy(&legacy_path);
+			write(&input_path, &result);
+			return result;
+		}
 
-		assert!(
-			Path::new(input_path).exists(),
-			"Input path does not exist: {}",
-			input_path
-		);
+		let file = File::open(&input_path).unwrap();
+		bincode::deserialize_from(BufReader::new(file))
+			.unwrap_or_else(|_| panic!("Failed to read preprocessing from {}", input_path))
+	}
+
+	/// Loads preprocessed points from the old pretty Debug format of the map.
+	fn load_legacy(input_path: &str) -> PointMap<PointSet> {
 		let file = File::open(input_path).unwrap();
 		let reader = BufReader::new(file);
-		let mut result = HashMap::new();
-		let mut point = Point::new(-69, -69);
-		let mut within: HashSet<Point> = HashSet::new();
-		let mut found = false;
+		let mut result = PointMap::default();
+		// Point whose neighborhood is currently being read, if any
+		let mut key: Option<Point> = None;
+		let mut within = PointSet::default();
 		use regex::Regex;
 		// Regex pattern matching points (x, y)
-		let re = Regex::new(r"\((\d+), (\d+)\)").unwrap();
+		let re = Regex::new(r"\(\d+, \d+\)").unwrap();
 
+		// The file is the pretty Debug format of the map, i.e.
+		// {
+		//     (x, y): {
+		//         (x', y'),
+		//     },
+		//     (x, y): {},
+		// }
 		for line in reader.lines() {
 			let line = line.unwrap();
 			let line = line.trim();
-			if line.eq("}") {
-				result.insert(point, within.clone());
-				return result;
-			}
-			if line.len() <= 2 {
-				continue;
-			}
-			for cap in re.captures_iter(line) {
-				let x = cap[1].parse::<i32>().unwrap();
-				let y = cap[2].parse::<i32>().unwrap();
-				let p = Point::new(x, y);
-				if line.chars().last().unwrap() == '{' {
-					if found {
-						result.insert(point, within.clone());
-						within.clear();
-					}
-					point = p;
-				} else {
+			let point = re.find(line).map(|m| m.as_str().parse::<Point>().unwrap());
+			match (point, key) {
+				// "(x, y): {}," is an empty neighborhood
+				(Some(p), None) if line.trim_end_matches(',').ends_with("{}") => {
+					result.insert(p, PointSet::default());
+				}
+				// "(x, y): {" opens a neighborhood
+				(Some(p), None) => key = Some(p),
+				// "(x', y')," is a point in the current neighborhood
+				(Some(p), Some(_)) => {
 					within.insert(p);
 				}
-				found = true;
+				// "}," closes the current neighborhood
+				(None, Some(k)) if line.starts_with('}') => {
+					result.insert(k, std::mem::take(&mut within));
+					key = None;
+				}
+				// "{" and "}" open and close the whole map
+				_ => {}
 			}
 		}
 
 		result
 	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		#[test]
+		fn load_legacy_reads_every_neighborhood() {
+			let map = load_legacy(&path("small", "service", "txt"));
+			assert_eq!(map.len(), 30 * 30);
+			for (&p, within) in map.iter() {
+				let mut expected = Point::points_within_naive(p, 3, 30, Metric::Euclidean, false);
+				expected.remove(&p);
+				assert_eq!(within, &expected, "neighborhood of {}", p);
+			}
+		}
+
+		#[test]
+		fn bincode_round_trip() {
+			let legacy = load_legacy(&path("small", "penalty", "txt"));
+			let output_path = std::env::temp_dir().join(format!("pengwin-{}-preprocess.bin", std::process::id()));
+			let output_path = output_path.to_str().unwrap();
+			write(output_path, &legacy);
+			let file = File::open(output_path).unwrap();
+			let read: PointMap<PointSet> = bincode::deserialize_from(BufReader::new(file)).unwrap();
+			assert_eq!(read, legacy);
+			assert_eq!(load("small", "penalty"), legacy);
+			std::fs::remove_file(output_path).unwrap();
+		}
+	}
+}
+
+
+/// How distance is measured when deciding whether a point is within a radius
+/// of another, i.e. which cells a tower covers and penalizes.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Metric {
+	// sqrt(dx^2 + dy^2), the metric of the project spec.
+	#[default]
+	Euclidean,
+	// |dx| + |dy|
+	Manhattan,
+	// max(|dx|, |dy|)
+	Chebyshev,
 }
 
+impl Metric {
+	/// Returns whether the offset (dx, dy) is within r under this metric, using
+	/// only integer arithmetic.
+	pub fn within(self, dx: i32, dy: i32, r: i32) -> bool {
+		match self {
+			Metric::Euclidean => dx.pow(2) + dy.pow(2) <= r.pow(2),
+			Metric::Manhattan => dx.abs() + dy.abs() <= r,
+			Metric::Chebyshev => dx.abs().max(dy.abs()) <= r,
+		}
+	}
+}
 
 /// Represents a lattice point on the grid. Has integer x-y coordinates.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -167,6 +266,32 @@ impl fmt::Display for Point {
 	}
 }
 
+/// Parses a point from either its file form "x y" or its display form
+/// "(x, y)".
+impl FromStr for Point {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		let coords: Vec<&str> = match s.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')) {
+			Some(inner) => inner.split(',').map(str::trim).collect(),
+			None => s.split_whitespace().collect(),
+		};
+		match coords[..] {
+			[x, y] => {
+				let x = x
+					.parse::<i32>()
+					.map_err(|_| format!("Invalid x coordinate in {:?}", s))?;
+				let y = y
+					.parse::<i32>()
+					.map_err(|_| format!("Invalid y coordinate in {:?}", s))?;
+				Ok(Point::new(x, y))
+			}
+			_ => Err(format!("Expected a point as \"x y\" or \"(x, y)\", got {:?}", s)),
+		}
+	}
+}
+
 impl PartialOrd for Point {
 	fn partial_cmp(&self, other: &Point) -> Option<Ordering> {
 		Some(self.cmp(other))
@@ -189,20 +314,41 @@ impl Ord for Point {
 	}
 }
 
+/// Componentwise sum, e.g. to offset a point by (dx, dy).
+impl Add for Point {
+	type Output = Point;
+
+	fn add(self, other: Point) -> Point {
+		Point::new(self.x + other.x, self.y + other.y)
+	}
+}
+
+/// Componentwise difference, i.e. the offset from other to self.
+impl Sub for Point {
+	type Output = Point;
+
+	fn sub(self, other: Point) -> Point {
+		Point::new(self.x - other.x, self.y - other.y)
+	}
+}
+
 impl Point {
 	/// Creates and returns a new Point with the given x and y coordinates.
 	pub fn new(x: i32, y: i32) -> Self {
 		Point { x, y }
 	}
 
-	/// Returns the Euclidean distance between two points.
-	fn dist(p1: &Point, p2: &Point) -> f64 {
-		(((p1.x - p2.x).pow(2) + (p1.y - p2.y).pow(2)) as f64).sqrt()
+	/// Returns the Euclidean distance between this point and the given point.
+	pub fn euclidean_distance(&self, p: &Point) -> f64 {
+		let d = *self - *p;
+		((d.x.pow(2) + d.y.pow(2)) as f64).sqrt()
 	}
 
-	/// Returns the Euclidean distance between this point and the given point.
-	fn dist_to(&self, p: &Point) -> f64 {
-		Point::dist(self, p)
+	/// Returns the Manhattan (L1) distance between this point and the given
+	/// point.
+	pub fn manhattan_distance(&self, p: &Point) -> i32 {
+		let d = *self - *p;
+		d.x.abs() + d.y.abs()
 	}
 
 	/// Returns the file string form of this point, e.g. (3, 4) -> "3 4".
@@ -218,13 +364,27 @@ impl Point {
 		self.y
 	}
 
-	pub fn points_within_naive(p: Point, r: u8, dim: u8) -> HashSet<Point> {
-		let mut result = HashSet::new();
+	/// Returns the grid points within radius r of p under the given metric,
+	/// including p itself. If wrap is set the grid is a torus, so points past
+	/// one edge come back in at the opposite edge, i.e. the distance is the
+	/// minimum over the translated copies of the grid around it.
+	pub fn points_within_naive(p: Point, r: u8, dim: u8, metric: Metric, wrap: bool) -> PointSet {
+		let mut result = PointSet::default();
+		if dim == 0 {
+			return result;
+		}
 		let r = r as i32;
 		for i in -r..(r + 1) {
 			for j in -r..(r + 1) {
-				if Self::within(r, p.x, p.y, p.x + i, p.y + j, dim) {
-					result.insert(Self::new(p.x + i, p.y + j));
+				let q = p + Point::new(i, j);
+				if wrap {
+					// Any copy of a point within r is offset by at most r, so the
+					// square around p reaches every one of them
+					if metric.within(i, j, r) {
+						result.insert(Point::new(q.x.rem_euclid(dim as i32), q.y.rem_euclid(dim as i32)));
+					}
+				} else if Self::within(r, p, q, dim, metric) {
+					result.insert(q);
 				}
 			}
 		}
@@ -232,31 +392,219 @@ impl Point {
 		result
 	}
 
+	/// Returns the lattice points along the segment from a to b (inclusive), in
+	/// order, using Bresenham's line algorithm.
+	pub fn points_on_segment(a: Point, b: Point) -> Vec<Point> {
+		let dx = (b.x - a.x).abs();
+		let dy = -(b.y - a.y).abs();
+		let sx = if a.x < b.x { 1 } else { -1 };
+		let sy = if a.y < b.y { 1 } else { -1 };
+
+		let mut result = Vec::new();
+		let mut p = a;
+		let mut err = dx + dy;
+		loop {
+			result.push(p);
+			if p == b {
+				break;
+			}
+			let e2 = 2 * err;
+			if e2 >= dy {
+				err += dy;
+				p.x += sx;
+			}
+			if e2 <= dx {
+				err += dx;
+				p.y += sy;
+			}
+		}
+		result
+	}
+
 	/// Returns a set of all the grid points within the given radius of the given
-	/// point.
-	pub fn points_within_radius(p: Point, r: u8, dim: u8) -> Result<&'static HashSet<Point>, &'static str> {
-		let result = match (dim, r) {
-			(30, 8) => PEN_S.get(&p),
-			(50, 10) => PEN_M.get(&p),
-			(100, 14) => PEN_L.get(&p),
-			(30, 3) => SVC_S.get(&p),
-			(50, 3) => SVC_M.get(&p),
-			(100, 3) => SVC_L.get(&p),
-			_ => None,
+	/// point (excluding the point itself) under the given metric, wrapping
+	/// around the edges if wrap is set. Uses the preprocessed data for the
+	/// standard (dim, r) pairs with the Euclidean metric and no wraparound, and
+	/// computes and caches the set otherwise.
+	pub fn points_within_radius(
+		p: Point,
+		r: u8,
+		dim: u8,
+		metric: Metric,
+		wrap: bool,
+	) -> Result<&'static PointSet, &'static str> {
+		if p.x < 0 || p.y < 0 || p.x >= dim as i32 || p.y >= dim as i32 {
+			return Err("Point is off the grid");
+		}
+		let preprocessed = match (metric, wrap, dim, r) {
+			(Metric::Euclidean, false, 30, 8) => &*PEN_S,
+			(Metric::Euclidean, false, 50, 10) => &*PEN_M,
+			(Metric::Euclidean, false, 100, 14) => &*PEN_L,
+			(Metric::Euclidean, false, 30, 3) => &*SVC_S,
+			(Metric::Euclidean, false, 50, 3) => &*SVC_M,
+			(Metric::Euclidean, false, 100, 3) => &*SVC_L,
+			_ => return Ok(&Self::computed_table(r, dim, metric, wrap)[&p]),
 		};
-		// println!("{}: {:?}", p, result);
-		match result {
-			Some(result) => Ok(result),
-			None => panic!("Didn't find preprocessed"),
+		preprocessed.get(&p).ok_or("Didn't find preprocessed")
+	}
+
+	/// Returns the points within the given radius of every point on the grid
+	/// (excluding the point itself), building the table the first time it is
+	/// requested.
+	fn computed_table(r: u8, dim: u8, metric: Metric, wrap: bool) -> &'static PointMap<PointSet> {
+		// A panic elsewhere while holding the lock leaves the cache as it was, so
+		// it is still safe to use after solve catches the panic
+		let key = (metric, wrap, dim, r);
+		if let Some(&table) = COMPUTED.read().unwrap_or_else(|e| e.into_inner()).get(&key) {
+			return table;
 		}
+		let mut computed = COMPUTED.write().unwrap_or_else(|e| e.into_inner());
+		computed.entry(key).or_insert_with(|| {
+			let mut table = PointMap::default();
+			for x in 0..dim as i32 {
+				for y in 0..dim as i32 {
+					let p = Point::new(x, y);
+					let mut points_within = Self::points_within_naive(p, r, dim, metric, wrap);
+					points_within.remove(&p);
+					table.insert(p, points_within);
+				}
+			}
+			Box::leak(Box::new(table))
+		})
 	}
 
-	/// Returns whether (x2, y2) is within r units of (x1, y1) and within this
-	/// Grid.
-	fn within(r: i32, x1: i32, y1: i32, x2: i32, y2: i32, d: u8) -> bool {
-		if x2 < 0 || x2 >= d as i32 || y2 < 0 || y2 >= d as i32 {
+	/// Returns whether q is within r units of p under the given metric, and
+	/// within a grid of dimension d.
+	fn within(r: i32, p: Point, q: Point, d: u8, metric: Metric) -> bool {
+		if q.x < 0 || q.x >= d as i32 || q.y < 0 || q.y >= d as i32 {
 			return false;
 		}
-		(x1 - x2).pow(2) + (y1 - y2).pow(2) <= r.pow(2)
+		let offset = q - p;
+		metric.within(offset.x, offset.y, r)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn computed_radius_matches_naive() {
+		for metric in [Metric::Euclidean, Metric::Manhattan, Metric::Chebyshev] {
+			for wrap in [false, true] {
+				for x in 0..7 {
+					for y in 0..7 {
+						let p = Point::new(x, y);
+						let mut expected = Point::points_within_naive(p, 2, 7, metric, wrap);
+						expected.remove(&p);
+						assert_eq!(Point::points_within_radius(p, 2, 7, metric, wrap).unwrap(), &expected);
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn computed_radius_is_shared_per_table() {
+		let a = Point::points_within_radius(Point::new(1, 1), 2, 6, Metric::Euclidean, false).unwrap();
+		let b = Point::points_within_radius(Point::new(1, 1), 2, 6, Metric::Euclidean, false).unwrap();
+		assert!(std::ptr::eq(a, b));
+	}
+
+	#[test]
+	fn radius_rejects_off_grid_points() {
+		assert!(Point::points_within_radius(Point::new(-1, 0), 2, 6, Metric::Euclidean, false).is_err());
+		assert!(Point::points_within_radius(Point::new(0, 6), 2, 6, Metric::Euclidean, false).is_err());
+	}
+
+	#[test]
+	fn segment_includes_both_ends_in_order() {
+		let (a, b) = (Point::new(0, 0), Point::new(4, 2));
+		let points = Point::points_on_segment(a, b);
+		assert_eq!(points.first(), Some(&a));
+		assert_eq!(points.last(), Some(&b));
+		assert_eq!(points.len(), 5);
+		// Each step moves to an adjacent (or diagonal) cell
+		for w in points.windows(2) {
+			let d = w[1] - w[0];
+			assert!(d.x.abs() <= 1 && d.y.abs() <= 1 && d != Point::new(0, 0));
+		}
+		let mut reversed = Point::points_on_segment(b, a);
+		reversed.reverse();
+		assert_eq!(reversed.len(), points.len());
+	}
+
+	#[test]
+	fn segment_of_a_single_point() {
+		let p = Point::new(3, -2);
+		assert_eq!(Point::points_on_segment(p, p), vec![p]);
+		assert_eq!(
+			Point::points_on_segment(Point::new(0, 0), Point::new(0, 3)),
+			vec![Point::new(0, 0), Point::new(0, 1), Point::new(0, 2), Point::new(0, 3)]
+		);
+	}
+
+	#[test]
+	fn parses_file_and_display_forms() {
+		assert_eq!("3 4".parse::<Point>(), Ok(Point::new(3, 4)));
+		assert_eq!("  3\t-4 ".parse::<Point>(), Ok(Point::new(3, -4)));
+		assert_eq!("(3, 4)".parse::<Point>(), Ok(Point::new(3, 4)));
+		assert_eq!("(3,4)".parse::<Point>(), Ok(Point::new(3, 4)));
+		let p = Point::new(12, 7);
+		assert_eq!(p.to_string().parse::<Point>(), Ok(p));
+		assert_eq!(p.file_string().parse::<Point>(), Ok(p));
+	}
+
+	#[test]
+	fn rejects_malformed_points() {
+		for s in ["", "3", "3 4 5", "x 4", "3 y", "(3 4)", "(3, 4", "3.5 4"] {
+			assert!(s.parse::<Point>().is_err(), "{:?} should not parse", s);
+		}
+	}
+
+	#[test]
+	fn arithmetic_and_distances() {
+		let (a, b) = (Point::new(1, 2), Point::new(4, 6));
+		assert_eq!(a + b, Point::new(5, 8));
+		assert_eq!(b - a, Point::new(3, 4));
+		assert_eq!(a - b + b, a);
+		assert_eq!(a.euclidean_distance(&b), 5.);
+		assert_eq!(b.euclidean_distance(&a), 5.);
+		assert_eq!(a.manhattan_distance(&b), 7);
+		assert_eq!(a.manhattan_distance(&a), 0);
+		assert_eq!(Point::new(-3, 0).file_string(), "-3 0");
+		assert!(Point::new(1, 9) < Point::new(2, 0) && Point::new(2, 0) < Point::new(2, 1));
+	}
+
+	#[test]
+	fn metrics_measure_distance_differently() {
+		assert!(Metric::Euclidean.within(3, 4, 5) && !Metric::Euclidean.within(3, 5, 5));
+		assert!(Metric::Manhattan.within(-2, 3, 5) && !Metric::Manhattan.within(3, 3, 5));
+		assert!(Metric::Chebyshev.within(5, -5, 5) && !Metric::Chebyshev.within(6, 0, 5));
+		assert_eq!(Metric::default(), Metric::Euclidean);
+
+		let within = |m| Point::points_within_naive(Point::new(5, 5), 3, 11, m, false);
+		assert_eq!(within(Metric::Euclidean).len(), 29);
+		assert_eq!(within(Metric::Manhattan).len(), 25);
+		assert_eq!(within(Metric::Chebyshev).len(), 49);
+		assert!(within(Metric::Euclidean).contains(&Point::new(7, 7)));
+		assert!(!within(Metric::Manhattan).contains(&Point::new(7, 7)));
+	}
+
+	#[test]
+	fn wrapped_radius_crosses_edges() {
+		let within = Point::points_within_radius(Point::new(0, 0), 2, 10, Metric::Euclidean, true).unwrap();
+		for p in [(9, 0), (8, 0), (0, 8), (9, 9), (1, 9)] {
+			assert!(within.contains(&Point::new(p.0, p.1)), "{:?}", p);
+		}
+		assert!(!within.contains(&Point::new(8, 8)));
+		// Same size as in the middle of the grid, less the point itself
+		assert_eq!(within.len(), 12);
+		let unwrapped = Point::points_within_radius(Point::new(0, 0), 2, 10, Metric::Euclidean, false).unwrap();
+		assert_eq!(unwrapped.len(), 5);
+
+		// A radius reaching around the whole grid covers each point once
+		let all = Point::points_within_naive(Point::new(1, 1), 5, 3, Metric::Chebyshev, true);
+		assert_eq!(all.len(), 9);
 	}
 }
@@ -0,0 +1,274 @@
+use std::fmt::Write;
+
+use image::{ImageResult, Rgb, RgbImage};
+
+use crate::grid::Grid;
+use crate::point::Point;
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const SERVICE_CIRCLE: Rgb<u8> = Rgb([215, 215, 215]);
+const COVERED_CITY: Rgb<u8> = Rgb([40, 80, 200]);
+const UNCOVERED_CITY: Rgb<u8> = Rgb([230, 0, 200]);
+
+/// How Grid::render_png draws a Grid.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+	// Side length of each cell in pixels
+	pub cell_size:       u32,
+	// Whether to outline the service radius of each tower
+	pub service_circles: bool,
+}
+
+impl RenderOptions {
+	pub const DEFAULT: RenderOptions = RenderOptions {
+		cell_size:       8,
+		service_circles: true,
+	};
+}
+
+impl Grid {
+	/// Draws this Grid as a PNG at path, with (0, 0) at the bottom left like
+	/// the Display impl. Cities are dots (blue if covered, magenta if not), and
+	/// towers are squares colored from green to red by their w_j relative to the
+	/// most penalized tower, so the expensive clusters stand out.
+	pub fn render_png(&self, path: &str, options: &RenderOptions) -> ImageResult<()> {
+		let cell = options.cell_size.max(1);
+		// A grid without cells is drawn as one blank cell, since an image can't
+		// be empty
+		let size = self.dimension().max(1) as u32 * cell;
+		let mut img = RgbImage::from_pixel(size, size, BACKGROUND);
+		// Top left pixel of the cell of p
+		let corner = |p: &Point| {
+			(
+				p.x as u32 * cell,
+				(self.dimension() as u32).saturating_sub(1 + p.y as u32) * cell,
+			)
+		};
+
+		if options.service_circles {
+			let radius = (self.service_radius() as u32 * cell) as f64;
+			for t in self.get_towers_ref().keys() {
+				let (x, y) = corner(t);
+				let center = ((x + cell / 2) as f64, (y + cell / 2) as f64);
+				draw_circle(&mut img, center, radius);
+			}
+		}
+
+		for (c, covering) in self.get_cities_ref() {
+			let color = if covering.is_empty() {
+				UNCOVERED_CITY
+			} else {
+				COVERED_CITY
+			};
+			let (x, y) = corner(c);
+			// Dot in the middle half of the cell
+			fill_rect(&mut img, x + cell / 4, y + cell / 4, (cell / 2).max(1), color);
+		}
+
+		let max_w = self
+			.get_towers_ref()
+			.values()
+			.map(|p| p.len())
+			.max()
+			.unwrap_or(0)
+			.max(1);
+		for (t, penalized) in self.get_towers_ref() {
+			let (x, y) = corner(t);
+			fill_rect(
+				&mut img,
+				x,
+				y,
+				cell,
+				penalty_color(penalized.len() as f64 / max_w as f64),
+			);
+		}
+
+		img.save(path)
+	}
+
+	/// Returns this Grid as an SVG drawing, one unit per cell with (0, 0) at the
+	/// bottom left: the outline of the grid, faint service radius circles, a dot
+	/// for each city, and a square for each tower colored like render_png. Each
+	/// tower has a tooltip with its position, w_j and penalty contribution.
+	pub fn to_svg(&self) -> String {
+		let dim = self.dimension() as f64;
+		// Center of the cell of p
+		let center = |p: &Point| (p.x as f64 + 0.5, dim - p.y as f64 - 0.5);
+		let mut cities: Vec<(&Point, bool)> = self
+			.get_cities_ref()
+			.iter()
+			.map(|(c, covering)| (c, covering.is_empty()))
+			.collect();
+		cities.sort();
+		let mut towers: Vec<(&Point, usize)> = self.get_towers_ref().iter().map(|(t, p)| (t, p.len())).collect();
+		towers.sort();
+		let max_w = towers.iter().map(|(_, w)| *w).max().unwrap_or(0).max(1);
+
+		let mut svg = String::new();
+		// Writing to a String can't fail
+		writeln!(
+			svg,
+			r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-0.5 -0.5 {0} {0}" width="{1}" height="{1}">"#,
+			dim + 1.,
+			(dim + 1.) * 8.
+		)
+		.unwrap();
+		writeln!(
+			svg,
+			r#"<rect x="0" y="0" width="{0}" height="{0}" fill="white" stroke="black" stroke-width="0.1"/>"#,
+			dim
+		)
+		.unwrap();
+
+		writeln!(
+			svg,
+			r#"<g fill="none" stroke="{}" stroke-width="0.1">"#,
+			hex(SERVICE_CIRCLE)
+		)
+		.unwrap();
+		for (t, _) in &towers {
+			let (x, y) = center(t);
+			writeln!(svg, r#"<circle cx="{}" cy="{}" r="{}"/>"#, x, y, self.service_radius()).unwrap();
+		}
+		writeln!(svg, "</g>").unwrap();
+
+		writeln!(svg, "<g>").unwrap();
+		for (c, uncovered) in cities {
+			let (x, y) = center(c);
+			let color = if uncovered { UNCOVERED_CITY } else { COVERED_CITY };
+			writeln!(svg, r#"<circle cx="{}" cy="{}" r="0.25" fill="{}"/>"#, x, y, hex(color)).unwrap();
+		}
+		writeln!(svg, "</g>").unwrap();
+
+		writeln!(svg, "<g>").unwrap();
+		for (t, w_j) in towers {
+			// Top left corner, inset a tenth of a cell
+			let (x, y) = (t.x as f64 + 0.1, (dim - 1. - t.y as f64) + 0.1);
+			writeln!(
+				svg,
+				r#"<rect x="{}" y="{}" width="0.8" height="0.8" fill="{}"><title>Tower {}, w_j = {}, penalty {:.2}</title></rect>"#,
+				x,
+				y,
+				hex(penalty_color(w_j as f64 / max_w as f64)),
+				t,
+				w_j,
+				170. * Grid::tower_cost(w_j)
+			)
+			.unwrap();
+		}
+		writeln!(svg, "</g>").unwrap();
+		writeln!(svg, "</svg>").unwrap();
+		svg
+	}
+}
+
+/// Returns the color as #rrggbb.
+fn hex(color: Rgb<u8>) -> String {
+	let [r, g, b] = color.0;
+	format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Returns green for 0, through yellow, to red for 1.
+fn penalty_color(t: f64) -> Rgb<u8> {
+	let t = t.clamp(0., 1.);
+	Rgb([
+		(255. * (2. * t).min(1.)) as u8,
+		(200. * (2. * (1. - t)).min(1.)) as u8,
+		0,
+	])
+}
+
+/// Colors the square of the given side length with top left corner (x, y),
+/// clipped to the image.
+fn fill_rect(img: &mut RgbImage, x: u32, y: u32, side: u32, color: Rgb<u8>) {
+	for px in x..(x + side).min(img.width()) {
+		for py in y..(y + side).min(img.height()) {
+			img.put_pixel(px, py, color);
+		}
+	}
+}
+
+/// Outlines the circle of the given radius around center (in pixels), clipped
+/// to the image.
+fn draw_circle(img: &mut RgbImage, center: (f64, f64), radius: f64) {
+	let (cx, cy) = center;
+	let x_range = (cx - radius - 1.).max(0.) as u32..((cx + radius + 2.) as u32).min(img.width());
+	for px in x_range {
+		let y_range = (cy - radius - 1.).max(0.) as u32..((cy + radius + 2.) as u32).min(img.height());
+		for py in y_range {
+			let dist = ((px as f64 - cx).powi(2) + (py as f64 - cy).powi(2)).sqrt();
+			if (dist - radius).abs() < 0.5 {
+				img.put_pixel(px, py, SERVICE_CIRCLE);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+	use crate::grid::tests::{grid_with_cities, temp_path};
+
+	#[test]
+	fn png_draws_cities_and_towers() {
+		let mut grid = grid_with_cities(4, 1, 2, &[(0, 0), (3, 3)]);
+		grid.add_tower(0, 1);
+		let path = temp_path("render.png");
+		let options = RenderOptions {
+			cell_size:       4,
+			service_circles: false,
+		};
+		grid.render_png(&path, &options).unwrap();
+		let img = image::open(&path).unwrap().to_rgb8();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(img.dimensions(), (16, 16));
+		// (0, 0) is at the bottom left, so y is flipped
+		assert_eq!(*img.get_pixel(0, 8), penalty_color(0.));
+		assert_eq!(*img.get_pixel(2, 14), COVERED_CITY);
+		assert_eq!(*img.get_pixel(14, 2), UNCOVERED_CITY);
+		assert_eq!(*img.get_pixel(8, 8), BACKGROUND);
+	}
+
+	#[test]
+	fn png_of_empty_grid() {
+		let path = temp_path("render-empty.png");
+		Grid::new(0, 0, 0).render_png(&path, &RenderOptions::DEFAULT).unwrap();
+		let img = image::open(&path).unwrap().to_rgb8();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(img.dimensions(), (8, 8));
+	}
+
+	#[test]
+	fn svg_draws_cities_and_towers() {
+		let mut grid = grid_with_cities(4, 1, 2, &[(0, 0), (3, 3)]);
+		grid.add_tower(0, 1);
+		grid.add_tower(1, 1);
+		let svg = grid.to_svg();
+
+		assert!(svg.starts_with("<svg "));
+		assert!(svg.trim_end().ends_with("</svg>"));
+		assert_eq!(svg.matches("<title>").count(), 2);
+		assert!(svg.contains("<title>Tower (0, 1), w_j = 1, penalty"));
+		assert_eq!(svg.matches(&hex(COVERED_CITY)).count(), 1);
+		assert_eq!(svg.matches(&hex(UNCOVERED_CITY)).count(), 1);
+	}
+
+	#[test]
+	fn svg_flips_y_and_scales_with_dimension() {
+		let mut grid = grid_with_cities(4, 2, 2, &[(0, 0)]);
+		grid.add_tower(0, 1);
+		let svg = grid.to_svg();
+		assert!(svg.contains(r#"viewBox="-0.5 -0.5 5 5" width="40" height="40""#));
+		// (0, 0) is at the bottom left
+		assert!(svg.contains(r#"<circle cx="0.5" cy="3.5" r="0.25""#));
+		assert!(svg.contains(r#"<rect x="0.1" y="2.1" width="0.8""#));
+		assert!(svg.contains(r#"<circle cx="0.5" cy="2.5" r="2"/>"#));
+
+		let empty = Grid::new(0, 0, 0).to_svg();
+		assert!(empty.contains(r#"viewBox="-0.5 -0.5 1 1""#));
+		assert!(!empty.contains("<circle") && !empty.contains("<title>"));
+	}
+}
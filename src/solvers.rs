@@ -1,20 +1,32 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{self, AtomicBool};
+use std::time::{Duration, Instant};
+use std::{fmt, io};
 
 use colored::Colorize;
+use good_lp::ResolutionError;
+use log::{debug, info, trace, warn};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
 use stopwatch::Stopwatch;
 
-use crate::annealing;
-use crate::grid::Grid;
-use crate::point::Point;
+use crate::grid::{Grid, GridParseError, WriteOutcome};
+use crate::lp::LpStatus;
+use crate::point::{Point, PointSet};
+use crate::{annealing, api, exact, genetic};
 
 
 // Greedy parameters
 // What percent of the total do we consider in addition to max coverage
 const PERCENT_REMAINING: f32 = 0.25;
 
+// Multi-start greedy parameters
+// How many randomized greedy runs to take the best of
+const MULTI_GREEDY_STARTS: usize = 20;
+
 // LP parameters
 // Max time in seconds
 const LP_CUTOFF_TIME: u32 = 500000;
@@ -34,16 +46,660 @@ const HILLCLIMB_ITERATIONS_PER_THREAD: usize = 0;
 // (large)
 const HILLCLIMB_RADIUS: u8 = 10;
 
+// Tabu search parameters
+const TABU_ITERATIONS: usize = 1000;
+// How many iterations a vacated position stays tabu
+const TABU_TENURE: usize = 10;
+const TABU_RADIUS: u8 = 3;
+
+// Exact parameters
+// Largest grid dimension the exact solver will run on, since its running time
+// is exponential. Small inputs are 30x30.
+const EXACT_MAX_DIMENSION: u8 = 30;
+
+// Pipeline parameters
+// Max time in seconds of the initial LP solve, after which it falls back on
+// greedy
+const PIPELINE_LP_TIME: u32 = 60;
+
+// Genetic algorithm parameters
+const GA_POPULATION: usize = 50;
+const GA_GENERATIONS: usize = 200;
+// Chance that a child is mutated
+const GA_MUTATION_RATE: f64 = 0.2;
+
 // Simulated annealing parameters
 const SA_ITERATIONS: u32 = 1000;
 const SA_RADIUS: u8 = 43;
 
+// ------- Interruption -------
+
+/// Set once the user presses Ctrl-C. The long running solvers check it every
+/// iteration and stop early, writing the best solution they have. Being a
+/// static, it is shared by every rayon worker thread.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Asks every running solver to stop as soon as it can.
+pub fn request_stop() {
+	STOP.store(true, atomic::Ordering::Relaxed);
+}
+
+/// Returns whether the solvers have been asked to stop.
+pub fn stop_requested() -> bool {
+	STOP.load(atomic::Ordering::Relaxed)
+}
+
+// ------- Solver configuration -------
+
+/// Parameters from the solve command line. Each solver reads the ones it
+/// honors, falling back on its own defaults for any that aren't given.
+#[derive(Debug, Clone, Default)]
+pub struct SolverConfig {
+	// Seconds to spend on an input (rlp), or per LP solve (lp, pipeline)
+	pub time_limit:    Option<u64>,
+	// How far towers may move (hillclimb, rand_hillclimb, tabu, pipeline)
+	pub radius:        Option<u8>,
+	// Seed for the random number generators (rlp, hillclimb, rand_hillclimb,
+	// annealing, genetic, multi_greedy, tabu)
+	pub seed:          Option<u64>,
+	// Iterations per thread (rand_hillclimb), per run (annealing), in total
+	// (tabu), generations (genetic), or starts (multi_greedy)
+	pub iterations:    Option<usize>,
+	// How many iterations a vacated position stays tabu (tabu)
+	pub tenure:        Option<usize>,
+	// Number of individuals in each generation (genetic)
+	pub population:    Option<usize>,
+	// Chance that a child is mutated (genetic)
+	pub mutation_rate: Option<f64>,
+	// Number of threads to run on (rlp, rand_hillclimb)
+	pub threads:       Option<usize>,
+	// Cap on the number of towers (lp, pipeline)
+	pub max_towers:    Option<usize>,
+	// Number of runs to take the best of (annealing)
+	pub restarts:      usize,
+	// Whether to print the solver log of each LP solve (lp, pipeline)
+	pub verbose:       bool,
+	// Whether to write the LP model next to the output (lp, rlp, pipeline)
+	pub dump_model:    bool,
+}
+
+impl SolverConfig {
+	/// Returns a thread pool with the configured number of threads, defaulting
+	/// to one per cpu.
+	fn thread_pool(&self) -> rayon::ThreadPool {
+		rayon::ThreadPoolBuilder::new()
+			.num_threads(self.threads.unwrap_or_else(num_cpus::get))
+			.build()
+			.expect("Failed to build thread pool")
+	}
+
+	/// Returns where to write the LP model for the given output path, which is
+	/// the output with a .lp extension, if dump_model is set.
+	pub fn model_path(&self, output_path: &str) -> Option<String> {
+		self.dump_model.then(|| {
+			Path::new(output_path)
+				.with_extension("lp")
+				.to_str()
+				.unwrap()
+				.to_string()
+		})
+	}
+
+	/// Returns a random number generator seeded with the configured seed plus
+	/// stream (e.g. a thread index), so runs with the same seed are reproducible,
+	/// or seeded from entropy if no seed was given.
+	pub fn rng(&self, stream: u64) -> StdRng {
+		match self.seed {
+			Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(stream)),
+			None => StdRng::from_entropy(),
+		}
+	}
+}
+
+// ------- Solver trait -------
+
+/// A solver that places towers on a Grid, writing its solution to the output
+/// path if it beats what is already there.
+pub trait Solver: Sync {
+	/// Name used to select the solver from the command line.
+	fn name(&self) -> &str;
+
+	/// One line description for the list command.
+	fn description(&self) -> &str;
+
+	/// Whether the solver works on the existing output, rather than building a
+	/// solution from scratch.
+	fn needs_existing_output(&self) -> bool {
+		false
+	}
+
+	/// Solve options the solver honors, e.g. "--seed".
+	fn options(&self) -> &[&str] {
+		&[]
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError>;
+}
+
+/// What a solver run ended with.
+#[derive(Debug, Clone, Copy)]
+pub struct SolveReport {
+	// Penalty of the grid the solver left behind.
+	pub penalty: f64,
+	pub elapsed: Duration,
+	// Whether the output file now holds a better solution than before the run
+	// (Written), or the existing solution was kept because it is at least as
+	// good (Unchanged) or strictly better (Worse) than the solver's.
+	pub outcome: WriteOutcome,
+}
+
+impl SolveReport {
+	/// Returns whether the output file now holds a better solution than before
+	/// the run, or was written for the first time.
+	pub fn improved(&self) -> bool {
+		self.outcome == WriteOutcome::Written
+	}
+}
+
+/// Why a solver failed on an input.
+#[derive(Debug)]
+pub enum SolveError {
+	// Reading or writing a file failed, e.g. the output.
+	Io(io::Error),
+	// The input file isn't a valid grid.
+	Parse(GridParseError),
+	// The LP couldn't be solved.
+	Lp(ResolutionError),
+	// Anything else, e.g. an error from argmin.
+	Other(String),
+	// The solver panicked with the given message.
+	Panicked(String),
+}
+
+impl fmt::Display for SolveError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SolveError::Io(e) => write!(f, "{}", e),
+			SolveError::Parse(e) => write!(f, "invalid input, {}", e),
+			SolveError::Lp(e) => write!(f, "LP failed, {}", e),
+			SolveError::Other(e) => write!(f, "{}", e),
+			SolveError::Panicked(e) => write!(f, "panicked, {}", e),
+		}
+	}
+}
+
+impl std::error::Error for SolveError {}
+
+impl From<io::Error> for SolveError {
+	fn from(e: io::Error) -> Self {
+		SolveError::Io(e)
+	}
+}
+
+impl From<GridParseError> for SolveError {
+	fn from(e: GridParseError) -> Self {
+		SolveError::Parse(e)
+	}
+}
+
+impl From<ResolutionError> for SolveError {
+	fn from(e: ResolutionError) -> Self {
+		SolveError::Lp(e)
+	}
+}
+
+/// Runs f on the grid, timing it and checking whether it improved the output.
+/// If it didn't, the grid f left behind is compared against the existing
+/// solution, to tell whether the solver did worse.
+fn timed_solve<E>(
+	grid: &mut Grid,
+	output_path: &str,
+	f: impl FnOnce(&mut Grid, &str) -> Result<(), E>,
+) -> Result<SolveReport, SolveError>
+where
+	SolveError: From<E>, {
+	let previous_penalty = output_penalty(output_path);
+	let start = Instant::now();
+	f(grid, output_path)?;
+	let elapsed = start.elapsed();
+
+	let outcome = match (previous_penalty, output_penalty(output_path)) {
+		(None, Some(_)) => WriteOutcome::Written,
+		(Some(previous), Some(current)) if current < previous => WriteOutcome::Written,
+		(Some(previous), _) if grid.is_valid() && grid.penalty() > previous => WriteOutcome::Worse,
+		_ => WriteOutcome::Unchanged,
+	};
+	Ok(SolveReport {
+		penalty: grid.penalty(),
+		elapsed,
+		outcome,
+	})
+}
+
+/// Returns the penalty in the header of the output file, if there is one.
+fn output_penalty(output_path: &str) -> Option<f64> {
+	if !Path::new(output_path).is_file() {
+		return None;
+	}
+	api::get_penalty_from_file(output_path).ok().map(api::round)
+}
+
+/// Solver backed by a plain function with no configuration.
+pub struct FnSolver {
+	pub name:                  &'static str,
+	pub description:           &'static str,
+	pub needs_existing_output: bool,
+	pub options:               &'static [&'static str],
+	pub f:                     fn(&mut Grid, &str, &SolverConfig) -> Result<(), SolveError>,
+}
+
+impl Solver for FnSolver {
+	fn name(&self) -> &str {
+		self.name
+	}
+
+	fn description(&self) -> &str {
+		self.description
+	}
+
+	fn needs_existing_output(&self) -> bool {
+		self.needs_existing_output
+	}
+
+	fn options(&self) -> &[&str] {
+		self.options
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		timed_solve(grid, output_path, |g, p| (self.f)(g, p, config))
+	}
+}
+
+pub struct Greedy {
+	// What percent of the total do we consider in addition to max coverage
+	pub percent_remaining: f32,
+}
+
+impl Greedy {
+	pub const DEFAULT: Greedy = Greedy {
+		percent_remaining: PERCENT_REMAINING,
+	};
+}
+
+impl Solver for Greedy {
+	fn name(&self) -> &str {
+		"greedy"
+	}
+
+	fn description(&self) -> &str {
+		"Repeatedly places the tower covering the most cities, preferring less added penalty"
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, _config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		timed_solve(grid, output_path, |g, p| greedy(g, p, self.percent_remaining))
+	}
+}
+
+pub struct MultiGreedy {
+	// How many randomized greedy runs to take the best of
+	pub starts:            usize,
+	// What percent of the total do we consider in addition to max coverage
+	pub percent_remaining: f32,
+}
+
+impl MultiGreedy {
+	pub const DEFAULT: MultiGreedy = MultiGreedy {
+		starts:            MULTI_GREEDY_STARTS,
+		percent_remaining: PERCENT_REMAINING,
+	};
+}
+
+impl Solver for MultiGreedy {
+	fn name(&self) -> &str {
+		"multi_greedy"
+	}
+
+	fn description(&self) -> &str {
+		"Runs greedy several times breaking ties randomly, keeping the best"
+	}
+
+	fn options(&self) -> &[&str] {
+		&["--iterations", "--seed"]
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		let starts = config.iterations.unwrap_or(self.starts);
+		let mut rng = config.rng(0);
+		timed_solve(grid, output_path, |g, p| {
+			multi_greedy(g, p, starts, self.percent_remaining, &mut rng)
+		})
+	}
+}
+
+pub struct Hillclimb {
+	// How far a tower may be moved in a single step
+	pub radius: u8,
+}
+
+impl Hillclimb {
+	pub const DEFAULT: Hillclimb = Hillclimb {
+		radius: HILLCLIMB_RADIUS,
+	};
+}
+
+impl Solver for Hillclimb {
+	fn name(&self) -> &str {
+		"hillclimb"
+	}
+
+	fn description(&self) -> &str {
+		"Removes and moves towers of the existing output while that improves it"
+	}
+
+	fn needs_existing_output(&self) -> bool {
+		true
+	}
+
+	fn options(&self) -> &[&str] {
+		&["--radius", "--seed"]
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		let radius = config.radius.unwrap_or(self.radius);
+		let mut rng = config.rng(0);
+		timed_solve(grid, output_path, |g, p| hillclimb(g, p, radius, &mut rng))
+	}
+}
+
+pub struct RandomizedLp {
+	// How long each thread keeps trying, in seconds
+	pub secs_per_input: u64,
+	// Max time of each LP solve in seconds
+	pub cutoff_time:    u32,
+}
+
+impl RandomizedLp {
+	pub const DEFAULT: RandomizedLp = RandomizedLp {
+		secs_per_input: SECS_PER_INPUT,
+		cutoff_time:    CUTOFF_TIME,
+	};
+}
+
+impl Solver for RandomizedLp {
+	fn name(&self) -> &str {
+		"rlp"
+	}
+
+	fn description(&self) -> &str {
+		"Solves randomized LPs on every thread for a fixed time, keeping the best"
+	}
+
+	fn options(&self) -> &[&str] {
+		&["--time-limit", "--threads", "--seed", "--dump-model"]
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		let secs_per_input = config.time_limit.unwrap_or(self.secs_per_input);
+		timed_solve(grid, output_path, |g, p| {
+			randomize_valid_solution_with_lp_threaded(g, p, secs_per_input, self.cutoff_time, config)
+		})
+	}
+}
+
+pub struct Tabu {
+	pub iterations: usize,
+	// How many iterations a vacated position stays tabu
+	pub tenure:     usize,
+	// How far a tower may be moved in a single step
+	pub radius:     u8,
+}
+
+impl Tabu {
+	pub const DEFAULT: Tabu = Tabu {
+		iterations: TABU_ITERATIONS,
+		tenure:     TABU_TENURE,
+		radius:     TABU_RADIUS,
+	};
+}
+
+impl Solver for Tabu {
+	fn name(&self) -> &str {
+		"tabu"
+	}
+
+	fn description(&self) -> &str {
+		"Tabu search from the existing output, taking the best move even if it is worse"
+	}
+
+	fn needs_existing_output(&self) -> bool {
+		true
+	}
+
+	fn options(&self) -> &[&str] {
+		&["--iterations", "--tenure", "--radius", "--seed"]
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		let iterations = config.iterations.unwrap_or(self.iterations);
+		let tenure = config.tenure.unwrap_or(self.tenure);
+		let radius = config.radius.unwrap_or(self.radius);
+		let mut rng = config.rng(0);
+		timed_solve(grid, output_path, |g, p| {
+			tabu(g, p, iterations, tenure, radius, &mut rng)
+		})
+	}
+}
+
+pub struct Pipeline {
+	// Max time of the initial LP solve in seconds
+	pub lp_time: u32,
+	// How far a tower may be moved in a single hillclimb step
+	pub radius:  u8,
+}
+
+impl Pipeline {
+	pub const DEFAULT: Pipeline = Pipeline {
+		lp_time: PIPELINE_LP_TIME,
+		radius:  HILLCLIMB_RADIUS,
+	};
+}
+
+impl Solver for Pipeline {
+	fn name(&self) -> &str {
+		"pipeline"
+	}
+
+	fn description(&self) -> &str {
+		"Solves the LP (or greedy if it fails), then hillclimbs and prunes, writing once"
+	}
+
+	fn options(&self) -> &[&str] {
+		&[
+			"--time-limit",
+			"--radius",
+			"--max-towers",
+			"--seed",
+			"--verbose",
+			"--dump-model",
+		]
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		let lp_time = config.time_limit.map_or(self.lp_time, |t| t as u32);
+		let radius = config.radius.unwrap_or(self.radius);
+		let mut rng = config.rng(0);
+		timed_solve(grid, output_path, |g, p| {
+			pipeline(
+				g,
+				p,
+				lp_time,
+				radius,
+				config.max_towers,
+				config.verbose,
+				config.model_path(p).as_deref(),
+				&mut rng,
+			)
+		})
+	}
+}
+
+pub struct Genetic {
+	pub population_size: usize,
+	pub generations:     usize,
+	// Chance that a child is mutated
+	pub mutation_rate:   f64,
+}
+
+impl Genetic {
+	pub const DEFAULT: Genetic = Genetic {
+		population_size: GA_POPULATION,
+		generations:     GA_GENERATIONS,
+		mutation_rate:   GA_MUTATION_RATE,
+	};
+}
+
+impl Solver for Genetic {
+	fn name(&self) -> &str {
+		"genetic"
+	}
+
+	fn description(&self) -> &str {
+		"Evolves a population of covers by crossover and mutation, seeded from random LP solutions"
+	}
+
+	fn options(&self) -> &[&str] {
+		&["--population", "--iterations", "--mutation-rate", "--seed"]
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		let params = genetic::Params {
+			population_size: config.population.unwrap_or(self.population_size),
+			generations:     config.iterations.unwrap_or(self.generations),
+			mutation_rate:   config.mutation_rate.unwrap_or(self.mutation_rate),
+		};
+		let mut rng = config.rng(0);
+		timed_solve(grid, output_path, |g, p| genetic::run(g, p, &params, &mut rng))
+	}
+}
+
+pub struct Exact {
+	// Refuse grids larger than this
+	pub max_dimension: u8,
+}
+
+impl Exact {
+	pub const DEFAULT: Exact = Exact {
+		max_dimension: EXACT_MAX_DIMENSION,
+	};
+}
+
+impl Solver for Exact {
+	fn name(&self) -> &str {
+		"exact"
+	}
+
+	fn description(&self) -> &str {
+		"Finds an optimal solution by branch and bound, only for small inputs"
+	}
+
+	fn solve(&self, grid: &mut Grid, output_path: &str, _config: &SolverConfig) -> Result<SolveReport, SolveError> {
+		// Checked before anything is read or written, so the existing output is
+		// left as it was
+		if grid.dimension() > self.max_dimension {
+			return Err(SolveError::Other(format!(
+				"the exact solver only runs on grids up to {0}x{0}, not {1}x{1}",
+				self.max_dimension,
+				grid.dimension()
+			)));
+		}
+		timed_solve(grid, output_path, exact::run)
+	}
+}
+
+// ------- Registry -------
+
+/// Defines SOLVERS, the solvers that can be selected from the command line and
+/// are shown by the list command, which find them by Solver::name. To add a
+/// solver, implement Solver (or wrap a function in an FnSolver) and register it
+/// below.
+macro_rules! register_solvers {
+	($($solver:expr),* $(,)?) => {
+		/// Every registered solver, in the order they were registered.
+		pub static SOLVERS: &[&dyn Solver] = &[$(&$solver),*];
+	};
+}
+
+register_solvers! {
+	FnSolver {
+		name: "benchmark",
+		description: "Places a tower on every uncovered city, for benchmarking",
+		needs_existing_output: false,
+		options: &[],
+		f: benchmark_greedy,
+	},
+	Greedy::DEFAULT,
+	MultiGreedy::DEFAULT,
+	FnSolver {
+		name: "setcover",
+		description: "Classic greedy set cover, ignoring penalty",
+		needs_existing_output: false,
+		options: &[],
+		f: set_cover,
+	},
+	FnSolver {
+		name: "lp",
+		description: "Solves the integer program with CBC",
+		needs_existing_output: false,
+		options: &["--time-limit", "--max-towers", "--verbose", "--dump-model"],
+		f: linear_programming,
+	},
+	RandomizedLp::DEFAULT,
+	Exact::DEFAULT,
+	Tabu::DEFAULT,
+	Genetic::DEFAULT,
+	Hillclimb::DEFAULT,
+	Pipeline::DEFAULT,
+	FnSolver {
+		name: "rand_hillclimb",
+		description: "Threaded hillclimb of the existing output, restarting from random LP solutions",
+		needs_existing_output: true,
+		options: &["--iterations", "--radius", "--threads", "--seed"],
+		f: rand_hillclimb_threaded,
+	},
+	FnSolver {
+		name: "sort_and_read_penalty",
+		description: "Rewrites the existing output with its towers sorted and prints its penalty",
+		needs_existing_output: true,
+		options: &[],
+		f: sort_and_read_penalty,
+	},
+	FnSolver {
+		name: "prune",
+		description: "Removes towers of the existing output whose cities are all covered by others",
+		needs_existing_output: true,
+		options: &[],
+		f: prune,
+	},
+	FnSolver {
+		name: "annealing",
+		description: "Simulated annealing from the existing output",
+		needs_existing_output: true,
+		options: &["--iterations", "--restarts", "--seed"],
+		f: simulated_annealing,
+	},
+}
+
+/// Returns the registered solver with the given name, if any.
+pub fn find_solver(name: &str) -> Option<&'static dyn Solver> {
+	SOLVERS.iter().copied().find(|s| s.name() == name)
+}
+
 // ------- Solver functions -------
 
 // -- Naive Greedy --
 /// Greedy algorithm for benchmarking.
 /// Places towers at all city locations that haven't been covered
-pub fn benchmark_greedy(grid: &mut Grid, output_path: &str) {
+pub fn benchmark_greedy(grid: &mut Grid, output_path: &str, _config: &SolverConfig) -> Result<(), SolveError> {
 	let cities = grid.get_cities_ref().clone();
 	let city_points = cities.keys();
 
@@ -54,36 +710,100 @@ pub fn benchmark_greedy(grid: &mut Grid, output_path: &str) {
 		}
 		grid.add_tower(city.get_x(), city.get_y());
 	}
-	grid.write_solution(output_path);
+	grid.write_solution(output_path, false)?;
+	Ok(())
 }
 
 // -- Greedy --
 /// Greedy algorithm for solving the grid.
 /// Places a tower such that it covers the most cities.
 /// Picks a range of covered and minimizes the added penalty.
-pub fn greedy(grid: &mut Grid, output_path: &str) {
-	let mut cities = grid.get_cities_ref().clone().into_keys().collect::<Vec<Point>>();
+pub fn greedy(grid: &mut Grid, output_path: &str, percent_remaining: f32) -> io::Result<()> {
+	greedy_cover(grid, percent_remaining);
+	grid.write_solution(output_path, false)?;
+	Ok(())
+}
+
+/// Runs the greedy construction starts times from scratch, breaking ties
+/// between equally good towers randomly, and writes the best result. Prints the
+/// spread of the penalties.
+pub fn multi_greedy(
+	grid: &mut Grid,
+	output_path: &str,
+	starts: usize,
+	percent_remaining: f32,
+	rng: &mut impl Rng,
+) -> io::Result<()> {
+	let mut penalties: Vec<f64> = Vec::with_capacity(starts);
+	let mut best = None;
+	for _ in 0..starts.max(1) {
+		if stop_requested() && best.is_some() {
+			break;
+		}
+		grid.remove_all_towers();
+		greedy_cover_with(grid, percent_remaining, |ties| *ties.choose(rng).unwrap());
+		let penalty = grid.penalty();
+		if penalties.iter().all(|&p| penalty < p) {
+			best = Some(grid.checkpoint());
+		}
+		penalties.push(penalty);
+	}
+	grid.restore(&best.unwrap());
+
+	penalties.sort_by(f64::total_cmp);
+	info!(
+		"{} starts. Best: {}, median: {}, mean: {:.6}, worst: {}",
+		penalties.len(),
+		penalties[0],
+		penalties[penalties.len() / 2],
+		penalties.iter().sum::<f64>() / penalties.len() as f64,
+		penalties[penalties.len() - 1]
+	);
+	grid.write_solution(output_path, false)?;
+	Ok(())
+}
+
+/// Places towers on the grid as in greedy, without writing the solution.
+fn greedy_cover(grid: &mut Grid, percent_remaining: f32) {
+	// Ties are broken by the smallest point so the result is deterministic
+	greedy_cover_with(grid, percent_remaining, |ties| ties[0]);
+}
+
+/// Places towers on the grid as in greedy, using pick to choose among the
+/// (sorted) towers that add the lowest cost.
+fn greedy_cover_with(grid: &mut Grid, percent_remaining: f32, mut pick: impl FnMut(&[Point]) -> Point) {
+	let mut cities = grid.cities().collect::<Vec<Point>>();
 
 	// Continue until cities are covered
 	while cities.len() != 0 {
-		let mut d: HashMap<Point, u32> = HashMap::new();
-
-		for city in &cities {
-			for possible_tower in Point::points_within_radius(*city, grid.service_radius(), grid.dimension()).unwrap() {
-				let counter = d.entry(*possible_tower).or_insert(0);
-				*counter += 1
-			}
+		// Every point covering some uncovered city
+		let mut candidates = PointSet::default();
+		for &city in &cities {
+			candidates.insert(city);
+			candidates.extend(
+				Point::points_within_radius(
+					city,
+					grid.service_radius(),
+					grid.dimension(),
+					grid.metric(),
+					grid.wrap(),
+				)
+				.unwrap(),
+			);
 		}
 
 		// Towers to be considered, mapped to added cost
 		let mut towers_to_be_considered: HashMap<Point, f64> = HashMap::new();
 
 		// Grab among (us) the towers that cover the most
-		let mut ordered_possibles: Vec<(Point, u32)> = d.into_iter().collect::<Vec<(Point, u32)>>();
-		ordered_possibles.sort_by_key(|a| a.1);
-		ordered_possibles.reverse();
+		let mut ordered_possibles: Vec<(Point, u32)> = candidates.into_iter().map(|t| (t, grid.coverage_gain(t))).collect();
+		// Ties are broken by the point so the result is deterministic
+		ordered_possibles.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
 
-		let max = ordered_possibles[0].1;
+		let max = match ordered_possibles.first() {
+			Some(&(_, max)) => max,
+			None => break,
+		};
 		let total = ordered_possibles.len();
 		let mut index = 0;
 
@@ -98,29 +818,33 @@ pub fn greedy(grid: &mut Grid, output_path: &str) {
 			}
 		}
 
-		// Next extract PERCENT_REMAINING of the rest
-		let end = std::cmp::min(((total - index) as f32 * PERCENT_REMAINING) as usize, total);
+		// Next extract percent_remaining of the rest
+		let end = std::cmp::min(((total - index) as f32 * percent_remaining) as usize, total);
 
 		for i in index..end {
 			towers_to_be_considered.insert(ordered_possibles[i].0, 0.0);
 		}
 
-		// Now test inserting each tower into grid, updating added cost value
-
+		// Now compute the penalty of inserting each tower into grid, updating added
+		// cost value
 		for (tower, cost) in towers_to_be_considered.iter_mut() {
-			grid.add_tower(tower.get_x(), tower.get_y());
-			*cost += grid.penalty();
-			grid.remove_tower(tower.get_x(), tower.get_y())
+			*cost += grid.penalty_if_added(*tower);
 		}
 
-		// Pick the tower that adds the lowest cost
-		let tower_to_add = towers_to_be_considered
+		// Pick among the towers that add the lowest cost. Floats are only
+		// partially ordered, so compare by the total order
+		let min_cost = towers_to_be_considered
+			.values()
+			.copied()
+			.min_by(f64::total_cmp)
+			.unwrap();
+		let mut ties: Vec<Point> = towers_to_be_considered
 			.iter()
-			// Rust doesn't allow ordering by float, so just compare the integers for now
-			// TODO https://docs.rs/float-cmp/0.5.2/float_cmp/ ?
-			.max_by(|a, b| (*a.1 as u32).cmp(&(*b.1 as u32)))
-			.unwrap()
-			.0;
+			.filter(|(_, cost)| cost.total_cmp(&min_cost).is_eq())
+			.map(|(&t, _)| t)
+			.collect();
+		ties.sort();
+		let tower_to_add = pick(&ties);
 
 		grid.add_tower(tower_to_add.get_x(), tower_to_add.get_y());
 
@@ -135,170 +859,309 @@ pub fn greedy(grid: &mut Grid, output_path: &str) {
 		}
 		cities = new_cities;
 	}
+}
+
+
+// -- Set Cover --
+/// Classic greedy set-cover approximation, ignoring penalty.
+/// Repeatedly places the tower covering the most still-uncovered cities.
+pub fn set_cover(grid: &mut Grid, output_path: &str, _config: &SolverConfig) -> Result<(), SolveError> {
+	// Candidate towers, mapped to the cities they would cover
+	let mut candidates: HashMap<Point, Vec<Point>> = HashMap::new();
+	for city in grid.cities() {
+		let coverage = Point::points_within_radius(
+			city,
+			grid.service_radius(),
+			grid.dimension(),
+			grid.metric(),
+			grid.wrap(),
+		)
+		.unwrap();
+		for &tower in coverage.iter().chain(std::iter::once(&city)) {
+			candidates.entry(tower).or_default().push(city);
+		}
+	}
+
+	let mut uncovered: HashSet<Point> = grid.cities().collect();
+	while !uncovered.is_empty() {
+		// Ties are broken by the smallest point so the result is deterministic
+		let (&tower, _) = candidates
+			.iter()
+			.map(|(t, cs)| (t, cs.iter().filter(|c| uncovered.contains(c)).count()))
+			.max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+			.unwrap();
+
+		for city in candidates.remove(&tower).unwrap() {
+			uncovered.remove(&city);
+		}
+		grid.add_tower(tower.x, tower.y);
+	}
 
-	grid.write_solution(output_path);
+	grid.write_solution(output_path, false)?;
+	Ok(())
 }
 
 
 // -- Linear Programming --
 // TODO: move out of grid class
-pub fn linear_programming(grid: &mut Grid) {
-	grid.lp_solve(LP_CUTOFF_TIME);
+pub fn linear_programming(grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<(), SolveError> {
+	let max_time = config.time_limit.map_or(LP_CUTOFF_TIME, |t| t as u32);
+	let (status, gap) = grid.lp_solve(
+		max_time,
+		config.max_towers,
+		config.verbose,
+		config.model_path(output_path).as_deref(),
+	)?;
+	match gap {
+		Some(gap) => info!("LP {:?}, gap {:.2}%", status, gap * 100.),
+		None => info!("LP {:?}", status),
+	}
+	grid.write_solution(output_path, false)?;
+	Ok(())
 }
 
 
 // -- Randomize Valid Solution threaded
-pub fn randomize_valid_solution_with_lp_threaded(grid: &mut Grid, output_path: &str) {
+/// Runs randomize_valid_solution_with_lp on each thread. If a seed is given,
+/// thread i seeds its random number generator with seed + i. Only the first
+/// thread dumps the LP model, since they all solve the same one.
+pub fn randomize_valid_solution_with_lp_threaded(
+	grid: &mut Grid,
+	output_path: &str,
+	secs_per_input: u64,
+	cutoff_time: u32,
+	config: &SolverConfig,
+) -> Result<(), SolveError> {
+	let pool = config.thread_pool();
 	let mut grids: Vec<_> = vec![];
-	for _ in 0..(num_cpus::get()) {
+	for _ in 0..pool.current_num_threads() {
 		grids.push(grid.clone());
 	}
-	grids
-		.par_iter_mut()
-		.for_each(|g: &mut Grid| randomize_valid_solution_with_lp(g, output_path));
+	pool.install(|| {
+		grids.par_iter_mut().enumerate().try_for_each(|(i, g)| {
+			let mut rng = config.rng(i as u64);
+			let dump_model = config.model_path(output_path).filter(|_| i == 0);
+			randomize_valid_solution_with_lp(
+				g,
+				output_path,
+				secs_per_input,
+				cutoff_time,
+				dump_model.as_deref(),
+				&mut rng,
+			)
+		})
+	})
 }
 
 
 // -- Randomize Valid Solution with LP --
-pub fn randomize_valid_solution_with_lp(grid: &mut Grid, output_path: &str) {
-	let mut rng = thread_rng();
+pub fn randomize_valid_solution_with_lp(
+	grid: &mut Grid,
+	output_path: &str,
+	secs_per_input: u64,
+	cutoff_time: u32,
+	mut dump_model: Option<&str>,
+	rng: &mut impl Rng,
+) -> Result<(), SolveError> {
 	let mut best_penalty_so_far = f64::INFINITY;
+	let (mut num_solves, mut num_timed_out) = (0, 0);
+	// Sorted towers of every solution found so far. Different seeds often give
+	// the same optimum, so each repeat is excluded from later solves.
+	let mut seen: HashSet<Vec<Point>> = HashSet::new();
+	let mut excluded: Vec<Vec<Point>> = Vec::new();
 	let sw = Stopwatch::start_new();
 
 	// Grab a valid solution and see if it is better
-	// TODO: prevent getting same one over and over
-	while sw.elapsed().as_secs() < SECS_PER_INPUT {
-		let p = grid.random_lp_solve(CUTOFF_TIME, rng.gen_range(1..=u32::MAX));
+	while sw.elapsed().as_secs() < secs_per_input && !stop_requested() {
+		// Only the seed and exclusions change between solves, so the model is
+		// dumped once
+		let (p, status) = grid.random_lp_solve(cutoff_time, rng.gen_range(1..=u32::MAX), &excluded, dump_model.take())?;
+		num_solves += 1;
+		if status == LpStatus::TimedOut {
+			num_timed_out += 1;
+			debug!("LP timed out after {} secs, using the best found: {}", cutoff_time, p);
+		}
+		let mut towers: Vec<Point> = grid.towers().collect();
+		towers.sort();
+		if !seen.insert(towers.clone()) {
+			excluded.push(towers);
+		}
 		// println!("{} penalty: {}", i, p);
 		if p < best_penalty_so_far {
 			best_penalty_so_far = p;
-			grid.write_solution(output_path);
+			grid.write_solution(output_path, false)?;
+		}
+		// No towers is only a solution without cities, and every later solve
+		// would find it again
+		if grid.tower_count() == 0 {
+			break;
 		}
 
 		let time = sw.elapsed().as_secs();
 		if sw.elapsed().as_secs() % 10 == 0 {
-			println!("{} secs passed. Best so far: {}", time, best_penalty_so_far);
+			trace!("{} secs passed. Best so far: {}", time, best_penalty_so_far);
 		}
 		// Reset grid
 		grid.remove_all_towers();
 	}
-	println!("Best: {}", best_penalty_so_far);
+	info!(
+		"Best: {} ({} LP solves, {} distinct, {} timed out)",
+		best_penalty_so_far,
+		num_solves,
+		seen.len(),
+		num_timed_out
+	);
+	Ok(())
 }
 
 /// First grabs the current solution we have.
 /// Then, sees if any improvements can be made by moving a tower slightly, and
 /// makes them.
-pub fn hillclimb(grid: &mut Grid, output_path: &str) {
+pub fn hillclimb(grid: &mut Grid, output_path: &str, radius: u8, rng: &mut impl Rng) -> Result<(), SolveError> {
 	// println!("Hillclimbing for {}", output_path);
-	let initial_towers = Grid::towers_from_file(output_path);
-	for tower in initial_towers {
-		grid.add_tower(tower.x, tower.y);
-	}
+	load_or_bootstrap(grid, output_path, rng)?;
 	let old_penalty = grid.penalty();
 
-	if hillclimb_helper(grid, output_path, old_penalty) {
+	if hillclimb_helper(grid, output_path, old_penalty, radius, rng)? && !stop_requested() {
 		grid.remove_all_towers();
-		hillclimb(grid, output_path);
+		hillclimb(grid, output_path, radius, rng)?;
 	}
 	let new_penalty = grid.penalty();
 	if new_penalty < old_penalty {
-		println!("Improved! {} -> {}", old_penalty, new_penalty);
+		info!("Improved! {} -> {}", old_penalty, new_penalty);
 	} else {
-		println!(
-			"Hillclimb could not improve with radius {}. {}",
-			HILLCLIMB_RADIUS, new_penalty
-		);
+		info!("Hillclimb could not improve with radius {}. {}", radius, new_penalty);
 	}
+	Ok(())
 }
 
 /// Multithreaded randomized hillclimb. Looks at locally optimal choices, and if
 /// there are none, shuffles and reruns hillclimb. Repeats for a certain number
-/// of iterations per thread.
-pub fn rand_hillclimb_threaded(grid: &mut Grid, output_path: &str) {
-	let initial_towers = Grid::towers_from_file(output_path);
-	for tower in initial_towers {
-		grid.add_tower(tower.x, tower.y);
-	}
+/// of iterations per thread. If a seed is given, thread i seeds its random
+/// number generator with seed + i (and the bootstrap LP, if there is no output
+/// yet, uses the seed itself).
+pub fn rand_hillclimb_threaded(grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<(), SolveError> {
+	let iterations = config.iterations.unwrap_or(HILLCLIMB_ITERATIONS_PER_THREAD);
+	let radius = config.radius.unwrap_or(HILLCLIMB_RADIUS);
+	load_or_bootstrap(grid, output_path, &mut config.rng(0))?;
 	let old_penalty = grid.penalty();
+	let pool = config.thread_pool();
 	let mut grids: Vec<_> = vec![];
-	for _ in 0..(num_cpus::get()) {
+	for _ in 0..pool.current_num_threads() {
 		grids.push(grid.clone());
 	}
-	grids
-		.par_iter_mut()
-		.for_each(|g: &mut Grid| rand_hillclimb(g, output_path, HILLCLIMB_ITERATIONS_PER_THREAD, old_penalty));
+	pool.install(|| {
+		grids.par_iter_mut().enumerate().try_for_each(|(i, g)| {
+			let mut rng = config.rng(i as u64);
+			rand_hillclimb(g, output_path, iterations, radius, old_penalty, &mut rng)
+		})
+	})?;
 
-	let new_towers = Grid::towers_from_file(output_path);
 	grid.remove_all_towers();
-	for tower in new_towers {
-		grid.add_tower(tower.x, tower.y);
-	}
+	grid.apply_solution(output_path)?;
 	let new_penalty = grid.penalty();
 	if new_penalty < old_penalty {
-		println!("{}  {} -> {}", "Improved!".green(), old_penalty, new_penalty);
+		info!("{}  {} -> {}", "Improved!".green(), old_penalty, new_penalty);
 	} else {
-		println!(
+		info!(
 			"Randomized hillclimb could not improve in {} iterations with radius {}. {}",
-			HILLCLIMB_ITERATIONS_PER_THREAD, HILLCLIMB_RADIUS, new_penalty
+			iterations, radius, new_penalty
 		);
 	}
+	Ok(())
 }
 
 /// Same as normal hillclimb, except randomizes the grid when reaching a peak,
 /// and redoes hillclimb.
-fn rand_hillclimb(grid: &mut Grid, output_path: &str, iterations: usize, global_penalty: f64) {
-	let mut rng = thread_rng();
-
+fn rand_hillclimb(
+	grid: &mut Grid,
+	output_path: &str,
+	iterations: usize,
+	radius: u8,
+	global_penalty: f64,
+	rng: &mut impl Rng,
+) -> Result<(), SolveError> {
 	for i in 0..(iterations + 1) {
+		if stop_requested() {
+			break;
+		}
 		loop {
-			if !hillclimb_helper(grid, output_path, global_penalty) {
+			if !hillclimb_helper(grid, output_path, global_penalty, radius, rng)? {
 				let pen = grid.penalty();
 				if pen < global_penalty {
-					println!("Improvement on iteration {}: {} -> {}", i, global_penalty, pen);
-					grid.write_solution(output_path);
+					debug!("Improvement on iteration {}: {} -> {}", i, global_penalty, pen);
+					grid.write_solution(output_path, false)?;
 				} else if i % 10 == 0 {
 					// println!("No improvement by iteration {}.", i);
 				}
-				grid.random_lp_solve(1, rng.gen_range(1..=u32::MAX)); // reinitialize LP-pseudorandom towers
+				grid.random_lp_solve(1, rng.gen_range(1..=u32::MAX), &[], None)?; // reinitialize LP-pseudorandom towers
 				break;
 			}
 		}
 	}
+	Ok(())
 }
 
-/// Runs hillclimb on this grid and returns whether any improvements were made.
-fn hillclimb_helper(grid: &mut Grid, output_path: &str, global_penalty: f64) -> bool {
-	fn adjacent_towers(g: &Grid, t: Point, r: u8) -> Vec<Point> {
-		// need to change to points_within_naive if want to use different r values.
+/// Places the towers of the existing output on the grid. If there is no output
+/// yet, starts from a quick random LP solution instead and writes it out, so
+/// there is always a solution to improve on.
+fn load_or_bootstrap(grid: &mut Grid, output_path: &str, rng: &mut impl Rng) -> Result<(), SolveError> {
+	if Path::new(output_path).is_file() {
+		grid.apply_solution(output_path)?;
+		return Ok(());
+	}
+	let (penalty, _) = grid.random_lp_solve(1, rng.gen_range(1..=u32::MAX), &[], None)?;
+	info!("No existing output, starting from a random LP solution: {}", penalty);
+	grid.write_solution(output_path, false)?;
+	Ok(())
+}
 
-		let mut adjacent_towers: HashSet<Point> = match r {
-			3 | 8 | 10 | 14 => Point::points_within_radius(t, r, g.dimension()).unwrap().clone(),
-			_ => Point::points_within_naive(t, r, g.dimension()),
-		};
-		for (tower, _) in g.get_towers_ref() {
-			adjacent_towers.remove(tower);
-		}
-		adjacent_towers.into_iter().collect()
+/// Returns the points within r of t that don't have a tower.
+fn adjacent_towers(g: &Grid, t: Point, r: u8) -> Vec<Point> {
+	let mut adjacent_towers: PointSet = Point::points_within_radius(t, r, g.dimension(), g.metric(), g.wrap())
+		.unwrap()
+		.clone();
+	for tower in g.towers() {
+		adjacent_towers.remove(&tower);
+	}
+	adjacent_towers.into_iter().collect()
+}
+
+/// Runs hillclimb on this grid and returns whether any improvements were made,
+/// writing the improved grid if it beats the global penalty.
+fn hillclimb_helper(
+	grid: &mut Grid,
+	output_path: &str,
+	global_penalty: f64,
+	radius: u8,
+	rng: &mut impl Rng,
+) -> io::Result<bool> {
+	let changed = hillclimb_step(grid, radius, rng);
+	if changed && grid.penalty() < global_penalty {
+		grid.write_solution(output_path, false)?;
 	}
+	Ok(changed)
+}
 
+/// Makes the first improvement found by removing a redundant tower or moving a
+/// tower within the radius, returning whether there was one.
+fn hillclimb_step(grid: &mut Grid, radius: u8, rng: &mut impl Rng) -> bool {
 	let old_penalty = grid.penalty();
 	let mut changed = false;
-	let old_towers = (*grid.get_towers_ref()).clone();
-	let mut rng = thread_rng();
-	'outer: for &tower in old_towers.keys() {
+	// Towers to try, sorted so the result only depends on the rng
+	let mut towers: Vec<Point> = grid.towers().collect();
+	towers.sort();
+	'outer: for tower in towers {
 		// first sees if valid even without this tower, and if so
-		// removes it.
-		grid.remove_tower(tower.x, tower.y);
-		if grid.is_valid() {
+		// removes it
+		if grid.is_removable(tower) {
+			grid.remove_tower(tower.x, tower.y);
 			changed = true;
-			grid.write_solution(output_path);
 			break 'outer;
-		} else {
-			grid.add_tower(tower.x, tower.y);
 		}
 
-		let mut adj_towers: Vec<Point> = adjacent_towers(grid, tower, HILLCLIMB_RADIUS).into_iter().collect();
-		adj_towers.shuffle(&mut rng);
+		let mut adj_towers: Vec<Point> = adjacent_towers(grid, tower, radius).into_iter().collect();
+		adj_towers.shuffle(rng);
 		// now tries to move the tower to a better location
 		for adj_tower in adj_towers {
 			// change r (third value) if desired
@@ -310,9 +1173,6 @@ fn hillclimb_helper(grid: &mut Grid, output_path: &str, global_penalty: f64) ->
 					changed = true;
 					// println!("{} -> {}, Old: {}, New: {}", tower, adj_tower, old_penalty,
 					// new_penalty);
-					if new_penalty < global_penalty {
-						grid.write_solution(output_path);
-					}
 					break 'outer;
 				}
 			}
@@ -322,19 +1182,413 @@ fn hillclimb_helper(grid: &mut Grid, output_path: &str, global_penalty: f64) ->
 	changed
 }
 
-pub fn sort_and_read_penalty(grid: &mut Grid, output_path: &str) {
-	let towers = Grid::towers_from_file(output_path);
-	for tower in towers {
+/// Tabu search starting from the current solution. Each iteration makes the
+/// best valid move (removing a tower, or moving one within radius), even if it
+/// is worse, except that towers may not move onto a position vacated in the
+/// last tenure iterations unless that beats the best solution so far. Writes
+/// the best solution found.
+pub fn tabu(
+	grid: &mut Grid,
+	output_path: &str,
+	iterations: usize,
+	tenure: usize,
+	radius: u8,
+	rng: &mut impl Rng,
+) -> io::Result<()> {
+	grid.apply_solution(output_path)?;
+	// Moves are only taken while every city stays covered, so an output that
+	// doesn't cover every city is repaired first, and written even if the
+	// search doesn't improve on it
+	let repaired = !grid.is_valid();
+	if repaired {
+		let added = grid.cover_remaining_greedily(rng);
+		warn!("{} doesn't cover every city, added {} towers", output_path, added.len());
+	}
+	let old_penalty = grid.penalty();
+	let mut best_penalty = old_penalty;
+	let mut best_towers: Vec<Point> = grid.towers().collect();
+	let mut tabu_positions: VecDeque<Point> = VecDeque::with_capacity(tenure + 1);
+
+	for i in 0..iterations {
+		if stop_requested() {
+			break;
+		}
+		// (penalty after the move, tower moved, where it moved to or None if removed)
+		let mut best_move: Option<(f64, Point, Option<Point>)> = None;
+		let mut consider = |candidate: (f64, Point, Option<Point>)| {
+			// Ties are broken by position so the search is deterministic
+			let is_better = match best_move {
+				None => true,
+				Some(best) => candidate
+					.0
+					.total_cmp(&best.0)
+					.then((candidate.1, candidate.2).cmp(&(best.1, best.2)))
+					.is_lt(),
+			};
+			if is_better {
+				best_move = Some(candidate);
+			}
+		};
+
+		let critical_towers = grid.critical_towers();
+		let towers: Vec<Point> = grid.towers().collect();
+		for tower in towers {
+			// Removing a tower that isn't the sole cover of a city keeps it valid
+			if !critical_towers.contains(&tower) {
+				consider((grid.penalty_if_removed(tower), tower, None));
+			}
+			for adj_tower in adjacent_towers(grid, tower, radius) {
+				grid.move_tower(tower, adj_tower);
+				if grid.is_valid() {
+					let new_penalty = grid.penalty();
+					if !tabu_positions.contains(&adj_tower) || new_penalty < best_penalty {
+						consider((new_penalty, tower, Some(adj_tower)));
+					}
+				}
+				grid.move_tower(adj_tower, tower); // undo move
+			}
+		}
+
+		let (new_penalty, tower, adj_tower) = match best_move {
+			Some(m) => m,
+			None => break, // every move is tabu
+		};
+		match adj_tower {
+			Some(adj_tower) => grid.move_tower(tower, adj_tower),
+			None => grid.remove_tower(tower.x, tower.y),
+		}
+		tabu_positions.push_back(tower);
+		if tabu_positions.len() > tenure {
+			tabu_positions.pop_front();
+		}
+
+		if new_penalty < best_penalty {
+			debug!("Improvement on iteration {}: {} -> {}", i, best_penalty, new_penalty);
+			best_penalty = new_penalty;
+			best_towers = grid.towers().collect();
+		}
+	}
+
+	grid.remove_all_towers();
+	for tower in best_towers {
 		grid.add_tower(tower.x, tower.y);
 	}
-	println!("Penalty: {}", grid.penalty());
-	grid.overwrite_with_sorted_solution(output_path);
+	if best_penalty < old_penalty {
+		info!("{}  {} -> {}", "Improved!".green(), old_penalty, best_penalty);
+		grid.write_solution(output_path, repaired)?;
+	} else if repaired {
+		info!("Repaired {}. {}", output_path, best_penalty);
+		grid.write_solution(output_path, true)?;
+	} else {
+		info!(
+			"Tabu search could not improve in {} iterations with radius {}. {}",
+			iterations, radius, best_penalty
+		);
+	}
+	Ok(())
+}
+
+pub fn sort_and_read_penalty(grid: &mut Grid, output_path: &str, _config: &SolverConfig) -> Result<(), SolveError> {
+	grid.apply_solution(output_path)?;
+	info!("Penalty: {}", grid.penalty());
+	grid.overwrite_with_sorted_solution(output_path)?;
+	Ok(())
+}
+
+/// Builds a solution from scratch in stages: an initial cover from the LP (or
+/// greedy, if the LP fails), hillclimbed to a local optimum, then pruned of
+/// redundant towers. Prints the penalty after each stage and only writes the
+/// final solution.
+#[allow(clippy::too_many_arguments)]
+pub fn pipeline(
+	grid: &mut Grid,
+	output_path: &str,
+	lp_time: u32,
+	radius: u8,
+	max_towers: Option<usize>,
+	verbose: bool,
+	dump_model: Option<&str>,
+	rng: &mut impl Rng,
+) -> io::Result<()> {
+	grid.remove_all_towers();
+	match grid.lp_solve(lp_time, max_towers, verbose, dump_model) {
+		Ok((status, _)) => info!("LP ({:?}): {}", status, grid.penalty()),
+		Err(e) => {
+			warn!("LP failed, {}, falling back on greedy", e);
+			grid.remove_all_towers();
+			greedy_cover(grid, PERCENT_REMAINING);
+			info!("Greedy: {}", grid.penalty());
+		}
+	}
+
+	while !stop_requested() && hillclimb_step(grid, radius, rng) {}
+	info!("Hillclimb: {}", grid.penalty());
+
+	let removed = grid.prune_redundant_towers();
+	info!("Prune ({} removed): {}", removed, grid.penalty());
+
+	grid.write_solution(output_path, false)?;
+	Ok(())
+}
+
+/// Removes the redundant towers of the existing output, rewriting it if that
+/// improved it.
+pub fn prune(grid: &mut Grid, output_path: &str, _config: &SolverConfig) -> Result<(), SolveError> {
+	if !Path::new(output_path).is_file() {
+		return Err(io::Error::new(io::ErrorKind::NotFound, "no existing output to prune").into());
+	}
+	grid.apply_solution(output_path)?;
+	if !grid.is_valid() {
+		warn!("skipping, the existing output is not a valid solution");
+		return Ok(());
+	}
+
+	let old_penalty = grid.penalty();
+	let removed = grid.prune_redundant_towers();
+	if removed > 0 {
+		info!(
+			"{}  Removed {} redundant tower(s): {} -> {}",
+			"Improved!".green(),
+			removed,
+			old_penalty,
+			grid.penalty()
+		);
+		grid.write_solution(output_path, false)?;
+	} else {
+		info!("No redundant towers. {}", old_penalty);
+	}
+	Ok(())
 }
 
 /// Anneal
-pub fn simulated_annealing(grid: &mut Grid, output_path: &str) {
-	if let Err(ref e) = annealing::run(grid, output_path) {
-		println!("{}", e);
-		std::process::exit(1);
+pub fn simulated_annealing(grid: &mut Grid, output_path: &str, config: &SolverConfig) -> Result<(), SolveError> {
+	let max_iters = config.iterations.map_or(annealing::MAX_ITERS, |i| i as u64);
+	annealing::run(grid, output_path, config.restarts, max_iters, &mut config.rng(0))
+		.map_err(|e| SolveError::Other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+	use crate::grid::tests::{grid_with_cities, random_grid, temp_path};
+
+	#[test]
+	fn tabu_repairs_invalid_output() {
+		let mut grid = grid_with_cities(8, 1, 2, &[(1, 1), (6, 6)]);
+		let path = temp_path("tabu-invalid.out");
+		let mut partial = grid.clone();
+		partial.add_tower(1, 1);
+		partial.write_output(&path).unwrap();
+
+		tabu(&mut grid, &path, 10, 3, 2, &mut StdRng::seed_from_u64(1270)).unwrap();
+		let mut written = grid_with_cities(8, 1, 2, &[(1, 1), (6, 6)]);
+		written.apply_solution(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		assert!(written.is_valid());
+		assert_eq!(written, grid);
+	}
+
+	#[test]
+	fn set_cover_covers_every_city() {
+		let path = temp_path("set-cover-plus.out");
+		let mut grid = grid_with_cities(3, 1, 1, &[(1, 1), (0, 1), (1, 0), (2, 1), (1, 2)]);
+		set_cover(&mut grid, &path, &SolverConfig::default()).unwrap();
+		assert_eq!(grid.towers().collect::<Vec<Point>>(), vec![Point::new(1, 1)]);
+
+		let mut rng = StdRng::seed_from_u64(1192);
+		for _ in 0..5 {
+			let mut grid = random_grid(&mut rng, 10, 2, 3, 20);
+			set_cover(&mut grid, &path, &SolverConfig::default()).unwrap();
+			assert!(grid.is_valid());
+			assert!(grid.tower_count() <= grid.city_count());
+			fs::remove_file(&path).unwrap();
+		}
+	}
+
+	#[test]
+	fn greedy_picks_the_cheapest_tower() {
+		let path = temp_path("greedy.out");
+		// The first tower goes to (1, 2), the smallest of the equally good
+		// points, and the second then stays out of its penalty radius
+		let mut grid = grid_with_cities(8, 1, 3, &[(2, 2), (2, 5)]);
+		greedy(&mut grid, &path, 1.).unwrap();
+		assert_eq!(
+			grid.towers().collect::<HashSet<Point>>(),
+			[Point::new(1, 2), Point::new(2, 5)].into_iter().collect()
+		);
+		assert_eq!(grid.penalty(), 340.);
+
+		let mut rng = StdRng::seed_from_u64(1253);
+		for _ in 0..5 {
+			let mut grid = random_grid(&mut rng, 10, 2, 3, 20);
+			greedy(&mut grid, &path, 0.5).unwrap();
+			assert!(grid.is_valid());
+			fs::remove_file(&path).unwrap();
+		}
+	}
+
+	#[test]
+	fn hillclimb_bootstraps_missing_output() {
+		let path = temp_path("hillclimb.out");
+		let mut rng = StdRng::seed_from_u64(1274);
+		let mut grid = random_grid(&mut rng, 10, 2, 3, 20);
+		let mut solved = grid.clone();
+		hillclimb(&mut solved, &path, 2, &mut rng).unwrap();
+		assert!(solved.is_valid());
+
+		grid.apply_solution(&path).unwrap();
+		assert!(grid.is_valid());
+		assert!(grid.penalty() <= solved.penalty());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn seeded_runs_are_reproducible() {
+		let config = SolverConfig {
+			seed: Some(1286),
+			..SolverConfig::default()
+		};
+		assert_eq!(config.rng(3).gen::<u64>(), config.rng(3).gen::<u64>());
+		assert_ne!(config.rng(3).gen::<u64>(), config.rng(4).gen::<u64>());
+
+		let grid = random_grid(&mut StdRng::seed_from_u64(1286), 12, 2, 4, 25);
+		let solve = |name: &str| {
+			let path = temp_path(name);
+			let mut g = grid.clone();
+			multi_greedy(&mut g, &path, 5, 0.5, &mut config.rng(0)).unwrap();
+			fs::remove_file(&path).unwrap();
+			g
+		};
+		assert_eq!(solve("seeded-a.out"), solve("seeded-b.out"));
+	}
+
+	#[test]
+	fn solve_errors_convert_and_propagate() {
+		let e: SolveError = io::Error::new(io::ErrorKind::NotFound, "gone").into();
+		assert!(matches!(e, SolveError::Io(_)));
+		assert_eq!(e.to_string(), "gone");
+		let e: SolveError = Grid::from_reader("1\n".as_bytes()).unwrap_err().into();
+		assert!(matches!(e, SolveError::Parse(GridParseError::MissingHeader)));
+		assert!(e.to_string().starts_with("invalid input, "));
+		let e: SolveError = ResolutionError::Infeasible.into();
+		assert!(e.to_string().starts_with("LP failed, "));
+		assert_eq!(SolveError::Panicked("oops".to_string()).to_string(), "panicked, oops");
+
+		// A write failure reaches the caller instead of panicking
+		let mut grid = grid_with_cities(8, 1, 3, &[(1, 1)]);
+		let path = temp_path("no-such-dir/001.out");
+		let e = find_solver("setcover")
+			.unwrap()
+			.solve(&mut grid, &path, &SolverConfig::default())
+			.unwrap_err();
+		assert!(matches!(e, SolveError::Io(_)));
+	}
+
+	#[test]
+	fn solvers_handle_grids_without_cities() {
+		let grid = Grid::from_reader("0\n8\n1\n3\n".as_bytes()).unwrap();
+		assert!(grid.is_valid());
+		assert_eq!(grid.penalty_lower_bound(), 0.);
+		let config = SolverConfig {
+			iterations: Some(3),
+			time_limit: Some(1),
+			seed: Some(1330),
+			..SolverConfig::default()
+		};
+		for name in [
+			"benchmark",
+			"greedy",
+			"multi_greedy",
+			"setcover",
+			"lp",
+			"exact",
+			"tabu",
+			"hillclimb",
+			"prune",
+		] {
+			let path = temp_path(&format!("empty-{}.out", name));
+			let solver = find_solver(name).unwrap();
+			if solver.needs_existing_output() {
+				fs::write(&path, "0\n").unwrap();
+			}
+			let mut g = grid.clone();
+			let report = solver.solve(&mut g, &path, &config).unwrap();
+			assert_eq!((report.penalty, g.tower_count()), (0., 0), "{}", name);
+			assert_eq!(Grid::towers_from_file(&path).unwrap(), HashSet::new(), "{}", name);
+			fs::remove_file(&path).unwrap();
+		}
+	}
+
+	#[test]
+	fn solvers_describe_themselves() {
+		let improvers: Vec<&str> = SOLVERS
+			.iter()
+			.filter(|s| s.needs_existing_output())
+			.map(|s| s.name())
+			.collect();
+		assert!(improvers.contains(&"hillclimb") && improvers.contains(&"annealing"));
+		assert!(!improvers.contains(&"greedy") && !improvers.contains(&"lp"));
+		for solver in SOLVERS {
+			assert!(!solver.description().is_empty(), "{} has no description", solver.name());
+			assert!(!solver.description().ends_with('.'), "{}", solver.name());
+			assert!(
+				solver.options().iter().all(|o| o.starts_with("--")),
+				"{}",
+				solver.name()
+			);
+		}
+	}
+
+	#[test]
+	fn registered_solvers_are_found_by_unique_names() {
+		let names: HashSet<&str> = SOLVERS.iter().map(|s| s.name()).collect();
+		assert_eq!(names.len(), SOLVERS.len(), "solver names must be unique");
+		for solver in SOLVERS {
+			let found = find_solver(solver.name()).unwrap();
+			assert_eq!(found.description(), solver.description());
+		}
+		assert!(find_solver("no_such_solver").is_none());
+		assert!(find_solver("Greedy").is_none());
+	}
+
+	#[test]
+	fn improvers_fail_on_unreadable_output() {
+		let grid = grid_with_cities(8, 1, 2, &[(1, 1), (6, 6)]);
+		let config = SolverConfig {
+			iterations: Some(3),
+			threads: Some(1),
+			seed: Some(1320),
+			..SolverConfig::default()
+		};
+		for name in [
+			"hillclimb",
+			"rand_hillclimb",
+			"tabu",
+			"sort_and_read_penalty",
+			"prune",
+			"annealing",
+		] {
+			let path = temp_path(&format!("unreadable-{}.out", name));
+			// (9, 9) is off the 8x8 grid
+			fs::write(&path, "2\n1 1\n9 9\n").unwrap();
+			let mut g = grid.clone();
+			let e = find_solver(name).unwrap().solve(&mut g, &path, &config).unwrap_err();
+			assert!(
+				e.to_string().contains("tower (9, 9) is off the grid"),
+				"{}: {}",
+				name,
+				e
+			);
+			assert_eq!(fs::read_to_string(&path).unwrap(), "2\n1 1\n9 9\n", "{}", name);
+			fs::remove_file(&path).unwrap();
+		}
+		let path = temp_path("unreadable-missing.out");
+		let e = find_solver("prune")
+			.unwrap()
+			.solve(&mut grid.clone(), &path, &config)
+			.unwrap_err();
+		assert!(matches!(e, SolveError::Io(ref e) if e.kind() == io::ErrorKind::NotFound));
 	}
 }
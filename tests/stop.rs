@@ -0,0 +1,62 @@
+// Stopping sets a process wide flag that every later solve sees, so these tests
+// get a test binary of their own.
+
+use std::fs;
+
+use pengwin::grid::Grid;
+use pengwin::solvers::{find_solver, request_stop, stop_requested, SolverConfig};
+
+#[test]
+fn stopped_solver_still_writes_a_solution() {
+	request_stop();
+	assert!(stop_requested());
+
+	let dir = std::env::temp_dir().join(format!("pengwin-stop-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (input, output) = (dir.join("001.in"), dir.join("001.out"));
+	fs::write(&input, "2\n30\n3\n8\n1 1\n20 20\n").unwrap();
+	let mut grid = Grid::from_file(input.to_str().unwrap()).unwrap();
+	let config = SolverConfig {
+		iterations: Some(1_000_000),
+		seed: Some(1319),
+		..SolverConfig::default()
+	};
+	let report = find_solver("multi_greedy")
+		.unwrap()
+		.solve(&mut grid, output.to_str().unwrap(), &config)
+		.unwrap();
+	assert!(report.improved());
+
+	let mut solution = Grid::from_file(input.to_str().unwrap()).unwrap();
+	solution.apply_solution(output.to_str().unwrap()).unwrap();
+	assert!(solution.is_valid());
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stopped_exact_search_keeps_the_incumbent() {
+	request_stop();
+
+	// Far too many cities to search exhaustively
+	let dir = std::env::temp_dir().join(format!("pengwin-stop-exact-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let (input, output) = (dir.join("001.in"), dir.join("001.out"));
+	let cities: Vec<String> = (0..30).map(|i| format!("{} {}", i, (i * 7) % 30)).collect();
+	fs::write(&input, format!("30\n30\n3\n8\n{}\n", cities.join("\n"))).unwrap();
+	let mut incumbent = Grid::from_file(input.to_str().unwrap()).unwrap();
+	for i in 0..30 {
+		incumbent.add_tower(i, (i * 7) % 30);
+	}
+	incumbent.write_solution(output.to_str().unwrap(), true).unwrap();
+	let written = fs::read_to_string(&output).unwrap();
+
+	let mut grid = Grid::from_file(input.to_str().unwrap()).unwrap();
+	let report = find_solver("exact")
+		.unwrap()
+		.solve(&mut grid, output.to_str().unwrap(), &SolverConfig::default())
+		.unwrap();
+	assert_eq!(report.penalty, incumbent.penalty());
+	assert_eq!(grid, incumbent);
+	assert_eq!(fs::read_to_string(&output).unwrap(), written);
+	fs::remove_dir_all(&dir).unwrap();
+}